@@ -57,6 +57,7 @@ pub fn compare(search: &str, target: &str) -> Option<MatchResult> {
     for (search_index, search_char) in search.iter().enumerate() {
         for (target_index, target_char) in target.iter().enumerate() {
             let index = search_index * width + target_index;
+            #[allow(clippy::obfuscated_if_else)]
             let score = (search_char != target_char)
                 .then_some(0)
                 .unwrap_or_else(|| scores[index] + 1 + contiguous[index]);
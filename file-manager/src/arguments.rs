@@ -1,12 +1,170 @@
-use std::path::PathBuf;
+use std::{num::NonZeroUsize, path::PathBuf};
 
-use clap::Parser;
+use clap::{Parser, Subcommand, ValueEnum};
 
 #[derive(Parser)]
 #[command(name = "JPFileManager", author = "JP")]
 #[command(about = "App for organising files")]
 #[command(version = None, long_about = None)]
+#[command(args_conflicts_with_subcommands = true, arg_required_else_help = true)]
 pub(crate) struct Arguments {
-    /// Folder containing files that should be organised.
-    pub(crate) folder: PathBuf,
+    /// Folder containing files that should be organised. Same as the `gui` command.
+    pub(crate) folder: Option<PathBuf>,
+
+    /// Number of folders whose meta data is kept in memory. Less recently used folders are
+    /// written if they were changed and read again when needed.
+    #[arg(
+        long,
+        global = true,
+        value_name = "FOLDERS",
+        default_value_t = meta::Repository::DEFAULT_FOLDER_CACHE_SIZE
+    )]
+    pub(crate) folder_cache: NonZeroUsize,
+
+    #[command(subcommand)]
+    pub(crate) command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub(crate) enum Command {
+    /// Opens the GUI for a folder.
+    Gui {
+        /// Folder containing files that should be organised.
+        folder: PathBuf,
+
+        /// Serve a local JSON API for other tools on a loopback address like `127.0.0.1:7878`
+        /// or on a Unix socket like `unix:/path/to/socket`.
+        #[arg(long, value_name = "ADDRESS")]
+        serve: Option<String>,
+    },
+
+    /// Adds a tag to files.
+    Tag {
+        /// Tag as `key` or `key=value`.
+        tag: String,
+
+        #[command(flatten)]
+        files: FileArguments,
+    },
+
+    /// Removes a tag from files.
+    Untag {
+        /// Tag as `key` to remove all values or `key=value` to remove one value.
+        tag: String,
+
+        #[command(flatten)]
+        files: FileArguments,
+    },
+
+    /// Prints the paths of the files matching a query, best match first.
+    Query {
+        /// Query like `person:Anna and tag:location=Rome`.
+        query: String,
+
+        /// Only list files below these folders instead of all root folders.
+        #[arg(short, long)]
+        folder: Vec<PathBuf>,
+    },
+
+    /// Lists, adds or removes persons.
+    #[command(subcommand)]
+    Persons(PersonsCommand),
+
+    /// Writes a report of the meta data of the files matching a query.
+    Export(ExportArguments),
+
+    /// Looks for inconsistencies in the meta data, e.g. of files that do not exist anymore.
+    ///
+    /// Exits with a failure code if problems were found.
+    Check,
+}
+
+impl Arguments {
+    /// Command to run, opening the GUI if only a folder was given.
+    pub(crate) fn command(self) -> Command {
+        match (self.command, self.folder) {
+            (Some(command), _) => command,
+            (None, Some(folder)) => Command::Gui {
+                folder,
+                serve: None,
+            },
+            (None, None) => unreachable!("clap requires a folder or a command"),
+        }
+    }
+}
+
+#[derive(clap::Args)]
+pub(crate) struct FileArguments {
+    /// Files to change.
+    #[arg(required = true)]
+    pub(crate) files: Vec<PathBuf>,
+
+    /// Root folder of the files, gets added if it is not known yet.
+    ///
+    /// Defaults to the known root folder containing each file.
+    #[arg(short, long)]
+    pub(crate) root: Option<PathBuf>,
+}
+
+#[derive(clap::Args)]
+pub(crate) struct ExportArguments {
+    /// Query like `person:Anna and tag:location=Rome`, exports all files by default.
+    #[arg(default_value = "")]
+    pub(crate) query: String,
+
+    /// Only export files below these folders instead of all root folders.
+    #[arg(short, long)]
+    pub(crate) folder: Vec<PathBuf>,
+
+    #[arg(long, value_enum, default_value_t = ExportFormat::Csv)]
+    pub(crate) format: ExportFormat,
+
+    /// Write a table of persons and their files instead, ignores the format.
+    #[arg(long)]
+    pub(crate) person_report: bool,
+
+    /// File to write to instead of the standard output.
+    #[arg(short, long)]
+    pub(crate) output: Option<PathBuf>,
+}
+
+#[derive(Subcommand)]
+pub(crate) enum PersonsCommand {
+    /// Prints the id, name and pronouns of all persons.
+    List,
+
+    /// Adds a person and prints its id.
+    Add {
+        name: String,
+
+        #[arg(short, long)]
+        pronouns: Option<String>,
+    },
+
+    /// Removes a person by id or by name.
+    ///
+    /// Files keep referencing the removed person, `check` lists them.
+    Remove {
+        /// Id or name of the person, ignoring case.
+        person: String,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub(crate) enum ExportFormat {
+    /// One path per line.
+    List,
+    Csv,
+    /// One JSON object per line.
+    Jsonl,
+}
+
+impl From<ExportFormat> for meta::export::Format {
+    fn from(format: ExportFormat) -> Self {
+        match format {
+            ExportFormat::List => meta::export::Format::FileList,
+            ExportFormat::Csv => meta::export::Format::Csv,
+            ExportFormat::Jsonl => meta::export::Format::JsonLines,
+        }
+    }
 }
@@ -0,0 +1,376 @@
+use crate::{
+    arguments::{ExportArguments, FileArguments, PersonsCommand},
+    Error, Result,
+};
+
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::PathBuf,
+};
+
+use meta::{
+    export,
+    model::{Person, PersonId, RootFolderId},
+    query::Query,
+    text, Repository,
+};
+
+/// Adds the tag given as `key` or `key=value` to the files.
+pub(crate) fn tag(meta: &Repository, tag: &str, files: &FileArguments) -> Result<()> {
+    change_tag(meta, &with_root_folders(meta, files)?, tag, false)?;
+    Ok(meta.save()?)
+}
+
+/// Removes the tag given as `key` or `key=value` from the files, see [meta::model::MetaFile::remove_tag].
+pub(crate) fn untag(meta: &Repository, tag: &str, files: &FileArguments) -> Result<()> {
+    change_tag(meta, &with_root_folders(meta, files)?, tag, true)?;
+    Ok(meta.save()?)
+}
+
+/// Adds or removes the tag given as `key` or `key=value` to or from the files in the root folders.
+pub(crate) fn change_tag(
+    meta: &Repository,
+    files: &[(RootFolderId, PathBuf)],
+    tag: &str,
+    remove: bool,
+) -> Result<()> {
+    let (key, value) = split_tag(tag);
+    for (root_folder_id, path) in files {
+        if remove {
+            // Files without meta data have no tags to remove.
+            if meta.load_file(path)?.is_some() {
+                meta.edit_file(root_folder_id, path, |file| file.remove_tag(key, value))?;
+            }
+        } else {
+            meta.edit_file(root_folder_id, path, |file| file.add_tag(key, value))?;
+        }
+    }
+    if !remove {
+        meta.edit_root_folders(|root_folders| root_folders.file_tags.insert(key.to_string()));
+    }
+    Ok(())
+}
+
+/// Prints the paths of the files matching the query.
+pub(crate) fn query(meta: &Repository, query: &str, folders: &[PathBuf]) -> Result<()> {
+    let paths = find(meta, query, folders)?;
+    Ok(export::write_file_list(&paths, io::stdout().lock())?)
+}
+
+pub(crate) fn persons(meta: &Repository, command: &PersonsCommand) -> Result<()> {
+    match command {
+        PersonsCommand::List => {
+            let persons = meta.persons();
+            let mut persons: Vec<_> = persons.entries().iter().collect();
+            persons.sort_by_key(|(id, _)| id.0);
+            let mut stdout = io::stdout().lock();
+            for (id, person) in persons {
+                match &person.pronouns {
+                    Some(pronouns) => writeln!(stdout, "{}\t{}\t{pronouns}", id.0, person.name),
+                    None => writeln!(stdout, "{}\t{}", id.0, person.name),
+                }
+                .map_err(Error::WriteFile)?;
+            }
+        }
+        PersonsCommand::Add { name, pronouns } => {
+            let id = meta
+                .edit_persons(|persons| persons.add(Person::new(name.clone(), pronouns.clone())));
+            meta.save_persons()?;
+            println!("{}", id.0);
+        }
+        PersonsCommand::Remove { person } => {
+            let id = find_person(meta, person)?;
+            meta.edit_persons(|persons| persons.remove(&id));
+            meta.save_persons()?;
+        }
+    }
+    Ok(())
+}
+
+/// Writes the report of the files matching the query to the output file or the standard output.
+pub(crate) fn export(meta: &Repository, arguments: &ExportArguments) -> Result<()> {
+    let paths = find(meta, &arguments.query, &arguments.folder)?;
+    let rows = meta.report(&paths)?;
+    let writer: Box<dyn Write> = match &arguments.output {
+        Some(path) => Box::new(BufWriter::new(
+            File::create(path).map_err(Error::WriteFile)?,
+        )),
+        None => Box::new(io::stdout().lock()),
+    };
+    if arguments.person_report {
+        export::write_person_report(&rows, writer)?;
+    } else {
+        export::write_report(&rows, arguments.format.into(), writer)?;
+    }
+    Ok(())
+}
+
+/// Prints the problems found in the meta data. Returns `false` if there were any.
+pub(crate) fn check(meta: &Repository) -> Result<bool> {
+    let problems = meta.check()?;
+    let mut stdout = io::stdout().lock();
+    for problem in &problems {
+        writeln!(stdout, "{problem}").map_err(Error::WriteFile)?;
+    }
+    Ok(problems.is_empty())
+}
+
+/// Splits a tag given as `key` or `key=value`.
+pub(crate) fn split_tag(tag: &str) -> (&str, Option<&str>) {
+    match tag.split_once('=') {
+        Some((key, value)) => (key.trim(), Some(value.trim())),
+        None => (tag.trim(), None),
+    }
+}
+
+/// Canonicalizes the files and pairs them with their root folders, see [FileArguments::root].
+fn with_root_folders(
+    meta: &Repository,
+    arguments: &FileArguments,
+) -> Result<Vec<(RootFolderId, PathBuf)>> {
+    let root_folder_id = match &arguments.root {
+        Some(root) => {
+            let id = meta.edit_root_folders(|root_folders| root_folders.get_or_create(root))?;
+            meta.save_root_folders()?;
+            Some(id)
+        }
+        None => None,
+    };
+    root_folders_of(meta, &arguments.files, root_folder_id)
+}
+
+/// Canonicalizes the files and pairs them with the given root folder,
+/// or with the known root folder containing them.
+pub(crate) fn root_folders_of(
+    meta: &Repository,
+    files: &[PathBuf],
+    root_folder_id: Option<RootFolderId>,
+) -> Result<Vec<(RootFolderId, PathBuf)>> {
+    let root_folders = meta.root_folders();
+    files
+        .iter()
+        .map(|file| {
+            let path = file.canonicalize()?;
+            let root_folder_id = match root_folder_id {
+                Some(id) => id,
+                None => root_folders
+                    .find(&path)
+                    .ok_or_else(|| Error::NoRootFolder(path.clone()))?,
+            };
+            Ok((root_folder_id, path))
+        })
+        .collect()
+}
+
+/// Files matching the query below the folders, or in all existing root folders if no folders are given.
+pub(crate) fn find(meta: &Repository, query: &str, folders: &[PathBuf]) -> Result<Vec<PathBuf>> {
+    let query = Query::parse(query)?;
+    let folders = folders
+        .iter()
+        .map(|folder| folder.canonicalize())
+        .collect::<io::Result<Vec<_>>>()?;
+
+    let mut root_folder_ids = Vec::new();
+    if folders.is_empty() {
+        root_folder_ids.extend(
+            meta.root_folders()
+                .entries()
+                .iter()
+                .filter(|(_, root_folder)| root_folder.exists())
+                .map(|(id, _)| *id),
+        );
+    }
+    for folder in &folders {
+        let id = meta
+            .root_folders()
+            .find(folder)
+            .ok_or_else(|| Error::NoRootFolder(folder.clone()))?;
+        if !root_folder_ids.contains(&id) {
+            root_folder_ids.push(id);
+        }
+    }
+
+    let mut paths = meta.search(&query, &root_folder_ids)?;
+    if !folders.is_empty() {
+        paths.retain(|path| folders.iter().any(|folder| path.starts_with(folder)));
+    }
+    Ok(paths)
+}
+
+/// Finds a person by id or by name, ignoring case.
+fn find_person(meta: &Repository, person: &str) -> Result<PersonId> {
+    if let Ok(id) = person.parse().map(PersonId) {
+        if meta.persons().person(&id).is_some() {
+            return Ok(id);
+        }
+    }
+    let persons = meta.persons();
+    let mut ids = persons
+        .entries()
+        .iter()
+        .filter(|(_, entry)| text::equals_ignore_case(&entry.name, person))
+        .map(|(id, _)| *id);
+    match (ids.next(), ids.next()) {
+        (Some(id), None) => Ok(id),
+        (Some(_), Some(_)) => Err(Error::AmbiguousPerson(person.to_string())),
+        (None, _) => Err(Error::UnknownPerson(person.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::arguments::ExportFormat;
+
+    use std::fs;
+
+    use meta::test_support::TempFolder;
+
+    /// Repository in `data` of a new temporary folder with the files below `library`,
+    /// which is not a root folder yet.
+    fn repository(name: &str, files: &[&str]) -> (TempFolder, Repository) {
+        let files: Vec<_> = files.iter().map(|file| format!("library/{file}")).collect();
+        let folder =
+            TempFolder::new(name).with_files(&files.iter().map(String::as_str).collect::<Vec<_>>());
+        let meta = Repository::load_or_create(folder.join("data")).unwrap();
+        (folder, meta)
+    }
+
+    fn tags(meta: &Repository, path: PathBuf) -> Option<Vec<(String, Option<String>)>> {
+        let file = meta.load_file(path).unwrap()?;
+        let mut tags: Vec<_> = file
+            .tag_values()
+            .map(|(key, value)| (key.to_string(), value.map(str::to_string)))
+            .collect();
+        tags.sort();
+        Some(tags)
+    }
+
+    #[test]
+    fn split_tags() {
+        assert_eq!(("location", Some("Rome")), split_tag(" location = Rome "));
+        assert_eq!(("holiday", None), split_tag("holiday "));
+        assert_eq!(("note", Some("a=b")), split_tag("note=a=b"));
+    }
+
+    #[test]
+    fn tag_and_untag_files() {
+        let (folder, meta) = repository("tag", &["a.jpg", "b.jpg"]);
+        let library = folder.join("library");
+        let files = |names: &[&str], root: Option<PathBuf>| FileArguments {
+            files: names.iter().map(|name| library.join(name)).collect(),
+            root,
+        };
+
+        assert!(matches!(
+            tag(&meta, "holiday", &files(&["a.jpg"], None)),
+            Err(Error::NoRootFolder(_))
+        ));
+        tag(
+            &meta,
+            "location=Rome",
+            &files(&["a.jpg"], Some(library.clone())),
+        )
+        .unwrap();
+        tag(&meta, "location=Paris", &files(&["a.jpg"], None)).unwrap();
+        assert!(meta.root_folders().file_tags.contains("location"));
+        untag(&meta, "location=Rome", &files(&["a.jpg", "b.jpg"], None)).unwrap();
+
+        assert_eq!(
+            Some(vec![("location".into(), Some("Paris".into()))]),
+            tags(&meta, library.join("a.jpg"))
+        );
+        // Untagging does not create meta data.
+        assert_eq!(None, tags(&meta, library.join("b.jpg")));
+    }
+
+    #[test]
+    fn find_files_below_folders() {
+        let (folder, meta) = repository("find", &["a.jpg", "trip/b.jpg", "trip/c.jpg"]);
+        let library = folder.join("library");
+        let root_folder_id = meta
+            .edit_root_folders(|root_folders| root_folders.get_or_create(&library))
+            .unwrap();
+        let files = root_folders_of(
+            &meta,
+            &[library.join("a.jpg"), library.join("trip/b.jpg")],
+            None,
+        )
+        .unwrap();
+        assert!(files.iter().all(|(id, _)| *id == root_folder_id));
+        change_tag(&meta, &files, "holiday", false).unwrap();
+
+        assert_eq!(
+            vec![library.join("a.jpg"), library.join("trip/b.jpg")],
+            find(&meta, "tag:holiday", &[]).unwrap()
+        );
+        assert_eq!(
+            vec![library.join("trip/b.jpg")],
+            find(&meta, "tag:holiday", &[library.join("trip")]).unwrap()
+        );
+        assert!(matches!(
+            find(&meta, "tag:holiday", &[folder.join("data")]),
+            Err(Error::NoRootFolder(_))
+        ));
+    }
+
+    #[test]
+    fn find_persons_by_id_or_name() {
+        let (_folder, meta) = repository("persons", &[]);
+        let anna = meta.edit_persons(|persons| persons.add(Person::new("Anna".into(), None)));
+        for _ in 0..2 {
+            meta.edit_persons(|persons| persons.add(Person::new("Ben".into(), None)));
+        }
+
+        assert_eq!(anna, find_person(&meta, "Anna").unwrap());
+        assert_eq!(anna, find_person(&meta, &anna.0.to_string()).unwrap());
+        assert!(matches!(
+            find_person(&meta, "Ben"),
+            Err(Error::AmbiguousPerson(_))
+        ));
+        assert_eq!(anna, find_person(&meta, "ANNA").unwrap());
+        assert!(matches!(
+            find_person(&meta, "Anne"),
+            Err(Error::UnknownPerson(_))
+        ));
+        assert!(matches!(
+            find_person(&meta, "99"),
+            Err(Error::UnknownPerson(_))
+        ));
+    }
+
+    #[test]
+    fn export_and_check_files() {
+        let (folder, meta) = repository("export", &["a.jpg", "b.jpg"]);
+        let library = folder.join("library");
+        let files = FileArguments {
+            files: vec![library.join("a.jpg"), library.join("b.jpg")],
+            root: Some(library.clone()),
+        };
+        tag(&meta, "holiday", &files).unwrap();
+        assert!(check(&meta).unwrap());
+
+        let output = folder.join("report.txt");
+        let arguments = ExportArguments {
+            query: "tag:holiday".into(),
+            folder: Vec::new(),
+            format: ExportFormat::List,
+            person_report: false,
+            output: Some(output.clone()),
+        };
+        export(&meta, &arguments).unwrap();
+        assert_eq!(
+            format!(
+                "{}\n{}\n",
+                library.join("a.jpg").display(),
+                library.join("b.jpg").display()
+            ),
+            fs::read_to_string(&output).unwrap()
+        );
+
+        fs::remove_file(library.join("b.jpg")).unwrap();
+        assert!(!check(&meta).unwrap());
+    }
+}
@@ -0,0 +1,538 @@
+use crate::{Error, Result};
+
+use std::{
+    ffi::OsString,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use meta::{model::MetaFile, xmp::Xmp, Repository};
+
+const SOI: u8 = 0xD8;
+const SOS: u8 = 0xDA;
+const EOI: u8 = 0xD9;
+const APP1: u8 = 0xE1;
+const APP13: u8 = 0xED;
+
+const XMP_HEADER: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+const PHOTOSHOP_HEADER: &[u8] = b"Photoshop 3.0\0";
+const RESOURCE_SIGNATURE: &[u8] = b"8BIM";
+const IPTC_RESOURCE: u16 = 0x0404;
+
+/// Maximal content size of a JPEG segment, excluding the length field.
+const MAX_SEGMENT_SIZE: usize = u16::MAX as usize - 2;
+
+/// Maximal sizes of the IPTC-IIM keyword (2:25) and caption (2:120) records in bytes.
+const MAX_KEYWORD_SIZE: usize = 64;
+const MAX_CAPTION_SIZE: usize = 2000;
+
+/// Meta data of a JPEG image that gets written into its embedded XMP and IPTC blocks.
+pub(crate) struct Embedding {
+    pub(crate) path: PathBuf,
+    xmp: Xmp,
+}
+
+/// Collects the meta data of all JPEG images among the paths that have persons, tags or a caption.
+///
+/// Nothing is written, so the result can be shown as a dry run before calling [Embedding::write].
+pub(crate) fn plan(meta: &Repository, paths: &[PathBuf]) -> Result<Vec<Embedding>> {
+    let mut embeddings = Vec::new();
+    for path in paths.iter().filter(|path| is_jpeg(path)) {
+        let Some(file) = meta.load_file(path)? else {
+            continue;
+        };
+        if file.persons.is_empty() && file.tags.is_empty() && file.caption.is_none() {
+            continue;
+        }
+        let file = MetaFile {
+            persons: file.persons.clone(),
+            regions: file.regions.clone(),
+            tags: file.tags.clone(),
+            caption: file.caption.clone(),
+            ..Default::default()
+        };
+        embeddings.push(Embedding {
+            path: path.clone(),
+            xmp: Xmp::from_meta_file(&file, &meta.persons()),
+        });
+    }
+    Ok(embeddings)
+}
+
+fn is_jpeg(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| {
+            extension.eq_ignore_ascii_case("jpg") || extension.eq_ignore_ascii_case("jpeg")
+        })
+}
+
+/// Path of the backup of the original file, e.g. `image.jpg_original`.
+pub(crate) fn backup_path(path: &Path) -> PathBuf {
+    let mut backup = OsString::from(path.as_os_str());
+    backup.push("_original");
+    backup.into()
+}
+
+impl Embedding {
+    /// Short description of what gets written, e.g. for a dry run listing.
+    pub(crate) fn summary(&self) -> String {
+        let mut parts = Vec::new();
+        if !self.xmp.persons.is_empty() {
+            parts.push(format!("persons: {}", self.xmp.persons.join(", ")));
+        }
+        let keywords = self.keywords();
+        if !keywords.is_empty() {
+            parts.push(format!("keywords: {}", keywords.join(", ")));
+        }
+        if let Some(caption) = &self.xmp.description {
+            parts.push(format!("caption: {caption}"));
+        }
+        format!("{}: {}", self.path.display(), parts.join("; "))
+    }
+
+    /// Keywords for IPTC, which has no field for persons, so their names are included.
+    fn keywords(&self) -> Vec<String> {
+        let mut keywords: Vec<_> = self
+            .xmp
+            .tags
+            .iter()
+            .flat_map(|(key, values)| {
+                if values.is_empty() {
+                    vec![key.clone()]
+                } else {
+                    values.clone()
+                }
+            })
+            .collect();
+        keywords.sort();
+        keywords
+    }
+
+    /// Merges the meta data into the embedded XMP packet (see [Xmp::merge_into]) and replaces
+    /// the IPTC keywords and caption of the image.
+    ///
+    /// Pixel data and all other segments, including extended XMP, are copied unchanged.
+    /// If enabled, the original file is kept as backup (see [backup_path]), unless a backup
+    /// already exists.
+    pub(crate) fn write(&self, backup: bool) -> Result<()> {
+        let original = fs::read(&self.path)?;
+        let embedded = embed(&original, &self.xmp, &self.iptc())?;
+
+        let backup_path = backup_path(&self.path);
+        if backup && !backup_path.try_exists()? {
+            fs::write(backup_path, &original).map_err(Error::WriteFile)?;
+        }
+
+        // Write next to the file first, so the image is not lost if writing fails.
+        let mut temporary = OsString::from(self.path.as_os_str());
+        temporary.push(".tmp");
+        fs::write(&temporary, embedded).map_err(Error::WriteFile)?;
+        fs::rename(&temporary, &self.path).map_err(Error::WriteFile)
+    }
+
+    /// IPTC-IIM records with keywords, person names and caption, encoded as UTF-8.
+    fn iptc(&self) -> Vec<u8> {
+        let mut iptc = Vec::new();
+        // Coded character set: UTF-8.
+        iptc_record(&mut iptc, 1, 90, b"\x1b%G");
+        iptc_record(&mut iptc, 2, 0, &[0, 4]);
+        for keyword in self.keywords().iter().chain(&self.xmp.persons) {
+            iptc_record(
+                &mut iptc,
+                2,
+                25,
+                truncate(keyword, MAX_KEYWORD_SIZE).as_bytes(),
+            );
+        }
+        if let Some(caption) = &self.xmp.description {
+            iptc_record(
+                &mut iptc,
+                2,
+                120,
+                truncate(caption, MAX_CAPTION_SIZE).as_bytes(),
+            );
+        }
+        iptc
+    }
+}
+
+/// Longest prefix of the text with at most the given number of bytes that does not split
+/// a character.
+fn truncate(text: &str, max_size: usize) -> &str {
+    let mut end = text.len().min(max_size);
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    &text[..end]
+}
+
+fn iptc_record(iptc: &mut Vec<u8>, record: u8, dataset: u8, data: &[u8]) {
+    iptc.extend([0x1C, record, dataset]);
+    iptc.extend((data.len() as u16).to_be_bytes());
+    iptc.extend(data);
+}
+
+/// Splits IPTC-IIM data into its records as record number, dataset number and the whole
+/// bytes of the record. Stops at data that is not a record.
+fn iptc_records(iptc: &[u8]) -> Vec<(u8, u8, &[u8])> {
+    let mut records = Vec::new();
+    let mut position = 0;
+    while let Some(&[0x1C, record, dataset, high, low]) = iptc.get(position..position + 5) {
+        let length = u16::from_be_bytes([high, low]) as usize;
+        let (header, size) = if length & 0x8000 == 0 {
+            (5, length)
+        } else {
+            // Extended length, stored in the given number of bytes.
+            let count = length & 0x7FFF;
+            let Some(bytes) = iptc.get(position + 5..position + 5 + count) else {
+                break;
+            };
+            let size = bytes
+                .iter()
+                .fold(0usize, |size, byte| size << 8 | *byte as usize);
+            (5 + count, size)
+        };
+        let Some(bytes) = iptc.get(position..position + header + size) else {
+            break;
+        };
+        records.push((record, dataset, bytes));
+        position += header + size;
+    }
+    records
+}
+
+/// Returns the JPEG with the meta data merged into its XMP packet and the IPTC records of
+/// its Photoshop segment replaced. Segments are kept in place, other Photoshop resources and
+/// IPTC records are kept.
+fn embed(jpeg: &[u8], xmp: &Xmp, iptc: &[u8]) -> Result<Vec<u8>> {
+    if !jpeg.starts_with(&[0xFF, SOI]) {
+        return Err(Error::InvalidJpeg("missing start of image"));
+    }
+
+    let mut segments = Vec::new();
+    let mut position = 2;
+    let rest = loop {
+        let Some(&[0xFF, marker]) = jpeg.get(position..position + 2) else {
+            return Err(Error::InvalidJpeg("missing segment marker"));
+        };
+        if marker == 0xFF {
+            // Fill byte.
+            position += 1;
+            continue;
+        }
+        if marker == SOS || marker == EOI {
+            break &jpeg[position..];
+        }
+        let Some(&[high, low]) = jpeg.get(position + 2..position + 4) else {
+            return Err(Error::InvalidJpeg("truncated segment"));
+        };
+        let end = position + 2 + u16::from_be_bytes([high, low]) as usize;
+        let content = jpeg
+            .get(position + 4..end)
+            .ok_or(Error::InvalidJpeg("truncated segment"))?;
+        segments.push((marker, content.to_vec()));
+        position = end;
+    };
+
+    let is_xmp =
+        |(marker, content): &(u8, Vec<u8>)| *marker == APP1 && content.starts_with(XMP_HEADER);
+    let is_photoshop = |(marker, content): &(u8, Vec<u8>)| {
+        *marker == APP13 && content.starts_with(PHOTOSHOP_HEADER)
+    };
+
+    let xml = match segments.iter().find(|segment| is_xmp(segment)) {
+        Some((_, content)) => {
+            let existing = std::str::from_utf8(&content[XMP_HEADER.len()..])
+                .map_err(|_| Error::InvalidJpeg("embedded XMP is not UTF-8"))?;
+            xmp.merge_into(existing)?
+        }
+        None => xmp.to_xml(),
+    };
+    let mut xmp_segment = XMP_HEADER.to_vec();
+    xmp_segment.extend(xml.as_bytes());
+
+    let photoshop = segments
+        .iter()
+        .find(|segment| is_photoshop(segment))
+        .map(|(_, content)| &content[PHOTOSHOP_HEADER.len()..]);
+    let mut photoshop_segment = PHOTOSHOP_HEADER.to_vec();
+    photoshop_segment.extend(replace_iptc(photoshop.unwrap_or_default(), iptc));
+    if xmp_segment.len() > MAX_SEGMENT_SIZE || photoshop_segment.len() > MAX_SEGMENT_SIZE {
+        return Err(Error::InvalidJpeg("meta data too large to embed"));
+    }
+
+    let index = segments.iter().position(is_xmp);
+    replace_segment(&mut segments, index, (APP1, xmp_segment));
+    let index = segments.iter().position(is_photoshop);
+    replace_segment(&mut segments, index, (APP13, photoshop_segment));
+
+    let mut output = Vec::with_capacity(jpeg.len() + xml.len() + iptc.len());
+    output.extend([0xFF, SOI]);
+    for (marker, content) in segments {
+        output.extend([0xFF, marker]);
+        output.extend((content.len() as u16 + 2).to_be_bytes());
+        output.extend(content);
+    }
+    output.extend(rest);
+    Ok(output)
+}
+
+/// Replaces the segment at the index, or inserts it if there is no such segment yet.
+fn replace_segment(
+    segments: &mut Vec<(u8, Vec<u8>)>,
+    index: Option<usize>,
+    segment: (u8, Vec<u8>),
+) {
+    match index {
+        Some(index) => segments[index] = segment,
+        None => {
+            // Keep JFIF, EXIF and existing XMP segments first, as some readers expect.
+            let insert_at = segments
+                .iter()
+                .position(|(marker, _)| !(0xE0..=APP1).contains(marker))
+                .unwrap_or(segments.len());
+            segments.insert(insert_at, segment);
+        }
+    }
+}
+
+/// Replaces the records of the IPTC resource among the Photoshop image resources that are
+/// given in `iptc`. Other records, e.g. the copyright notice, are kept.
+fn replace_iptc(resources: &[u8], iptc: &[u8]) -> Vec<u8> {
+    let mut output = Vec::new();
+    let mut existing = &[][..];
+    let mut position = 0;
+    while let Some(resource) = resources.get(position..) {
+        if !resource.starts_with(RESOURCE_SIGNATURE) || resource.len() < 8 {
+            break;
+        }
+        let id = u16::from_be_bytes([resource[4], resource[5]]);
+        // Pascal string name padded to an even length.
+        let name_length = (resource[6] as usize + 2) & !1;
+        let Some(size) = resource.get(6 + name_length..10 + name_length) else {
+            break;
+        };
+        let size = u32::from_be_bytes([size[0], size[1], size[2], size[3]]) as usize;
+        let end = 10 + name_length + ((size + 1) & !1);
+        let Some(resource) = resource.get(..end.min(resource.len())) else {
+            break;
+        };
+        if id == IPTC_RESOURCE {
+            let data = 10 + name_length;
+            existing = &resource[data.min(resource.len())..(data + size).min(resource.len())];
+        } else {
+            output.extend(resource);
+        }
+        position += end;
+    }
+
+    let replaced: Vec<_> = iptc_records(iptc)
+        .iter()
+        .map(|(record, dataset, _)| (*record, *dataset))
+        .collect();
+    let mut records: Vec<_> = iptc_records(existing)
+        .into_iter()
+        .filter(|(record, dataset, _)| !replaced.contains(&(*record, *dataset)))
+        .chain(iptc_records(iptc))
+        .collect();
+    // Records have to be sorted by record number and start with their version (dataset 0),
+    // the order of the other datasets is kept.
+    records.sort_by_key(|(record, dataset, _)| (*record, *dataset != 0));
+    let iptc: Vec<u8> = records
+        .into_iter()
+        .flat_map(|(_, _, bytes)| bytes.iter().copied())
+        .collect();
+
+    output.extend(RESOURCE_SIGNATURE);
+    output.extend(IPTC_RESOURCE.to_be_bytes());
+    output.extend([0, 0]);
+    output.extend((iptc.len() as u32).to_be_bytes());
+    output.extend(&iptc);
+    if iptc.len() % 2 == 1 {
+        output.push(0);
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::HashMap;
+
+    const EXIF: &[u8] = b"Exif\0\0MM\0\x2a\0\0\0\x08\0\0";
+    const EXTENDED_XMP: &[u8] = b"http://ns.adobe.com/xmp/extension/\0\
+        0123456789ABCDEF0123456789ABCDEF\0\0\0\x10\0\0\0\0<x:xmpmeta/>";
+    const DQT: &[u8] = &[0; 65];
+    const SCAN: &[u8] = &[0xFF, SOS, 0, 2, 0x12, 0x34, 0xFF, 0x00, 0xFF, EOI];
+    const XMP: &str = r#"<x:xmpmeta xmlns:x="adobe:ns:meta/"><rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#"><rdf:Description rdf:about="" xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:xmpNote="http://ns.adobe.com/xmp/note/" xmlns:darktable="http://darktable.sf.net/" xmpNote:HasExtendedXMP="0123456789ABCDEF0123456789ABCDEF" darktable:history_end="2"><dc:subject><rdf:Bag><rdf:li>old</rdf:li></rdf:Bag></dc:subject></rdf:Description></rdf:RDF></x:xmpmeta>"#;
+
+    fn segment(marker: u8, content: &[u8]) -> Vec<u8> {
+        let mut segment = vec![0xFF, marker];
+        segment.extend((content.len() as u16 + 2).to_be_bytes());
+        segment.extend(content);
+        segment
+    }
+
+    fn resource(id: u16, data: &[u8]) -> Vec<u8> {
+        let mut resource = RESOURCE_SIGNATURE.to_vec();
+        resource.extend(id.to_be_bytes());
+        resource.extend([0, 0]);
+        resource.extend((data.len() as u32).to_be_bytes());
+        resource.extend(data);
+        if data.len() % 2 == 1 {
+            resource.push(0);
+        }
+        resource
+    }
+
+    fn record(record: u8, dataset: u8, data: &str) -> Vec<u8> {
+        let mut iptc = Vec::new();
+        iptc_record(&mut iptc, record, dataset, data.as_bytes());
+        iptc
+    }
+
+    /// JPEG with EXIF, XMP with extended XMP, a Photoshop segment with IPTC and
+    /// a quantization table.
+    fn jpeg() -> Vec<u8> {
+        let mut xmp = XMP_HEADER.to_vec();
+        xmp.extend(XMP.as_bytes());
+        let iptc = [
+            record(2, 0, "\0\x04"),
+            record(2, 25, "old"),
+            record(2, 80, "Anna"),
+            record(2, 116, "© Anna"),
+        ]
+        .concat();
+        let mut photoshop = PHOTOSHOP_HEADER.to_vec();
+        photoshop.extend(resource(0x0425, &[7; 16]));
+        photoshop.extend(resource(IPTC_RESOURCE, &iptc));
+
+        [
+            vec![0xFF, SOI],
+            segment(APP1, EXIF),
+            segment(APP1, &xmp),
+            segment(APP1, EXTENDED_XMP),
+            segment(APP13, &photoshop),
+            segment(0xDB, DQT),
+            SCAN.to_vec(),
+        ]
+        .concat()
+    }
+
+    /// Segments of the JPEG up to the scan, which is returned as last entry with marker SOS.
+    fn segments(jpeg: &[u8]) -> Vec<(u8, Vec<u8>)> {
+        let mut segments = Vec::new();
+        let mut position = 2;
+        while jpeg[position + 1] != SOS {
+            let length = u16::from_be_bytes([jpeg[position + 2], jpeg[position + 3]]) as usize;
+            segments.push((
+                jpeg[position + 1],
+                jpeg[position + 4..position + 2 + length].to_vec(),
+            ));
+            position += 2 + length;
+        }
+        segments.push((SOS, jpeg[position..].to_vec()));
+        segments
+    }
+
+    fn embedding() -> Embedding {
+        Embedding {
+            path: PathBuf::from("image.jpg"),
+            xmp: Xmp {
+                persons: vec!["Ben".into()],
+                tags: HashMap::from([("trip".into(), vec!["Rome".into()])]),
+                description: Some("Colosseum".into()),
+                ..Default::default()
+            },
+        }
+    }
+
+    #[test]
+    fn embed_keeps_other_meta_data() {
+        let original = segments(&jpeg());
+        let embedding = embedding();
+        let embedded = segments(&embed(&jpeg(), &embedding.xmp, &embedding.iptc()).unwrap());
+
+        assert_eq!(original.len(), embedded.len());
+        for index in [0, 2, 4, 5] {
+            assert_eq!(original[index], embedded[index], "segment {index}");
+        }
+
+        let (marker, xmp) = &embedded[1];
+        assert_eq!(APP1, *marker);
+        let xml = std::str::from_utf8(xmp.strip_prefix(XMP_HEADER).unwrap()).unwrap();
+        let parsed = Xmp::parse(xml).unwrap();
+        assert_eq!(embedding.xmp.persons, parsed.persons);
+        assert_eq!(embedding.xmp.tags, parsed.tags);
+        assert_eq!(embedding.xmp.description, parsed.description);
+        assert!(xml.contains(r#"xmpNote:HasExtendedXMP="0123456789ABCDEF0123456789ABCDEF""#));
+        assert!(xml.contains(r#"darktable:history_end="2""#));
+
+        let (marker, photoshop) = &embedded[3];
+        assert_eq!(APP13, *marker);
+        let resources = photoshop.strip_prefix(PHOTOSHOP_HEADER).unwrap();
+        let other = resource(0x0425, &[7; 16]);
+        assert!(resources.starts_with(&other));
+        let iptc = &resources[other.len() + 12..];
+        let records: Vec<_> = iptc_records(iptc)
+            .into_iter()
+            .map(|(record, dataset, bytes)| (record, dataset, bytes.to_vec()))
+            .collect();
+        assert_eq!(
+            vec![
+                (1, 90, record(1, 90, "\x1b%G")),
+                (2, 0, record(2, 0, "\0\x04")),
+                (2, 80, record(2, 80, "Anna")),
+                (2, 116, record(2, 116, "© Anna")),
+                (2, 25, record(2, 25, "Rome")),
+                (2, 25, record(2, 25, "Ben")),
+                (2, 120, record(2, 120, "Colosseum")),
+            ],
+            records
+        );
+    }
+
+    #[test]
+    fn embed_into_jpeg_without_meta_data() {
+        let jpeg = [vec![0xFF, SOI], segment(0xDB, DQT), SCAN.to_vec()].concat();
+        let embedding = embedding();
+        let embedded = segments(&embed(&jpeg, &embedding.xmp, &embedding.iptc()).unwrap());
+
+        let markers: Vec<_> = embedded.iter().map(|(marker, _)| *marker).collect();
+        assert_eq!(vec![APP1, APP13, 0xDB, SOS], markers);
+        let xml = std::str::from_utf8(&embedded[0].1[XMP_HEADER.len()..]).unwrap();
+        assert_eq!(embedding.xmp.persons, Xmp::parse(xml).unwrap().persons);
+        assert_eq!(SCAN, embedded[3].1);
+    }
+
+    #[test]
+    fn iptc_records_fit_their_limits() {
+        let mut embedding = embedding();
+        let keyword = "ä".repeat(40);
+        embedding.xmp.tags = HashMap::from([(keyword.clone(), vec![])]);
+        embedding.xmp.persons.clear();
+        embedding.xmp.description = Some("é".repeat(1500));
+
+        let iptc = embedding.iptc();
+        let records = iptc_records(&iptc);
+        let data = |dataset| {
+            let (_, _, bytes) = records
+                .iter()
+                .find(|(_, found, _)| *found == dataset)
+                .unwrap();
+            std::str::from_utf8(&bytes[5..]).unwrap().to_string()
+        };
+        assert_eq!("ä".repeat(32), data(25));
+        assert_eq!("é".repeat(1000), data(120));
+    }
+
+    #[test]
+    fn truncate_keeps_characters_whole() {
+        assert_eq!("ab", truncate("abc", 2));
+        assert_eq!("a", truncate("aé", 2));
+        assert_eq!("aé", truncate("aé", 3));
+        assert_eq!("", truncate("", 2));
+    }
+}
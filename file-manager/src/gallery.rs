@@ -0,0 +1,198 @@
+use crate::{images, Error, Result};
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use image::{imageops::FilterType, ImageFormat};
+use meta::{model::PersonId, Repository};
+use rayon::prelude::*;
+
+/// Maximal width and height of the images shown when opening a thumbnail.
+const IMAGE_SIZE: u32 = 1600;
+const THUMBNAIL_SIZE: u32 = 300;
+const MEDIA_FOLDER: &str = "media";
+
+const STYLE: &str = "body { font-family: sans-serif; margin: 2em; background: #222; color: #eee; }
+a { color: #9cf; }
+.images { display: flex; flex-wrap: wrap; gap: 1em; }
+figure { margin: 0; width: 300px; }
+figure img { max-width: 300px; max-height: 300px; }
+figcaption { font-size: 0.9em; }";
+
+/// Image of the gallery with the meta data shown for it.
+struct Entry {
+    source: PathBuf,
+    /// File name of the resized image, the thumbnail gets a prefix.
+    name: String,
+    caption: Option<String>,
+    persons: Vec<PersonId>,
+}
+
+/// Writes a static HTML gallery of the images into the folder, which gets created if needed.
+///
+/// The gallery consists of an `index.html` with all images, a page per person showing their
+/// images, and resized images and thumbnails. Returns the path of the `index.html`.
+pub(crate) fn export(
+    meta: &Repository,
+    paths: &[PathBuf],
+    folder: &Path,
+    title: &str,
+) -> Result<PathBuf> {
+    fs::create_dir_all(folder.join(MEDIA_FOLDER)).map_err(Error::WriteFile)?;
+
+    let mut entries = Vec::with_capacity(paths.len());
+    for (index, path) in paths.iter().enumerate() {
+        let file = meta.load_file(path)?;
+        let mut persons: Vec<_> = file
+            .as_ref()
+            .map(|file| file.persons.iter().copied().collect())
+            .unwrap_or_default();
+        persons.sort_by_key(|id| id.0);
+        entries.push(Entry {
+            source: path.clone(),
+            name: format!("{index:05}.jpg"),
+            caption: file.and_then(|file| file.caption),
+            persons,
+        });
+    }
+
+    // Errors are not sendable between threads, so only their messages are passed on.
+    let media_folder = folder.join(MEDIA_FOLDER);
+    let error = entries.par_iter().find_map_any(|entry| {
+        write_images(entry, &media_folder)
+            .err()
+            .map(|error| format!("{}: {error}", entry.source.display()))
+    });
+    if let Some(error) = error {
+        return Err(Error::ExportGallery(error));
+    }
+
+    let persons = meta.persons();
+    let mut by_person: BTreeMap<String, (PersonId, Vec<&Entry>)> = BTreeMap::new();
+    for entry in &entries {
+        for id in &entry.persons {
+            if let Some(person) = persons.person(id) {
+                by_person
+                    .entry(person.name.clone())
+                    .or_insert_with(|| (*id, Vec::new()))
+                    .1
+                    .push(entry);
+            }
+        }
+    }
+
+    let name = |id: &PersonId| persons.person(id).map(|person| person.name.as_str());
+    let all: Vec<_> = entries.iter().collect();
+    let mut links = String::new();
+    if !by_person.is_empty() {
+        links.push_str("<h2>People</h2>\n<ul>\n");
+        for (person, (id, person_entries)) in &by_person {
+            writeln!(
+                links,
+                "<li><a href=\"{}\">{}</a> ({})</li>",
+                person_page(id),
+                escape(person),
+                person_entries.len()
+            )
+            .unwrap();
+        }
+        links.push_str("</ul>\n");
+    }
+    let index = folder.join("index.html");
+    write_page(&index, title, &links, &all, &name)?;
+
+    for (person, (id, person_entries)) in &by_person {
+        let back = format!("<p><a href=\"index.html\">{}</a></p>\n", escape(title));
+        write_page(
+            &folder.join(person_page(id)),
+            person,
+            &back,
+            person_entries,
+            &name,
+        )?;
+    }
+    Ok(index)
+}
+
+fn person_page(id: &PersonId) -> String {
+    format!("person-{}.html", id.0)
+}
+
+/// Writes the resized image and the thumbnail of the entry into the media folder.
+fn write_images(entry: &Entry, media_folder: &Path) -> Result<()> {
+    let image = images::decode(&fs::read(&entry.source)?)?;
+    let resized = if image.width() > IMAGE_SIZE || image.height() > IMAGE_SIZE {
+        image.resize(IMAGE_SIZE, IMAGE_SIZE, FilterType::Lanczos3)
+    } else {
+        image.clone()
+    };
+    resized
+        .to_rgb8()
+        .save_with_format(media_folder.join(&entry.name), ImageFormat::Jpeg)?;
+    image
+        .thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE)
+        .to_rgb8()
+        .save_with_format(
+            media_folder.join(format!("thumbnail-{}", entry.name)),
+            ImageFormat::Jpeg,
+        )?;
+    Ok(())
+}
+
+fn write_page<'a>(
+    path: &Path,
+    title: &str,
+    header: &str,
+    entries: &[&Entry],
+    name: &impl Fn(&PersonId) -> Option<&'a str>,
+) -> Result<()> {
+    let title = escape(title);
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+         <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n\
+         <title>{title}</title>\n<style>\n{STYLE}\n</style>\n</head>\n<body>\n\
+         <h1>{title}</h1>\n{header}<div class=\"images\">\n"
+    );
+    for entry in entries {
+        write!(
+            html,
+            "<figure>\n<a href=\"{MEDIA_FOLDER}/{0}\"><img src=\"{MEDIA_FOLDER}/thumbnail-{0}\" loading=\"lazy\" alt=\"\"></a>\n",
+            entry.name
+        )
+        .unwrap();
+        let persons: Vec<_> = entry
+            .persons
+            .iter()
+            .filter_map(|id| {
+                let person = name(id)?;
+                Some(format!(
+                    "<a href=\"{}\">{}</a>",
+                    person_page(id),
+                    escape(person)
+                ))
+            })
+            .collect();
+        if entry.caption.is_some() || !persons.is_empty() {
+            html.push_str("<figcaption>");
+            if let Some(caption) = &entry.caption {
+                write!(html, "{}<br>", escape(caption)).unwrap();
+            }
+            html.push_str(&persons.join(", "));
+            html.push_str("</figcaption>\n");
+        }
+        html.push_str("</figure>\n");
+    }
+    html.push_str("</div>\n</body>\n</html>\n");
+    fs::write(path, html).map_err(Error::WriteFile)
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
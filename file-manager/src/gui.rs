@@ -1,23 +1,109 @@
+mod album_view;
+mod api;
+mod info_view;
 mod logic;
 mod main_view;
 mod meta_view;
+mod rename_view;
+mod timeline_view;
 
-use crate::images::ImageCache;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crate::embed::Embedding;
+use crate::images::{Dates, ImageCache, SortOrder};
+use crate::rename::Rename;
+use crate::server::Event;
+use crate::timeline::Timeline;
 
 use approximate_string_matcher::MatchResult;
+use crossbeam_channel::Receiver;
 use eframe::Frame;
 use eframe::{egui::Context, App};
-use meta::model::RootFolderId;
+use meta::export::Format;
+use meta::model::{AlbumId, Exif, Region, RootFolderId};
 use meta::Repository;
 
 pub(crate) struct FileManagerApp {
     images: ImageCache,
-    meta: Repository,
+    meta: Arc<Repository>,
     meta_current_folder: RootFolderId,
     meta_window_open: bool,
+    meta_kind: MetaKind,
     meta_search: String,
     meta_options: Vec<MetaOption>,
     meta_selected_option: isize,
+    filter_search: String,
+    filter_error: Option<String>,
+    triage_mode: bool,
+    untagged_count: usize,
+    /// Error of the last action in the main view, e.g. toggling the triage mode.
+    main_error: Option<String>,
+    album_window_open: bool,
+    album_name: String,
+    album_status: Option<String>,
+    export_format: Format,
+    /// Folder into which the HTML gallery gets exported.
+    gallery_folder: String,
+    /// Result of the gallery export running in the background.
+    gallery_export: Option<Receiver<Result<PathBuf, String>>>,
+    /// Folder of the link tree organised by person and tag.
+    view_folder: String,
+
+    /// Album that is browsed in its custom order.
+    browsed_album: Option<AlbumId>,
+
+    /// Caption of the image at `caption_path` that is being edited.
+    caption: String,
+    caption_path: Option<PathBuf>,
+    caption_focus_requested: bool,
+
+    /// Corners of the region that is being drawn on the image, relative to the image size.
+    region_drag: Option<((f32, f32), (f32, f32))>,
+
+    /// Region that gets assigned to the next person chosen in the meta window.
+    pending_region: Option<Region>,
+    show_regions: bool,
+
+    info_panel_open: bool,
+    /// EXIF data of the image at the given path, read for the info panel.
+    info_exif: Option<(PathBuf, Result<Option<Exif>, String>)>,
+    info_status: Option<String>,
+    /// Copy EXIF data of viewed images into their meta data.
+    store_exif: bool,
+    /// Keep the original when embedding meta data into images.
+    embed_backup: bool,
+    /// Meta data that would be embedded into images, shown as a dry run before it can be written.
+    embed_preview: Option<Vec<Embedding>>,
+
+    sort_order: SortOrder,
+    sort_reverse: bool,
+
+    timeline_open: bool,
+    /// Timeline of all images, built when the timeline is opened.
+    timeline: Option<Timeline>,
+    /// Dates taken of all images for the timeline and sorting by date, read in the background.
+    dates: Option<Arc<Dates>>,
+    dates_loading: Option<Receiver<Dates>>,
+
+    rename_window_open: bool,
+    rename_template: String,
+    /// Planned renames of the browsed images, shown before they are executed.
+    rename_preview: Option<Vec<Rename>>,
+    rename_status: Option<String>,
+
+    /// Result of indexing all root folders while it runs, see [FileManagerApp::index_all].
+    indexing: Option<Receiver<std::result::Result<(), String>>>,
+
+    /// Calls of the local server if it was started.
+    api_calls: Option<Receiver<Event>>,
+}
+
+/// Kind of meta data that is picked in the meta window.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum MetaKind {
+    Persons,
+    Albums,
 }
 
 pub(crate) enum MetaOption {
@@ -28,24 +114,69 @@ pub(crate) enum MetaOption {
 impl FileManagerApp {
     pub(crate) fn new(
         images: ImageCache,
-        meta: Repository,
+        meta: Arc<Repository>,
         meta_current_folder: RootFolderId,
+        api_calls: Option<Receiver<Event>>,
     ) -> Self {
         Self {
             images,
             meta,
             meta_current_folder,
             meta_window_open: false,
+            meta_kind: MetaKind::Persons,
             meta_search: String::new(),
             meta_options: vec![MetaOption::Create],
             meta_selected_option: 0,
+            filter_search: String::new(),
+            filter_error: None,
+            main_error: None,
+            triage_mode: false,
+            untagged_count: 0,
+            album_window_open: false,
+            album_name: String::new(),
+            album_status: None,
+            export_format: Format::FileList,
+            gallery_folder: String::new(),
+            gallery_export: None,
+            view_folder: String::new(),
+            browsed_album: None,
+            caption: String::new(),
+            caption_path: None,
+            caption_focus_requested: false,
+            region_drag: None,
+            pending_region: None,
+            show_regions: false,
+            info_panel_open: false,
+            info_exif: None,
+            info_status: None,
+            store_exif: false,
+            embed_backup: true,
+            embed_preview: None,
+            sort_order: SortOrder::Name,
+            sort_reverse: false,
+            timeline_open: false,
+            timeline: None,
+            dates: None,
+            dates_loading: None,
+            rename_window_open: false,
+            rename_template: String::new(),
+            rename_preview: None,
+            rename_status: None,
+            indexing: None,
+            api_calls,
         }
     }
 }
 
 impl App for FileManagerApp {
     fn update(&mut self, ctx: &Context, _frame: &mut Frame) {
+        self.handle_api_calls();
+        self.receive_index();
+        self.receive_dates();
+        self.receive_gallery_export();
         self.update_meta_view(ctx);
+        self.update_album_view(ctx);
+        self.update_rename_view(ctx);
         self.update_main_view(ctx);
     }
 
@@ -0,0 +1,272 @@
+use eframe::{
+    egui::{Button, ComboBox, Context, Grid, TextEdit, Ui, Window},
+    epaint::Color32,
+};
+use meta::{
+    export::Format,
+    model::{AlbumId, SmartAlbumId},
+};
+
+use super::FileManagerApp;
+
+impl FileManagerApp {
+    pub(crate) fn update_album_view(&mut self, ctx: &Context) {
+        if !self.album_window_open {
+            return;
+        }
+
+        let mut open = self.album_window_open;
+        Window::new("Albums")
+            .id(eframe::egui::Id::new("album_window"))
+            .collapsible(false)
+            .open(&mut open)
+            .show(ctx, |ui| {
+                self.add_albums(ui);
+                ui.separator();
+                self.add_smart_albums(ui);
+
+                if let Some(status) = &self.album_status {
+                    ui.colored_label(Color32::LIGHT_GRAY, status);
+                }
+            });
+        self.album_window_open = open;
+    }
+
+    fn add_albums(&mut self, ui: &mut Ui) {
+        ui.heading("Albums");
+        let mut albums: Vec<(AlbumId, String, usize, Option<String>)> = self
+            .meta
+            .albums()
+            .entries()
+            .iter()
+            .map(|(id, album)| {
+                let cover = album
+                    .cover
+                    .as_ref()
+                    .map(|cover| cover.path.to_string_lossy().into_owned());
+                (*id, album.title.clone(), album.files.len(), cover)
+            })
+            .collect();
+        albums.sort_by(|(_, a, _, _), (_, b, _, _)| a.cmp(b));
+
+        Grid::new("album_grid").striped(true).show(ui, |ui| {
+            for (id, title, count, cover) in albums {
+                let title = ui.label(title);
+                if let Some(cover) = cover {
+                    title.on_hover_text(format!("Cover: {cover}"));
+                }
+                ui.weak(format!("{count} images"));
+                if ui
+                    .button("Browse")
+                    .on_hover_text("Browse Images in the Order of the Album")
+                    .clicked()
+                {
+                    self.album_status = self.browse_album(&id).err().map(|error| error.to_string());
+                }
+                if ui
+                    .button("Set Cover")
+                    .on_hover_text("Use Current Image as Cover")
+                    .clicked()
+                {
+                    self.album_status = self
+                        .set_current_image_as_album_cover(&id)
+                        .err()
+                        .map(|error| error.to_string());
+                }
+                if ui.button("Delete").clicked() {
+                    self.meta.edit_albums(|albums| albums.remove(&id));
+                    if self.browsed_album == Some(id) {
+                        self.album_status = self
+                            .stop_browsing_album()
+                            .err()
+                            .map(|error| error.to_string());
+                    }
+                }
+                ui.end_row();
+            }
+        });
+
+        let Some(browsed_album) = self.browsed_album else {
+            return;
+        };
+        let title = self
+            .meta
+            .albums()
+            .album(&browsed_album)
+            .map_or_else(String::new, |album| album.title.clone());
+        ui.horizontal(|ui| {
+            ui.label(format!("Browsing {title}:"));
+            let earlier = ui.button("Move Earlier").clicked();
+            let later = ui.button("Move Later").clicked();
+            let remove = ui.button("Remove Image").clicked();
+            let stop = ui.button("Stop").clicked();
+            let result = if earlier {
+                self.move_current_image_in_album(-1)
+            } else if later {
+                self.move_current_image_in_album(1)
+            } else if remove {
+                self.remove_current_image_from_album()
+            } else if stop {
+                self.stop_browsing_album()
+            } else {
+                Ok(())
+            };
+            if let Err(error) = result {
+                self.album_status = Some(error.to_string());
+            }
+        });
+    }
+
+    fn add_smart_albums(&mut self, ui: &mut Ui) {
+        ui.heading("Smart Albums");
+        let mut albums: Vec<(SmartAlbumId, String, String)> = self
+            .meta
+            .smart_albums()
+            .entries()
+            .iter()
+            .map(|(id, album)| (*id, album.name.clone(), album.query.clone()))
+            .collect();
+        albums.sort_by(|(_, a, _), (_, b, _)| a.cmp(b));
+
+        Grid::new("smart_album_grid").striped(true).show(ui, |ui| {
+            for (id, name, query) in albums {
+                ui.label(name);
+                ui.weak(query);
+                if ui
+                    .button("Show")
+                    .on_hover_text("Only Browse Images in this Album")
+                    .clicked()
+                {
+                    self.album_status = self
+                        .show_smart_album(&id)
+                        .err()
+                        .map(|error| error.to_string());
+                }
+                if ui
+                    .button("Export")
+                    .on_hover_text("Write Files in the Export Format into the Root Folder")
+                    .clicked()
+                {
+                    self.album_status = Some(match self.export_smart_album(&id) {
+                        Ok(path) => format!("Exported to {}", path.display()),
+                        Err(error) => error.to_string(),
+                    });
+                }
+                if ui.button("Delete").clicked() {
+                    self.meta
+                        .edit_smart_albums(|smart_albums| smart_albums.remove(&id));
+                }
+                ui.end_row();
+            }
+        });
+
+        ui.separator();
+        ui.horizontal(|ui| {
+            ui.add(TextEdit::singleline(&mut self.album_name).hint_text("Name"));
+            let can_save =
+                !self.album_name.trim().is_empty() && !self.filter_search.trim().is_empty();
+            let save = ui
+                .add_enabled(can_save, Button::new("Save Current Filter"))
+                .on_hover_text("Store the Query of the Filter as Smart Album");
+            if save.clicked() {
+                self.album_status = self
+                    .save_filter_as_smart_album()
+                    .err()
+                    .map(|error| error.to_string());
+            }
+        });
+
+        ui.separator();
+        ui.horizontal(|ui| {
+            ComboBox::from_id_source("export_format")
+                .selected_text(self.export_format.name())
+                .show_ui(ui, |ui| {
+                    for format in Format::ALL {
+                        ui.selectable_value(&mut self.export_format, format, format.name());
+                    }
+                })
+                .response
+                .on_hover_text("Export Format");
+            if ui
+                .button("Export All Files")
+                .on_hover_text(
+                    "Write Meta Data of All Files in the Root Folder into the Root Folder",
+                )
+                .clicked()
+            {
+                self.album_status = Some(match self.export_all_files() {
+                    Ok(path) => format!("Exported to {}", path.display()),
+                    Err(error) => error.to_string(),
+                });
+            }
+            if ui
+                .button("Export Person Report")
+                .on_hover_text(
+                    "Write a CSV File Listing the Files of Each Person into the Root Folder",
+                )
+                .clicked()
+            {
+                self.album_status = Some(match self.export_person_report() {
+                    Ok(path) => format!("Exported to {}", path.display()),
+                    Err(error) => error.to_string(),
+                });
+            }
+        });
+
+        ui.horizontal(|ui| {
+            ui.add(
+                TextEdit::singleline(&mut self.gallery_folder)
+                    .hint_text("Folder, e.g. /home/anna/Gallery"),
+            );
+            let export = ui
+                .add_enabled(
+                    !self.gallery_folder.trim().is_empty() && self.gallery_export.is_none(),
+                    Button::new("Export Gallery"),
+                )
+                .on_hover_text("Write a Static HTML Gallery of the Browsed Images into the Folder");
+            if export.clicked() {
+                self.export_gallery(ui.ctx());
+            }
+            if self.gallery_export.is_some() {
+                ui.spinner();
+            }
+        });
+
+        ui.horizontal(|ui| {
+            ui.add(
+                TextEdit::singleline(&mut self.view_folder)
+                    .hint_text("Folder outside of the root folder"),
+            );
+            let enabled = !self.view_folder.trim().is_empty();
+            let update = ui
+                .add_enabled(enabled, Button::new("Update Links"))
+                .on_hover_text(
+                    "Link All Files into Folders by Person and by Tag, Removing Outdated Links",
+                );
+            let rebuild = ui
+                .add_enabled(enabled, Button::new("Rebuild Links"))
+                .on_hover_text("Recreate All Folders by Person and by Tag");
+            if update.clicked() || rebuild.clicked() {
+                let result = self.meta.update_view(
+                    &self.meta_current_folder,
+                    self.view_folder.trim(),
+                    !rebuild.clicked(),
+                );
+                self.album_status = Some(match result {
+                    Ok(update) => format!(
+                        "Created {} and removed {} links, kept {}{}",
+                        update.created,
+                        update.removed,
+                        update.unchanged,
+                        if update.fallbacks > 0 {
+                            format!(" ({} copied or hard linked)", update.fallbacks)
+                        } else {
+                            String::new()
+                        }
+                    ),
+                    Err(error) => error.to_string(),
+                });
+            }
+        });
+    }
+}
@@ -0,0 +1,30 @@
+use serde_json::{json, Value};
+
+use super::FileManagerApp;
+use crate::{server::Event, Result};
+
+impl FileManagerApp {
+    /// Answers the calls of the local server, see [crate::server::Server].
+    pub(crate) fn handle_api_calls(&mut self) {
+        let Some(calls) = &self.api_calls else {
+            return;
+        };
+        let events: Vec<Event> = calls.try_iter().collect();
+        for event in events {
+            match event {
+                Event::Call(call) => {
+                    let result = self.current_image().map_err(|error| error.to_string());
+                    call.reply(result);
+                }
+                Event::TagsChanged => self.update_untagged_count(),
+            }
+        }
+    }
+
+    fn current_image(&mut self) -> Result<Value> {
+        Ok(match self.images.current_image_path() {
+            Some(path) => json!(self.meta.report(std::slice::from_ref(path))?.first()),
+            None => Value::Null,
+        })
+    }
+}
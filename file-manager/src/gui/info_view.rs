@@ -0,0 +1,274 @@
+use eframe::egui::{Button, Grid, ScrollArea, Ui};
+use meta::{model::Exif, takeout::TakeoutImport};
+use rayon::prelude::*;
+
+use super::FileManagerApp;
+use crate::{embed, images, Result};
+
+impl FileManagerApp {
+    pub(crate) fn info_panel(&mut self, ui: &mut Ui) {
+        ui.heading("Info");
+        ScrollArea::vertical().show(ui, |ui| {
+            match self.current_exif() {
+                Ok(Some(exif)) => Self::add_exif(ui, &exif),
+                Ok(None) => {
+                    ui.label("No EXIF data");
+                }
+                Err(error) => {
+                    ui.label(format!("Could not read EXIF data: {error}"));
+                }
+            }
+
+            ui.separator();
+            ui.checkbox(&mut self.store_exif, "Store in Meta Data")
+                .on_hover_text("Copy EXIF Data of Viewed Images into the Meta Data to Query it");
+            if ui
+                .button("Store for All Images")
+                .on_hover_text("Copy EXIF Data of All Images into the Meta Data")
+                .clicked()
+            {
+                self.info_status = Some(match self.store_exif_of_all_images() {
+                    Ok(count) => format!("Stored EXIF data of {count} images"),
+                    Err(error) => error.to_string(),
+                });
+            }
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui
+                    .button("Write XMP Sidecars")
+                    .on_hover_text("Write Meta Data of All Images into XMP Sidecar Files")
+                    .clicked()
+                {
+                    self.info_status = Some(match self.write_xmp_of_all_images() {
+                        Ok((count, 0)) => format!("Wrote {count} XMP sidecars"),
+                        Ok((count, skipped)) => format!(
+                            "Wrote {count} XMP sidecars, kept {skipped} of other applications"
+                        ),
+                        Err(error) => error.to_string(),
+                    });
+                }
+                if ui
+                    .button("Read XMP Sidecars")
+                    .on_hover_text(
+                        "Read Meta Data from XMP Sidecar Files, e.g. of digiKam, darktable or Lightroom",
+                    )
+                    .clicked()
+                {
+                    self.info_status = Some(match self.read_xmp_of_all_images() {
+                        Ok(count) => format!("Read {count} XMP sidecars"),
+                        Err(error) => error.to_string(),
+                    });
+                }
+            });
+            if ui
+                .button("Import Google Takeout")
+                .on_hover_text("Read People, Descriptions, Dates and Locations from the JSON Files of a Google Photos Export")
+                .clicked()
+            {
+                self.info_status = Some(match self.import_takeout() {
+                    Ok(import) if import.unmatched.is_empty() => {
+                        format!("Imported meta data of {} images", import.imported)
+                    }
+                    Ok(import) => format!(
+                        "Imported meta data of {} images, found no images for {} JSON files",
+                        import.imported,
+                        import.unmatched.len()
+                    ),
+                    Err(error) => error.to_string(),
+                });
+            }
+
+            ui.separator();
+            self.embed_section(ui);
+
+            if let Some(status) = &self.info_status {
+                ui.label(status);
+            }
+        });
+    }
+
+    fn embed_section(&mut self, ui: &mut Ui) {
+        ui.horizontal(|ui| {
+            if ui
+                .button("Preview Embedding")
+                .on_hover_text("List the People, Tags and Captions that would be Written into the JPEG Images")
+                .clicked()
+            {
+                match embed::plan(&self.meta, self.images.all_paths()) {
+                    Ok(embeddings) => self.embed_preview = Some(embeddings),
+                    Err(error) => self.info_status = Some(error.to_string()),
+                }
+            }
+            if ui
+                .add_enabled(self.embed_preview.is_some(), Button::new("Embed into Images"))
+                .on_hover_text("Write the Previewed People, Tags and Captions into the Embedded XMP and IPTC Data of the JPEG Images")
+                .on_disabled_hover_text("Preview the Embedding First")
+                .clicked()
+            {
+                self.info_status = Some(self.embed_previewed_images());
+            }
+        });
+        ui.checkbox(&mut self.embed_backup, "Keep Originals")
+            .on_hover_text("Keep a Copy of Each Original Image, e.g. image.jpg_original");
+
+        if let Some(preview) = &self.embed_preview {
+            ui.label(format!("{} images would be changed:", preview.len()));
+            for embedding in preview {
+                ui.label(embedding.summary());
+            }
+        }
+    }
+
+    /// Writes the previewed meta data into the images and describes the result.
+    ///
+    /// Images that could not be written are skipped and listed, the others are still written.
+    fn embed_previewed_images(&mut self) -> String {
+        let embeddings = self.embed_preview.take().unwrap_or_default();
+        let errors: Vec<_> = embeddings
+            .iter()
+            .filter_map(|embedding| {
+                let error = embedding.write(self.embed_backup).err()?;
+                Some(format!("{}: {error}", embedding.path.display()))
+            })
+            .collect();
+        let written = embeddings.len() - errors.len();
+        if errors.is_empty() {
+            format!("Embedded meta data into {written} images")
+        } else {
+            format!(
+                "Embedded meta data into {written} of {} images, could not write:\n{}",
+                embeddings.len(),
+                errors.join("\n")
+            )
+        }
+    }
+
+    fn add_exif(ui: &mut Ui, exif: &Exif) {
+        Grid::new("exif_grid").num_columns(2).show(ui, |ui| {
+            let mut row = |name: &str, value: Option<String>| {
+                if let Some(value) = value {
+                    ui.label(name);
+                    ui.label(value);
+                    ui.end_row();
+                }
+            };
+            row(
+                "Date Taken",
+                exif.date_taken.as_ref().map(|date| date.replace('T', " ")),
+            );
+            row("Camera", exif.camera.clone());
+            row("Lens", exif.lens.clone());
+            row(
+                "Exposure",
+                exif.exposure_time.as_ref().map(|time| format!("{time} s")),
+            );
+            row(
+                "Aperture",
+                exif.f_number.map(|f_number| format!("f/{f_number:.1}")),
+            );
+            row("ISO", exif.iso.map(|iso| iso.to_string()));
+            row(
+                "Focal Length",
+                exif.focal_length.map(|length| format!("{length:.0} mm")),
+            );
+            row(
+                "GPS",
+                exif.coordinates()
+                    .map(|(latitude, longitude)| format!("{latitude:.6}, {longitude:.6}")),
+            );
+        });
+    }
+
+    /// Returns the EXIF data of the current image, reading it only when the image changed.
+    ///
+    /// Stores the EXIF data in the meta data if enabled.
+    fn current_exif(&mut self) -> std::result::Result<Option<Exif>, String> {
+        let Some(path) = self.images.current_image_path().cloned() else {
+            return Ok(None);
+        };
+        let exif = match &self.info_exif {
+            Some((exif_path, exif)) if *exif_path == path => exif.clone(),
+            _ => {
+                let exif = images::read_exif(&path).map_err(|error| error.to_string());
+                self.info_exif = Some((path.clone(), exif.clone()));
+                exif
+            }
+        }?;
+
+        if let Some(exif) = exif.as_ref().filter(|_| self.store_exif) {
+            self.meta
+                .edit_file(&self.meta_current_folder, &path, |file| {
+                    if file.exif.as_ref() != Some(exif) {
+                        file.exif = Some(exif.clone());
+                    }
+                })
+                .map_err(|error| error.to_string())?;
+        }
+        Ok(exif)
+    }
+
+    /// Reads the EXIF data of all images in parallel and stores it in the meta data.
+    ///
+    /// Images with unreadable EXIF data are skipped.
+    fn store_exif_of_all_images(&mut self) -> Result<usize> {
+        let found: Vec<_> = self
+            .images
+            .all_paths()
+            .par_iter()
+            .filter_map(|path| {
+                let exif = images::read_exif(path).ok().flatten()?;
+                Some((path.clone(), exif))
+            })
+            .collect();
+
+        for (path, exif) in &found {
+            self.meta
+                .edit_file(&self.meta_current_folder, path, |file| {
+                    file.exif = Some(exif.clone())
+                })?;
+        }
+        Ok(found.len())
+    }
+
+    /// Writes an XMP sidecar for every image that has meta data.
+    ///
+    /// Returns the number of written sidecars and of skipped sidecars of other applications.
+    fn write_xmp_of_all_images(&mut self) -> Result<(usize, usize)> {
+        let (mut count, mut skipped) = (0, 0);
+        for path in self.images.all_paths() {
+            match self.meta.export_xmp(path) {
+                Ok(Some(_)) => count += 1,
+                Ok(None) => {}
+                Err(meta::Error::ForeignSidecar(_)) => skipped += 1,
+                Err(error) => return Err(error.into()),
+            }
+        }
+        Ok((count, skipped))
+    }
+
+    /// Merges the XMP sidecars of all images into their meta data.
+    fn read_xmp_of_all_images(&mut self) -> Result<usize> {
+        let mut count = 0;
+        for path in self.images.all_paths() {
+            if self.meta.import_xmp(&self.meta_current_folder, path)? {
+                count += 1;
+            }
+        }
+
+        // Captions and untagged images may have changed.
+        self.caption_path = None;
+        self.update_untagged_count();
+        Ok(count)
+    }
+
+    fn import_takeout(&mut self) -> Result<TakeoutImport> {
+        let import = self.meta.import_takeout(&self.meta_current_folder)?;
+        for path in &import.unmatched {
+            eprintln!("Found no image for Google Takeout file {}", path.display());
+        }
+        self.caption_path = None;
+        self.update_untagged_count();
+        Ok(import)
+    }
+}
@@ -1,14 +1,54 @@
-use std::{borrow::Cow, cmp::Ordering};
+use std::{
+    borrow::Cow,
+    cmp::Ordering,
+    fs::File,
+    io::BufWriter,
+    path::{Path, PathBuf},
+    sync::Arc,
+    thread,
+};
 
 use approximate_string_matcher::compare;
+use crossbeam_channel::TryRecvError;
 use eframe::egui::{Context, Key};
-use meta::model::PersonId;
+use meta::{
+    export::Format,
+    model::{Album, AlbumId, ColorLabel, Flag, MetaFile, PersonId, SmartAlbum, SmartAlbumId},
+    query::Query,
+};
 
-use super::{FileManagerApp, MetaOption};
-use crate::Result;
+use super::{FileManagerApp, MetaKind, MetaOption};
+use crate::{
+    gallery,
+    images::{self, Dates, SortOrder},
+    Error, Result,
+};
 
 impl FileManagerApp {
-    pub(crate) fn open_meta_window(&mut self) {
+    /// Keys that set the rating while holding Ctrl (Cmd on Mac), indexed by rating.
+    const RATING_KEYS: [Key; 6] = [
+        Key::Num0,
+        Key::Num1,
+        Key::Num2,
+        Key::Num3,
+        Key::Num4,
+        Key::Num5,
+    ];
+    const FLAG_KEYS: [(Key, Flag); 3] = [
+        (Key::F, Flag::Favourite),
+        (Key::P, Flag::Pick),
+        (Key::X, Flag::Reject),
+    ];
+    const LABEL_KEYS: [(Key, ColorLabel); 5] = [
+        (Key::Num6, ColorLabel::Red),
+        (Key::Num7, ColorLabel::Yellow),
+        (Key::Num8, ColorLabel::Green),
+        (Key::Num9, ColorLabel::Blue),
+        (Key::Num0, ColorLabel::Purple),
+    ];
+
+    pub(crate) fn open_meta_window(&mut self, kind: MetaKind) {
+        self.meta_kind = kind;
         self.meta_search = String::new();
         // TODO: Populate with commonly used options.
         self.meta_options = vec![MetaOption::Create];
@@ -19,7 +59,8 @@ impl FileManagerApp {
         let root_path = self
             .meta
             .root_folders()
-            .root_folder(&self.meta_current_folder);
+            .root_folder(&self.meta_current_folder)
+            .cloned();
         self.images
             .current_image_path()
             .map_or("No File".into(), |path| {
@@ -30,18 +71,552 @@ impl FileManagerApp {
             })
     }
 
+    /// Restricts the browsed images to the ones matching the query in the search bar.
+    pub(crate) fn apply_filter(&mut self) -> Result<()> {
+        self.browsed_album = None;
+        let search = self.filter_search.trim();
+        if search.is_empty() {
+            self.images.remove_filter();
+            return Ok(());
+        }
+
+        let query = Query::parse(search)?;
+        self.meta.index(&self.meta_current_folder)?;
+        let meta = &self.meta;
+        let tokens = query.text_tokens();
+        if tokens.is_empty() {
+            self.images.set_filter(|path| meta.matches(&query, path));
+        } else {
+            // Browse matching images by their rank when searching captions, keeping the
+            // browse order for images with the same rank.
+            let mut ranked: Vec<_> = self
+                .images
+                .all_paths()
+                .iter()
+                .filter(|path| meta.matches(&query, path))
+                .map(|path| (meta.caption_score(&tokens, path), path.clone()))
+                .collect();
+            ranked.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+            self.images
+                .set_sequence(ranked.into_iter().map(|(_, path)| path).collect());
+        }
+        Ok(())
+    }
+
+    /// Uses the query of the smart album as filter for the browsed images.
+    pub(crate) fn show_smart_album(&mut self, id: &SmartAlbumId) -> Result<()> {
+        let smart_albums = self.meta.smart_albums();
+        let Some(album) = smart_albums.album(id) else {
+            return Ok(());
+        };
+        self.filter_search = album.query.clone();
+        self.filter_error = None;
+        self.apply_filter()
+    }
+
+    pub(crate) fn save_filter_as_smart_album(&mut self) -> Result<()> {
+        let query = self.filter_search.trim().to_string();
+        Query::parse(&query)?;
+        let name = std::mem::take(&mut self.album_name).trim().to_string();
+        self.meta
+            .edit_smart_albums(|smart_albums| smart_albums.add(SmartAlbum::new(name, query)));
+        Ok(())
+    }
+
+    /// Writes the files of the smart album in the export format into the current root folder.
+    pub(crate) fn export_smart_album(&mut self, id: &SmartAlbumId) -> Result<PathBuf> {
+        let name = self
+            .meta
+            .smart_albums()
+            .album(id)
+            .ok_or(meta::Error::InvalidSmartAlbum)?
+            .name
+            .replace(|c: char| !c.is_alphanumeric() && c != ' ' && c != '-', "_");
+        let paths = self
+            .meta
+            .evaluate_smart_album(id, &[self.meta_current_folder])?;
+        self.export(&name, &paths)
+    }
+
+    /// Writes all files with meta data in the export format into the current root folder.
+    pub(crate) fn export_all_files(&mut self) -> Result<PathBuf> {
+        let paths = self.meta.query(&Query::All, &[self.meta_current_folder])?;
+        self.export("All Files", &paths)
+    }
+
+    /// Writes the files of each person as CSV table `Persons.csv` into the current root
+    /// folder, failing if that file already exists.
+    pub(crate) fn export_person_report(&mut self) -> Result<PathBuf> {
+        let paths = self.meta.query(&Query::All, &[self.meta_current_folder])?;
+        let rows = self.meta.report(&paths)?;
+        let path = self.current_root_folder()?.join("Persons.csv");
+        let file = create_new_file(&path)?;
+        meta::export::write_person_report(&rows, BufWriter::new(file))?;
+        Ok(path)
+    }
+
+    /// Writes a static HTML gallery of the browsed images into the gallery folder
+    /// in the background, see [Self::receive_gallery_export].
+    pub(crate) fn export_gallery(&mut self, ctx: &Context) {
+        let paths: Vec<_> = self.images.paths().cloned().collect();
+        let title = match self.browsed_album {
+            Some(id) => self
+                .meta
+                .albums()
+                .album(&id)
+                .map(|album| album.title.clone()),
+            None => Some(self.filter_search.trim().to_string()).filter(|query| !query.is_empty()),
+        }
+        .unwrap_or_else(|| "Gallery".into());
+        let folder = PathBuf::from(self.gallery_folder.trim());
+        let meta = self.meta.clone();
+        let ctx = ctx.clone();
+        let (sender, receiver) = crossbeam_channel::bounded(1);
+        thread::spawn(move || {
+            // Errors are not sendable between threads, so only their messages are passed on.
+            let result = gallery::export(&meta, &paths, &folder, &title);
+            let _ = sender.send(result.map_err(|error| error.to_string()));
+            ctx.request_repaint();
+        });
+        self.gallery_export = Some(receiver);
+        self.album_status = Some("Exporting gallery…".into());
+    }
+
+    /// Shows the result of the gallery export once it is done.
+    pub(crate) fn receive_gallery_export(&mut self) {
+        let Some(receiver) = &self.gallery_export else {
+            return;
+        };
+        let result = match receiver.try_recv() {
+            Ok(result) => result,
+            Err(TryRecvError::Empty) => return,
+            Err(TryRecvError::Disconnected) => Err("gallery export stopped unexpectedly".into()),
+        };
+        self.gallery_export = None;
+        self.album_status = Some(match result {
+            Ok(path) => format!("Exported to {}", path.display()),
+            Err(error) => error,
+        });
+    }
+
+    /// Indexes the meta data of all root folders in the background, so queries of the whole
+    /// library do not have to read all folder data, see [Self::receive_index].
+    pub(crate) fn index_all(&mut self, ctx: &Context) {
+        let meta = self.meta.clone();
+        let ctx = ctx.clone();
+        let (sender, receiver) = crossbeam_channel::bounded(1);
+        thread::spawn(move || {
+            // Errors are not sendable between threads, so only their messages are passed on.
+            let _ = sender.send(meta.index_all().map_err(|error| error.to_string()));
+            ctx.request_repaint();
+        });
+        self.indexing = Some(receiver);
+    }
+
+    /// Shows the error of indexing once it is done.
+    pub(crate) fn receive_index(&mut self) {
+        let Some(receiver) = &self.indexing else {
+            return;
+        };
+        let result = match receiver.try_recv() {
+            Ok(result) => result,
+            Err(TryRecvError::Empty) => return,
+            Err(TryRecvError::Disconnected) => Err("indexing stopped unexpectedly".into()),
+        };
+        self.indexing = None;
+        if let Err(error) = result {
+            self.main_error = Some(format!("Could not index the meta data: {error}"));
+        }
+    }
+
+    /// Returns the dates taken of all images, or `None` while they are read in the background.
+    pub(crate) fn dates(&mut self, ctx: &Context) -> Option<Arc<Dates>> {
+        if self.dates.is_none() && self.dates_loading.is_none() {
+            let meta = self.meta.clone();
+            let paths = self.images.all_paths().to_vec();
+            let ctx = ctx.clone();
+            let (sender, receiver) = crossbeam_channel::bounded(1);
+            thread::spawn(move || {
+                let _ = sender.send(images::dates_taken(&meta, &paths));
+                ctx.request_repaint();
+            });
+            self.dates_loading = Some(receiver);
+        }
+        self.dates.clone()
+    }
+
+    /// Stores the dates taken once they are read and sorts the images if sorted by date.
+    pub(crate) fn receive_dates(&mut self) {
+        let Some(receiver) = &self.dates_loading else {
+            return;
+        };
+        let dates = match receiver.try_recv() {
+            Ok(dates) => dates,
+            Err(TryRecvError::Empty) => return,
+            Err(TryRecvError::Disconnected) => Dates::new(),
+        };
+        if self.sort_order == SortOrder::DateTaken {
+            self.images.sort(self.sort_order, self.sort_reverse, &dates);
+        }
+        self.dates_loading = None;
+        self.dates = Some(Arc::new(dates));
+    }
+
+    /// Forgets the dates taken and the timeline, so they are read again when needed.
+    pub(crate) fn reset_dates(&mut self) {
+        self.dates = None;
+        self.dates_loading = None;
+        self.timeline = None;
+    }
+
+    /// Sorts the images in the selected order. Sorting by date waits for the dates taken.
+    pub(crate) fn sort_images(&mut self, ctx: &Context) {
+        let dates = match self.sort_order {
+            SortOrder::DateTaken => match self.dates(ctx) {
+                Some(dates) => dates,
+                None => return,
+            },
+            _ => Default::default(),
+        };
+        self.images.sort(self.sort_order, self.sort_reverse, &dates);
+    }
+
+    /// Writes the files in the export format as `{name}.{extension}` into the current root
+    /// folder, failing if that file already exists.
+    fn export(&mut self, name: &str, paths: &[PathBuf]) -> Result<PathBuf> {
+        let path = self
+            .current_root_folder()?
+            .join(format!("{name}.{}", self.export_format.extension()));
+        let file = create_new_file(&path)?;
+        if self.export_format == Format::FileList {
+            meta::export::write_file_list(paths, BufWriter::new(file))?;
+        } else {
+            let rows = self.meta.report(paths)?;
+            meta::export::write_report(&rows, self.export_format, BufWriter::new(file))?;
+        }
+        Ok(path)
+    }
+
+    pub(crate) fn current_root_folder(&self) -> Result<PathBuf> {
+        Ok(self
+            .meta
+            .root_folders()
+            .root_folder(&self.meta_current_folder)
+            .ok_or(meta::Error::InvalidRootFolder)?
+            .clone())
+    }
+
+    /// Browses the files of the album in their custom order.
+    pub(crate) fn browse_album(&mut self, id: &AlbumId) -> Result<()> {
+        let sequence = self.meta.resolve_album(id)?;
+        self.images.set_sequence(sequence);
+        self.browsed_album = Some(*id);
+        Ok(())
+    }
+
+    pub(crate) fn stop_browsing_album(&mut self) -> Result<()> {
+        self.apply_filter()
+    }
+
+    pub(crate) fn add_current_image_to_album(&mut self, id: &AlbumId) -> Result<()> {
+        let Some(image_path) = self.images.current_image_path() else {
+            return Ok(());
+        };
+        let file = self
+            .meta
+            .file_reference(&self.meta_current_folder, image_path)?;
+        self.meta.edit_albums(|albums| {
+            albums
+                .album_mut(id)
+                .ok_or(meta::Error::InvalidAlbum)
+                .map(|album| album.add(file))
+        })?;
+        if self.browsed_album == Some(*id) {
+            self.browse_album(id)?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn set_current_image_as_album_cover(&mut self, id: &AlbumId) -> Result<()> {
+        let Some(image_path) = self.images.current_image_path() else {
+            return Ok(());
+        };
+        let file = self
+            .meta
+            .file_reference(&self.meta_current_folder, image_path)?;
+        self.meta.edit_albums(|albums| {
+            albums
+                .album_mut(id)
+                .ok_or(meta::Error::InvalidAlbum)
+                .map(|album| album.cover = Some(file))
+        })?;
+        Ok(())
+    }
+
+    /// Moves the current image by `step` positions inside of the browsed album.
+    pub(crate) fn move_current_image_in_album(&mut self, step: isize) -> Result<()> {
+        let (Some(id), Some(image_path)) = (self.browsed_album, self.images.current_image_path())
+        else {
+            return Ok(());
+        };
+        let file = self
+            .meta
+            .file_reference(&self.meta_current_folder, image_path)?;
+        self.meta.edit_albums(|albums| {
+            let album = albums.album_mut(&id).ok_or(meta::Error::InvalidAlbum)?;
+            if let Some(from) = album.position(&file) {
+                let to = (from as isize + step).clamp(0, album.files.len() as isize - 1);
+                album.move_file(from, to as usize);
+            }
+            Ok::<_, meta::Error>(())
+        })?;
+        self.browse_album(&id)
+    }
+
+    pub(crate) fn remove_current_image_from_album(&mut self) -> Result<()> {
+        let (Some(id), Some(image_path)) = (self.browsed_album, self.images.current_image_path())
+        else {
+            return Ok(());
+        };
+        let file = self
+            .meta
+            .file_reference(&self.meta_current_folder, image_path)?;
+        self.meta.edit_albums(|albums| {
+            albums
+                .album_mut(&id)
+                .ok_or(meta::Error::InvalidAlbum)
+                .map(|album| album.remove(&file))
+        })?;
+        self.browse_album(&id)
+    }
+
+    /// Applies `edit` to the meta data of the current image.
+    pub(crate) fn edit_current_file(&mut self, edit: impl FnOnce(&mut MetaFile)) -> Result<()> {
+        let Some(image_path) = self.images.current_image_path() else {
+            return Ok(());
+        };
+        self.meta
+            .edit_file(&self.meta_current_folder, image_path, edit)?;
+        Ok(())
+    }
+
+    /// Sets the rating of the current image or removes it if the image already has this rating.
+    pub(crate) fn toggle_rating(&mut self, rating: u8) -> Result<()> {
+        self.edit_current_file(|file| {
+            file.rating = (file.rating != Some(rating)).then_some(rating);
+        })
+    }
+
+    pub(crate) fn toggle_flag(&mut self, flag: Flag) -> Result<()> {
+        self.edit_current_file(|file| {
+            file.flag = (file.flag != Some(flag)).then_some(flag);
+        })
+    }
+
+    pub(crate) fn toggle_label(&mut self, label: ColorLabel) -> Result<()> {
+        self.edit_current_file(|file| {
+            file.label = (file.label != Some(label)).then_some(label);
+        })
+    }
+
+    /// Loads the caption of the current image into the caption editor if the image changed.
+    pub(crate) fn load_caption(&mut self) -> Result<()> {
+        let path = self.images.current_image_path().cloned();
+        if path == self.caption_path {
+            return Ok(());
+        }
+
+        self.caption = match &path {
+            Some(path) => self
+                .meta
+                .load_file(path)?
+                .and_then(|file| file.caption.clone())
+                .unwrap_or_default(),
+            None => String::new(),
+        };
+        self.caption_path = path;
+        Ok(())
+    }
+
+    pub(crate) fn save_caption(&mut self) -> Result<()> {
+        if self.caption_path.as_ref() != self.images.current_image_path() {
+            return Ok(());
+        }
+        let caption = self.caption.trim();
+        let caption = (!caption.is_empty()).then(|| caption.to_string());
+        self.edit_current_file(|file| file.caption = caption)
+    }
+
+    /// Short description of the rating, flag and label of the current image.
+    pub(crate) fn current_file_summary(&mut self) -> Result<String> {
+        let Some(image_path) = self.images.current_image_path() else {
+            return Ok(String::new());
+        };
+        let Some(file) = self.meta.load_file(image_path)? else {
+            return Ok(String::new());
+        };
+
+        let mut summary = Vec::new();
+        if let Some(rating) = file.rating {
+            summary.push(
+                (0..MetaFile::MAX_RATING)
+                    .map(|star| if star < rating { '★' } else { '☆' })
+                    .collect(),
+            );
+        }
+        if let Some(flag) = file.flag {
+            summary.push(flag.name().to_string());
+        }
+        if let Some(label) = file.label {
+            summary.push(label.name().to_string());
+        }
+        Ok(summary.join(" · "))
+    }
+
+    pub(crate) fn next_image(&mut self) {
+        if self.triage_mode {
+            let meta = &self.meta;
+            self.images.forward_to(|path| Self::is_untagged(meta, path));
+        } else {
+            self.images.forward();
+        }
+    }
+
+    pub(crate) fn previous_image(&mut self) {
+        if self.triage_mode {
+            let meta = &self.meta;
+            self.images.back_to(|path| Self::is_untagged(meta, path));
+        } else {
+            self.images.back();
+        }
+    }
+
+    /// In triage mode only images without persons and tags are browsed.
+    /// Switches the triage mode and shows the error if the root folder cannot be indexed.
+    pub(crate) fn toggle_triage_mode(&mut self) {
+        let result = self.set_triage_mode(!self.triage_mode);
+        self.show_main_error(result);
+    }
+
+    /// Shows the error of an action in the main view, or clears the previous one.
+    pub(crate) fn show_main_error(&mut self, result: Result<()>) {
+        self.main_error = result.err().map(|error| error.to_string());
+    }
+
+    fn set_triage_mode(&mut self, triage_mode: bool) -> Result<()> {
+        if !triage_mode {
+            self.triage_mode = false;
+            return Ok(());
+        }
+
+        self.meta.index(&self.meta_current_folder)?;
+        self.triage_mode = true;
+        self.update_untagged_count();
+        let meta = &self.meta;
+        let current_is_tagged = self
+            .images
+            .current_image_path()
+            .is_some_and(|path| !Self::is_untagged(meta, path));
+        if current_is_tagged {
+            self.next_image();
+        }
+        Ok(())
+    }
+
+    pub(crate) fn update_untagged_count(&mut self) {
+        self.untagged_count = self
+            .images
+            .all_paths()
+            .iter()
+            .filter(|path| Self::is_untagged(&self.meta, path))
+            .count();
+    }
+
+    fn is_untagged(meta: &meta::Repository, path: &Path) -> bool {
+        meta.read_file(path, |file| file.persons.is_empty() && file.tags.is_empty())
+            .unwrap_or(true)
+    }
+
     pub(crate) fn main_view_handle_input(&mut self, ctx: &Context) {
         if ctx.input(|input| input.key_pressed(Key::ArrowRight) || input.key_pressed(Key::PageDown))
         {
-            self.images.forward();
+            self.next_image();
         }
 
         if ctx.input(|input| input.key_pressed(Key::ArrowLeft) || input.key_pressed(Key::PageUp)) {
-            self.images.back();
+            self.previous_image();
+        }
+
+        if ctx.input(|input| input.modifiers.command) {
+            let rating = ctx.input(|input| {
+                Self::RATING_KEYS
+                    .iter()
+                    .position(|key| input.key_pressed(*key))
+            });
+            if let Some(rating) = rating {
+                let result = self.toggle_rating(rating as u8);
+                self.show_main_error(result);
+            }
+            return;
+        }
+
+        let flag = ctx.input(|input| {
+            Self::FLAG_KEYS
+                .iter()
+                .find(|(key, _)| input.key_pressed(*key))
+                .map(|(_, flag)| *flag)
+        });
+        if let Some(flag) = flag {
+            let result = self.toggle_flag(flag);
+            self.show_main_error(result);
+        }
+
+        if ctx.input(|input| input.key_pressed(Key::U)) {
+            let result = self.edit_current_file(|file| file.flag = None);
+            self.show_main_error(result);
+        }
+
+        let label = ctx.input(|input| {
+            Self::LABEL_KEYS
+                .iter()
+                .find(|(key, _)| input.key_pressed(*key))
+                .map(|(_, label)| *label)
+        });
+        if let Some(label) = label {
+            let result = self.toggle_label(label);
+            self.show_main_error(result);
         }
 
         if ctx.input(|input| input.key_pressed(Key::Num1)) {
-            self.open_meta_window();
+            self.open_meta_window(MetaKind::Persons);
+        }
+
+        if ctx.input(|input| input.key_pressed(Key::Num2)) {
+            self.album_window_open = true;
+        }
+
+        if ctx.input(|input| input.key_pressed(Key::Num3)) {
+            self.open_meta_window(MetaKind::Albums);
+        }
+
+        if ctx.input(|input| input.key_pressed(Key::R)) {
+            self.show_regions = !self.show_regions;
+        }
+
+        if ctx.input(|input| input.key_pressed(Key::L)) {
+            self.timeline_open = !self.timeline_open;
+        }
+
+        if ctx.input(|input| input.key_pressed(Key::I)) {
+            self.info_panel_open = !self.info_panel_open;
+        }
+
+        if ctx.input(|input| input.key_pressed(Key::C)) {
+            self.caption_focus_requested = true;
+        }
+
+        if ctx.input(|input| input.key_pressed(Key::T)) {
+            self.toggle_triage_mode();
         }
     }
 
@@ -50,9 +625,12 @@ impl FileManagerApp {
         let enter = ctx.input(|input| input.key_pressed(Key::Enter));
         if escape || enter {
             self.meta_window_open = false;
+            if escape {
+                self.pending_region = None;
+            }
             if enter {
-                self.meta_handle_confirm(self.meta_selected_option as usize)
-                    .unwrap(); // TODO: Improve error handling.
+                let result = self.meta_handle_confirm(self.meta_selected_option as usize);
+                self.show_main_error(result);
             }
             return;
         }
@@ -70,17 +648,50 @@ impl FileManagerApp {
 
     pub(crate) fn meta_handle_confirm(&mut self, option_index: usize) -> Result<()> {
         self.meta_window_open = false;
+        let pending_region = self.pending_region.take();
         let option = &self.meta_options[option_index];
-        match option {
-            MetaOption::Create => todo!(),
-            MetaOption::MatchResult(_, id) => {
+        match (self.meta_kind, option) {
+            (MetaKind::Albums, MetaOption::Create) => {
+                let title = self.meta_search.trim();
+                if !title.is_empty() {
+                    let id = self
+                        .meta
+                        .edit_albums(|albums| albums.add(Album::new(title.to_string())));
+                    self.add_current_image_to_album(&id)?;
+                }
+            }
+            (MetaKind::Albums, MetaOption::MatchResult(_, id)) => {
+                self.add_current_image_to_album(&AlbumId(*id))?;
+            }
+            (MetaKind::Persons, option) => {
+                let id = match option {
+                    MetaOption::Create => {
+                        let name = self.meta_search.trim();
+                        if name.is_empty() {
+                            return Ok(());
+                        }
+                        self.meta
+                            .edit_persons(|persons| persons.get_or_create(name))
+                    }
+                    MetaOption::MatchResult(_, id) => PersonId(*id),
+                };
                 let Some(image_path) = self.images.current_image_path() else {
                     return Ok(());
                 };
                 self.meta
-                    .load_or_create_file(&self.meta_current_folder, image_path)?
-                    .persons
-                    .insert(PersonId(*id));
+                    .edit_file(
+                        &self.meta_current_folder,
+                        image_path,
+                        |file| match pending_region {
+                            Some(region) => file.add_person_region(id, region),
+                            None => {
+                                file.persons.insert(id);
+                            }
+                        },
+                    )?;
+                if self.triage_mode {
+                    self.update_untagged_count();
+                }
             }
         }
 
@@ -90,19 +701,31 @@ impl FileManagerApp {
     pub(crate) fn update_meta_options(&mut self) {
         let search = self.meta_search.trim();
         self.meta_selected_option = 0;
-        self.meta_options = std::iter::once(MetaOption::Create)
-            .chain(
-                self.meta
-                    .persons()
-                    .entries()
-                    .iter()
-                    .filter_map(|(id, person)| {
-                        compare(search, &person.name)
-                            .map(|result| MetaOption::MatchResult(result, id.0))
-                    })
-                    .take(10),
-            )
-            .collect();
+        let matches: Vec<_> = match self.meta_kind {
+            MetaKind::Persons => self
+                .meta
+                .persons()
+                .entries()
+                .iter()
+                .filter_map(|(id, person)| {
+                    compare(search, &person.name)
+                        .map(|result| MetaOption::MatchResult(result, id.0))
+                })
+                .take(10)
+                .collect(),
+            MetaKind::Albums => self
+                .meta
+                .albums()
+                .entries()
+                .iter()
+                .filter_map(|(id, album)| {
+                    compare(search, &album.title)
+                        .map(|result| MetaOption::MatchResult(result, id.0))
+                })
+                .take(10)
+                .collect(),
+        };
+        self.meta_options = std::iter::once(MetaOption::Create).chain(matches).collect();
 
         self.meta_options.sort_by(|a, b| match (a, b) {
             (MetaOption::Create, MetaOption::Create) => Ordering::Equal,
@@ -114,3 +737,15 @@ impl FileManagerApp {
         })
     }
 }
+
+/// Creates the file for an export, refusing to overwrite an existing file.
+fn create_new_file(path: &Path) -> Result<File> {
+    File::options()
+        .write(true)
+        .create_new(true)
+        .open(path)
+        .map_err(|error| match error.kind() {
+            std::io::ErrorKind::AlreadyExists => Error::FileExists(path.to_path_buf()),
+            _ => Error::WriteFile(error),
+        })
+}
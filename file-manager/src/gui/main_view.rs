@@ -1,14 +1,23 @@
 use eframe::{
-    egui::{CentralPanel, Context, Image, TopBottomPanel, Ui},
-    epaint::vec2,
+    egui::{
+        CentralPanel, ComboBox, Context, Image, Key, Response, Sense, SidePanel, TextEdit,
+        TopBottomPanel, Ui,
+    },
+    emath::Align2,
+    epaint::{vec2, Color32, FontId, Pos2, Rect, Stroke},
 };
 use egui_extras::{Size, StripBuilder};
 
-use super::FileManagerApp;
+use super::{FileManagerApp, MetaKind};
+use crate::images::SortOrder;
+use meta::model::Region;
 
 impl FileManagerApp {
+    /// Minimal relative width and height of a region drawn on an image.
+    const MIN_REGION_SIZE: f32 = 0.01;
+
     pub(crate) fn update_main_view(&mut self, ctx: &Context) {
-        if !self.meta_window_open {
+        if !self.meta_window_open && !ctx.wants_keyboard_input() {
             self.main_view_handle_input(ctx);
         }
 
@@ -16,6 +25,18 @@ impl FileManagerApp {
             self.top_panel(ui);
         });
 
+        if self.timeline_open {
+            SidePanel::left(eframe::egui::Id::new("timeline_panel")).show(ctx, |ui| {
+                self.timeline_panel(ui);
+            });
+        }
+
+        if self.info_panel_open {
+            SidePanel::right(eframe::egui::Id::new("info_panel")).show(ctx, |ui| {
+                self.info_panel(ui);
+            });
+        }
+
         CentralPanel::default().show(ctx, |ui| {
             StripBuilder::new(ui)
                 .size(Size::remainder())
@@ -27,6 +48,10 @@ impl FileManagerApp {
         TopBottomPanel::bottom(eframe::egui::Id::new("bottom_panel")).show(ctx, |ui| {
             self.bottom_panel(ui);
         });
+
+        TopBottomPanel::bottom(eframe::egui::Id::new("caption_panel")).show(ctx, |ui| {
+            self.caption_panel(ui);
+        });
     }
 
     fn top_panel(&mut self, ui: &mut Ui) {
@@ -35,16 +60,164 @@ impl FileManagerApp {
                 .button("People")
                 .on_hover_text("Add or Remove People (Hotkey: 1)");
             if people.clicked() {
-                self.open_meta_window();
+                self.open_meta_window(MetaKind::Persons);
             }
 
             // TODO: Add more meta types.
             let _ = ui
                 .button("Events")
                 .on_hover_text("Add or Remove People (Hotkey: 1)");
+
+            let albums = ui
+                .button("Albums")
+                .on_hover_text("Browse, Save or Export Albums (Hotkey: 2)");
+            if albums.clicked() {
+                self.album_window_open = true;
+            }
+
+            let add_to_album = ui
+                .button("Add to Album")
+                .on_hover_text("Add Image to an Album (Hotkey: 3)");
+            if add_to_album.clicked() {
+                self.open_meta_window(MetaKind::Albums);
+            }
+
+            let rename = ui
+                .button("Rename")
+                .on_hover_text("Rename and Reorganise the Browsed Images by a Template");
+            if rename.clicked() {
+                self.rename_window_open = true;
+            }
+
+            let triage = ui
+                .selectable_label(self.triage_mode, "Untagged Only")
+                .on_hover_text("Only Browse Images without People or Tags (Hotkey: T)");
+            if triage.clicked() {
+                self.toggle_triage_mode();
+            }
+
+            let regions = ui
+                .selectable_label(self.show_regions, "Regions")
+                .on_hover_text(
+                    "Show Marked People (Hotkey: R)\nDraw on the Image to Mark a Person\n\
+                     Right-Click a Region to Remove It",
+                );
+            if regions.clicked() {
+                self.show_regions = !self.show_regions;
+            }
+
+            let timeline = ui
+                .selectable_label(self.timeline_open, "Timeline")
+                .on_hover_text("Navigate by Date Taken (Hotkey: L)");
+            if timeline.clicked() {
+                self.timeline_open = !self.timeline_open;
+            }
+
+            let info = ui
+                .selectable_label(self.info_panel_open, "Info")
+                .on_hover_text("Show EXIF Data (Hotkey: I)");
+            if info.clicked() {
+                self.info_panel_open = !self.info_panel_open;
+            }
+
+            ui.separator();
+            self.sort_selection(ui);
+
+            ui.separator();
+            self.filter_bar(ui);
+
+            if let Some(error) = &self.main_error {
+                ui.separator();
+                ui.colored_label(Color32::RED, error);
+            }
         });
     }
 
+    fn sort_selection(&mut self, ui: &mut Ui) {
+        let mut order = self.sort_order;
+        let mut reverse = self.sort_reverse;
+        let combo_box = ComboBox::from_id_source("sort_order")
+            .selected_text(order.name())
+            .show_ui(ui, |ui| {
+                SortOrder::ALL
+                    .iter()
+                    .map(|option| ui.selectable_value(&mut order, *option, option.name()))
+                    .reduce(|a, b| a | b)
+            });
+        let reverse_changed = ui
+            .checkbox(&mut reverse, "Reverse")
+            .on_hover_text("Browse in Reverse Order")
+            .changed();
+
+        // Selecting random again shuffles again.
+        let selected = combo_box
+            .inner
+            .flatten()
+            .is_some_and(|response| response.clicked());
+        if selected || reverse_changed {
+            self.sort_order = order;
+            self.sort_reverse = reverse;
+            self.sort_images(ui.ctx());
+        }
+        if self.dates_loading.is_some() && self.sort_order == SortOrder::DateTaken {
+            ui.spinner().on_hover_text("Reading Dates Taken");
+        }
+    }
+
+    fn filter_bar(&mut self, ui: &mut Ui) {
+        let search = ui
+            .add(
+                TextEdit::singleline(&mut self.filter_search)
+                    .hint_text("Filter, e.g. person:Anna and not tag:location"),
+            )
+            .on_hover_text("Only browse images matching the query (Confirm: Enter)");
+        let confirmed = search.lost_focus() && ui.input(|input| input.key_pressed(Key::Enter));
+
+        let clear = ui.button("Clear").on_hover_text("Browse all images");
+        if clear.clicked() {
+            self.filter_search.clear();
+        }
+
+        if confirmed || clear.clicked() {
+            self.filter_error = self.apply_filter().err().map(|error| error.to_string());
+        }
+
+        if let Some(error) = &self.filter_error {
+            ui.colored_label(Color32::RED, error);
+        } else if self.images.len() != self.images.total_len() {
+            ui.label(format!(
+                "{} of {} images",
+                self.images.len(),
+                self.images.total_len()
+            ));
+        }
+    }
+
+    fn caption_panel(&mut self, ui: &mut Ui) {
+        if let Err(error) = self.load_caption() {
+            // TODO: Improve error handling.
+            eprintln!("Encountered error while loading caption: {error}");
+        }
+
+        let enabled = self.caption_path.is_some();
+        let caption = ui
+            .add_enabled(
+                enabled,
+                TextEdit::singleline(&mut self.caption)
+                    .hint_text("Caption")
+                    .desired_width(f32::INFINITY),
+            )
+            .on_hover_text("Describe the Image (Hotkey: C)");
+        if self.caption_focus_requested {
+            caption.request_focus();
+            self.caption_focus_requested = false;
+        }
+        if caption.changed() {
+            let result = self.save_caption();
+            self.show_main_error(result);
+        }
+    }
+
     fn bottom_panel(&mut self, ui: &mut Ui) {
         ui.horizontal_centered(|ui| {
             StripBuilder::new(ui)
@@ -58,13 +231,34 @@ impl FileManagerApp {
                             .on_hover_text("Navigate to Previous Image (Hotkey: Left)");
 
                         if left_button.clicked() {
-                            self.images.back();
+                            self.previous_image();
                         }
                     });
                     strip.cell(|ui| {
                         ui.vertical_centered_justified(|ui| {
-                            let file_name = self.current_file_name();
-                            ui.label(file_name);
+                            let mut label = self.current_file_name().into_owned();
+                            match self.current_file_summary() {
+                                Ok(summary) if !summary.is_empty() => {
+                                    label = format!("{label} · {summary}");
+                                }
+                                Ok(_) => {}
+                                Err(error) => {
+                                    // TODO: Improve error handling.
+                                    eprintln!("Encountered error while loading meta data: {error}");
+                                }
+                            }
+                            if self.triage_mode {
+                                label = format!(
+                                    "{label} ({} of {} images untagged)",
+                                    self.untagged_count,
+                                    self.images.total_len()
+                                );
+                            }
+                            ui.label(label).on_hover_text(
+                                "Rating (Hotkey: Ctrl+0 to Ctrl+5)\n\
+                                 Favourite, Pick, Reject, Unflag (Hotkey: F, P, X, U)\n\
+                                 Red, Yellow, Green, Blue, Purple Label (Hotkey: 6 to 9, 0)",
+                            );
                         });
                     });
                     strip.cell(|ui| {
@@ -72,7 +266,7 @@ impl FileManagerApp {
                             .button(">")
                             .on_hover_text("Navigate to Next Image (Hotkey: Right)");
                         if right_button.clicked() {
-                            self.images.forward();
+                            self.next_image();
                         }
                     });
                 })
@@ -80,27 +274,150 @@ impl FileManagerApp {
     }
 
     fn add_image(&mut self, ctx: &Context, ui: &mut Ui) {
-        let Some(image) = self.images.current_image().transpose() else {
+        let Some(image) = self.images.current_image(ctx).transpose() else {
+            if self.images.is_loading() {
+                Self::add_placeholder(ui);
+            }
             return;
         };
 
-        let Ok(image) = image else {
-            // TODO: Improve error handling.
-            eprintln!("Encountered error while loading image: {}", image.err().unwrap());
-            return;
+        let image = match image {
+            Ok(image) => image,
+            Err(error) => {
+                ui.centered_and_justified(|ui| ui.colored_label(Color32::RED, error.to_string()));
+                return;
+            }
         };
 
+        // Dimensions already account for the EXIF orientation, see `images::load_image`.
+        let (texture_id, width, height) = (image.texture_id(ctx), image.width(), image.height());
         let size = ui.available_size_before_wrap().max(vec2(100.0, 100.0));
-        let aspect_x = size.y * (image.width() as f32) / (image.height() as f32);
+        let aspect_x = size.y * (width as f32) / (height as f32);
+        #[allow(clippy::obfuscated_if_else)]
         let fit = (aspect_x <= size.x)
             .then_some(vec2(aspect_x, size.y))
-            .unwrap_or_else(|| {
-                vec2(
-                    size.x,
-                    size.x * (image.height() as f32) / (image.width() as f32),
-                )
+            .unwrap_or_else(|| vec2(size.x, size.x * (height as f32) / (width as f32)));
+
+        let response = ui
+            .vertical_centered(|ui| {
+                ui.add(Image::new(texture_id, fit).sense(Sense::click_and_drag()))
+            })
+            .inner;
+        self.draw_region(ui, &response);
+        if self.show_regions {
+            self.remove_clicked_region(&response);
+            self.paint_regions(ui, response.rect);
+        }
+    }
+
+    /// Shown while the current image is loading, see [crate::images::ImageCache::current_image].
+    fn add_placeholder(ui: &mut Ui) {
+        ui.centered_and_justified(|ui| ui.spinner());
+    }
+
+    /// Lets the user draw a box on the image and choose the person shown in it afterwards.
+    fn draw_region(&mut self, ui: &mut Ui, response: &Response) {
+        let rect = response.rect;
+        let relative = |pos: Pos2| {
+            (
+                (pos.x - rect.min.x) / rect.width(),
+                (pos.y - rect.min.y) / rect.height(),
+            )
+        };
+
+        if let Some(pos) = response.interact_pointer_pos() {
+            if response.drag_started() {
+                self.region_drag = Some((relative(pos), relative(pos)));
+            } else if let Some((_, end)) = &mut self.region_drag {
+                *end = relative(pos);
+            }
+        }
+
+        let Some((start, end)) = self.region_drag else {
+            return;
+        };
+        let region = Region::from_corners(start, end);
+        if response.drag_released() {
+            self.region_drag = None;
+            if region.width > Self::MIN_REGION_SIZE && region.height > Self::MIN_REGION_SIZE {
+                self.pending_region = Some(region);
+                self.open_meta_window(MetaKind::Persons);
+            }
+        } else {
+            ui.painter().rect_stroke(
+                Self::region_rect(rect, &region),
+                0.0,
+                Stroke::new(2.0, Color32::YELLOW),
+            );
+        }
+    }
+
+    /// Removes the region of the current image that was clicked with the secondary button.
+    fn remove_clicked_region(&mut self, response: &Response) {
+        let (true, Some(pos)) = (
+            response.secondary_clicked(),
+            response.interact_pointer_pos(),
+        ) else {
+            return;
+        };
+        let Some(image_path) = self.images.current_image_path() else {
+            return;
+        };
+        let rect = response.rect;
+        let point = (
+            (pos.x - rect.min.x) / rect.width(),
+            (pos.y - rect.min.y) / rect.height(),
+        );
+        let clicked = self.meta.read_file(image_path, |file| {
+            file.regions.iter().find_map(|(person, regions)| {
+                let index = regions.iter().position(|region| region.contains(point))?;
+                Some((*person, index))
+            })
+        });
+        let Some(Some((person, index))) = clicked else {
+            return;
+        };
+        let result = self
+            .meta
+            .edit_file(&self.meta_current_folder, image_path, |file| {
+                file.remove_person_region(person, index);
             });
+        self.show_main_error(result.map_err(Into::into));
+    }
+
+    /// Paints the regions of the current image labelled with the names of the persons.
+    fn paint_regions(&mut self, ui: &mut Ui, rect: Rect) {
+        let Some(image_path) = self.images.current_image_path() else {
+            return;
+        };
+        let persons = self.meta.persons();
+        let painter = ui.painter();
+        // Meta data that cannot be read has no regions, the error is shown when editing it.
+        self.meta.read_file(image_path, |file| {
+            for (person, regions) in &file.regions {
+                let name = persons
+                    .person(person)
+                    .map_or("Unknown", |person| person.name.as_str());
+                for region in regions {
+                    let region_rect = Self::region_rect(rect, region);
+                    painter.rect_stroke(region_rect, 0.0, Stroke::new(2.0, Color32::YELLOW));
+                    painter.text(
+                        region_rect.left_bottom(),
+                        Align2::LEFT_TOP,
+                        name,
+                        FontId::proportional(14.0),
+                        Color32::YELLOW,
+                    );
+                }
+            }
+        });
+    }
 
-        ui.vertical_centered(|ui| ui.add(Image::new(image.texture_id(ctx), fit)));
+    /// Position of the region in the given rectangle of the image on screen.
+    fn region_rect(rect: Rect, region: &Region) -> Rect {
+        Rect::from_min_size(
+            rect.min + vec2(region.x * rect.width(), region.y * rect.height()),
+            vec2(region.width * rect.width(), region.height * rect.height()),
+        )
     }
 }
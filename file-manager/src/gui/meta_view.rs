@@ -5,7 +5,7 @@ use eframe::{
 };
 use egui_extras::{Column, TableBuilder};
 
-use super::{FileManagerApp, MetaOption};
+use super::{FileManagerApp, MetaKind, MetaOption};
 
 impl FileManagerApp {
     pub(crate) fn update_meta_view(&mut self, ctx: &Context) {
@@ -17,7 +17,11 @@ impl FileManagerApp {
             return;
         }
 
-        Window::new("People")
+        let title = match self.meta_kind {
+            MetaKind::Persons => "People",
+            MetaKind::Albums => "Albums",
+        };
+        Window::new(title)
             .id(eframe::egui::Id::new("meta_window"))
             .collapsible(false)
             .show(ctx, |ui| {
@@ -56,7 +60,8 @@ impl FileManagerApp {
 
     fn add_meta_option(&mut self, ui: &mut Ui, option_index: usize) {
         if ui.button("+").clicked() {
-            self.meta_handle_confirm(option_index).unwrap(); // TODO: Improve error handling.
+            let result = self.meta_handle_confirm(option_index);
+            self.show_main_error(result);
         }
 
         let option = &self.meta_options[option_index];
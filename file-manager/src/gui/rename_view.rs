@@ -0,0 +1,114 @@
+use eframe::{
+    egui::{Button, Context, Grid, ScrollArea, TextEdit, Window},
+    epaint::Color32,
+};
+
+use super::FileManagerApp;
+use crate::{
+    rename::{self, Template},
+    Result,
+};
+
+impl FileManagerApp {
+    pub(crate) fn update_rename_view(&mut self, ctx: &Context) {
+        if !self.rename_window_open {
+            return;
+        }
+
+        let mut open = self.rename_window_open;
+        Window::new("Rename Browsed Images")
+            .id(eframe::egui::Id::new("rename_window"))
+            .collapsible(false)
+            .open(&mut open)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    let template = ui
+                        .add(
+                            TextEdit::singleline(&mut self.rename_template)
+                                .hint_text("{year}/{month}-{event}/{date}_{seq}.{ext}")
+                                .desired_width(400.0),
+                        )
+                        .on_hover_text(
+                            "New Path Relative to the Root Folder\n\
+                             Date: {year} {month} {day} {hour} {minute} {second} {date} {time}\n\
+                             File: {name} {ext} {folder} {seq}\n\
+                             Meta Data: {event} {person} {persons} {tag:key} {camera} {rating}",
+                        );
+                    if template.changed() {
+                        self.rename_preview = None;
+                    }
+                    if ui.button("Preview").clicked() {
+                        self.rename_status = self.preview_rename().err().map(|e| e.to_string());
+                    }
+                    let can_rename = self
+                        .rename_preview
+                        .as_ref()
+                        .is_some_and(|renames| !renames.iter().any(|rename| rename.collision));
+                    let execute = ui
+                        .add_enabled(can_rename, Button::new("Rename"))
+                        .on_hover_text("Move the Images and their Meta Data as Previewed");
+                    if execute.clicked() {
+                        self.rename_status = Some(match self.execute_rename() {
+                            Ok(count) => format!("Renamed {count} images"),
+                            Err(error) => error.to_string(),
+                        });
+                    }
+                });
+
+                if let Some(status) = &self.rename_status {
+                    ui.colored_label(Color32::LIGHT_GRAY, status);
+                }
+
+                let Some(renames) = &self.rename_preview else {
+                    return;
+                };
+                let collisions = renames.iter().filter(|rename| rename.collision).count();
+                if collisions > 0 {
+                    ui.colored_label(
+                        Color32::RED,
+                        format!("{collisions} new paths collide with other files"),
+                    );
+                }
+                ScrollArea::vertical().max_height(400.0).show(ui, |ui| {
+                    Grid::new("rename_grid").striped(true).show(ui, |ui| {
+                        for rename in renames {
+                            ui.label(rename.from.display().to_string());
+                            let to = rename.to.display().to_string();
+                            if rename.collision {
+                                ui.colored_label(Color32::RED, to);
+                            } else if rename.from == rename.to {
+                                ui.weak(to);
+                            } else {
+                                ui.label(to);
+                            }
+                            ui.end_row();
+                        }
+                    });
+                });
+            });
+        self.rename_window_open = open;
+    }
+
+    fn preview_rename(&mut self) -> Result<()> {
+        self.rename_preview = None;
+        let template = Template::parse(self.rename_template.trim())?;
+        let root_folder = self.current_root_folder()?;
+        let paths: Vec<_> = self.images.paths().cloned().collect();
+        self.rename_preview = Some(template.plan(&self.meta, &root_folder, &paths)?);
+        Ok(())
+    }
+
+    fn execute_rename(&mut self) -> Result<usize> {
+        let Some(renames) = self.rename_preview.take() else {
+            return Ok(0);
+        };
+        let done = rename::execute(&self.meta, &self.meta_current_folder, &renames)?;
+        // Files were moved already, so their meta data has to be stored as well.
+        self.meta.save()?;
+
+        self.images.rename(&done);
+        self.caption_path = None;
+        self.reset_dates();
+        Ok(done.len())
+    }
+}
@@ -0,0 +1,77 @@
+use chrono::Month;
+use eframe::egui::{CollapsingHeader, ScrollArea, Ui};
+
+use super::FileManagerApp;
+use crate::timeline;
+
+impl FileManagerApp {
+    pub(crate) fn timeline_panel(&mut self, ui: &mut Ui) {
+        ui.horizontal(|ui| {
+            ui.heading("Timeline");
+            let refresh = ui
+                .button("⟳")
+                .on_hover_text("Read Dates of All Images Again");
+            if refresh.clicked() {
+                self.reset_dates();
+            }
+        });
+
+        if self.timeline.is_none() {
+            let Some(dates) = self.dates(ui.ctx()) else {
+                ui.centered_and_justified(|ui| ui.spinner());
+                return;
+            };
+            self.timeline = Some(timeline::build(&dates));
+        }
+        let Some(timeline) = &self.timeline else {
+            return;
+        };
+
+        // Collect the first image of the clicked period and jump to it after rendering.
+        let mut jump_to = None;
+        ScrollArea::vertical().show(ui, |ui| {
+            for (year, images_of_year) in timeline.years.iter().rev() {
+                let year_header =
+                    CollapsingHeader::new(format!("{year} ({})", images_of_year.len()))
+                        .id_source(("timeline", year))
+                        .show(ui, |ui| {
+                            for (month, images_of_month) in &images_of_year.months {
+                                let name = Month::try_from(*month as u8).map_or_else(
+                                    |_| month.to_string(),
+                                    |month| month.name().into(),
+                                );
+                                let month_header = CollapsingHeader::new(format!(
+                                    "{name} ({})",
+                                    images_of_month.len()
+                                ))
+                                .id_source(("timeline", year, month))
+                                .show(ui, |ui| {
+                                    for (day, images_of_day) in &images_of_month.days {
+                                        let label =
+                                            format!("{day}. ({})", images_of_day.images.len());
+                                        if ui.selectable_label(false, label).clicked() {
+                                            jump_to =
+                                                Some(images_of_day.paths().collect::<Vec<_>>());
+                                        }
+                                    }
+                                });
+                                if month_header.header_response.clicked() {
+                                    jump_to = Some(images_of_month.paths().collect());
+                                }
+                            }
+                        });
+                if year_header.header_response.clicked() {
+                    jump_to = Some(images_of_year.paths().collect());
+                }
+            }
+
+            if !timeline.undated.is_empty() {
+                ui.label(format!("Without Date ({})", timeline.undated.len()));
+            }
+        });
+
+        if let Some(paths) = jump_to {
+            self.images.select_first(&paths);
+        }
+    }
+}
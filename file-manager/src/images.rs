@@ -1,41 +1,131 @@
 use crate::{Error, Result};
 
 use std::{
-    collections::HashMap,
-    fs::File,
+    collections::{hash_map::RandomState, HashMap},
+    fs::{self, File},
+    hash::BuildHasher,
+    io::{BufReader, Cursor},
     num::NonZeroUsize,
     path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::SystemTime,
 };
 
+use chrono::{DateTime, Local, NaiveDateTime};
 use crossbeam_channel::{bounded, Receiver};
+use eframe::{egui::Context, epaint::ColorImage};
 use egui_extras::RetainedImage;
-use image::ImageFormat;
+use exif::{In, Tag, Value};
+use image::{DynamicImage, ImageFormat};
 use lru::LruCache;
-use rayon::{ThreadPool, ThreadPoolBuilder};
+use meta::{model::Exif, Repository};
+use rayon::{prelude::*, ThreadPool, ThreadPoolBuilder};
 use walkdir::WalkDir;
 
 pub(crate) fn find(folder_path: impl AsRef<Path>) -> Result<ImageCache> {
-    let mut paths = Vec::new();
-    let mut index = 0;
+    let mut all_paths = Vec::new();
 
-    for file in WalkDir::new(folder_path).sort_by_file_name() {
+    for file in WalkDir::new(folder_path) {
         let file = file?;
         let Ok(format) = ImageFormat::from_path(file.path()) else { continue; };
         if format.can_read() {
-            paths.push((index, file.into_path()));
-            index += 1;
+            all_paths.push(file.into_path());
         }
     }
 
-    Ok(ImageCache {
-        paths,
-        current_image: (index > 0).then_some(0),
+    let mut images = ImageCache {
+        current_image: None,
+        keys: all_paths
+            .iter()
+            .enumerate()
+            .map(|(key, path)| (path.clone(), key))
+            .collect(),
+        paths: all_paths
+            .iter()
+            .enumerate()
+            .map(|(key, path)| (key, path.clone()))
+            .collect(),
+        all_paths,
+        custom_sequence: false,
+        shown: None,
         values: LruCache::new(NonZeroUsize::new(ImageCache::SIZE).unwrap()),
         pool: ThreadPoolBuilder::new().build()?,
         processing: Default::default(),
-    })
+    };
+    images.sort(SortOrder::Name, false, &Dates::new());
+    images.current_image = (!images.paths.is_empty()).then_some(0);
+    Ok(images)
+}
+
+/// Order in which the found images are browsed.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum SortOrder {
+    /// Natural order of the paths, so `IMG_2.jpg` comes before `IMG_10.jpg`.
+    Name,
+    /// Date taken from the EXIF data, falling back to the modification time.
+    DateTaken,
+    Modified,
+    Size,
+    Random,
+}
+
+impl SortOrder {
+    pub(crate) const ALL: [SortOrder; 5] = [
+        SortOrder::Name,
+        SortOrder::DateTaken,
+        SortOrder::Modified,
+        SortOrder::Size,
+        SortOrder::Random,
+    ];
+
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            SortOrder::Name => "Name",
+            SortOrder::DateTaken => "Date Taken",
+            SortOrder::Modified => "Modified",
+            SortOrder::Size => "Size",
+            SortOrder::Random => "Random",
+        }
+    }
+}
+
+/// Dates taken of images by their paths, see [date_taken_or_modified].
+pub(crate) type Dates = HashMap<PathBuf, Option<NaiveDateTime>>;
+
+/// Date the image was taken according to its EXIF data, or its modification time otherwise.
+///
+/// EXIF data stored in the meta data is used instead of reading the image if available.
+pub(crate) fn date_taken_or_modified(
+    meta: &Repository,
+    path: impl AsRef<Path>,
+) -> Option<NaiveDateTime> {
+    let path = path.as_ref();
+    meta.read_file(path, |file| file.exif.as_ref()?.date_taken.clone())
+        .flatten()
+        .or_else(|| read_exif(path).ok().flatten()?.date_taken)
+        .and_then(|date| NaiveDateTime::parse_from_str(&date, "%Y-%m-%dT%H:%M:%S").ok())
+        .or_else(|| modified(path).map(|modified| DateTime::<Local>::from(modified).naive_local()))
+}
+
+/// Reads the dates taken of the images in parallel, which can take a while for many images.
+pub(crate) fn dates_taken(meta: &Repository, paths: &[PathBuf]) -> Dates {
+    paths
+        .par_iter()
+        .map(|path| (path.clone(), date_taken_or_modified(meta, path)))
+        .collect()
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
 }
 
+/// Loads the image and rotates or flips it as specified by its EXIF orientation,
+/// so the dimensions of the returned image are the dimensions as displayed.
 fn load_image(path: impl AsRef<Path>) -> Result<RetainedImage> {
     use std::io::prelude::Read;
     let mut bytes = Vec::new();
@@ -46,17 +136,177 @@ fn load_image(path: impl AsRef<Path>) -> Result<RetainedImage> {
         .file_name()
         .map_or("[Image]".into(), |name| name.to_string_lossy());
 
-    RetainedImage::from_image_bytes(debug_name, &bytes).map_err(Error::DisplayImage)
+    let image = decode(&bytes)?;
+    let size = [image.width() as usize, image.height() as usize];
+    let color_image =
+        ColorImage::from_rgba_unmultiplied(size, image.to_rgba8().as_flat_samples().as_slice());
+
+    Ok(RetainedImage::from_color_image(debug_name, color_image))
+}
+
+/// Decodes the image and rotates or flips it as specified by its EXIF orientation.
+pub(crate) fn decode(bytes: &[u8]) -> Result<DynamicImage> {
+    let image =
+        image::load_from_memory(bytes).map_err(|error| Error::DisplayImage(error.to_string()))?;
+    Ok(apply_orientation(image, orientation(bytes)))
+}
+
+/// Reads the EXIF orientation of the image, which is 1 if the image is stored as displayed.
+fn orientation(bytes: &[u8]) -> u32 {
+    exif::Reader::new()
+        .read_from_container(&mut Cursor::new(bytes))
+        .ok()
+        .and_then(|exif| {
+            exif.get_field(Tag::Orientation, In::PRIMARY)
+                .and_then(|field| field.value.get_uint(0))
+        })
+        .unwrap_or(1)
+}
+
+fn apply_orientation(image: DynamicImage, orientation: u32) -> DynamicImage {
+    match orientation {
+        2 => image.fliph(),
+        3 => image.rotate180(),
+        4 => image.flipv(),
+        5 => image.rotate90().fliph(),
+        6 => image.rotate90(),
+        7 => image.rotate270().fliph(),
+        8 => image.rotate270(),
+        _ => image,
+    }
+}
+
+/// Reads the EXIF meta data embedded in JPEG, TIFF, PNG or WebP files.
+///
+/// Returns `None` if the file does not contain EXIF meta data.
+pub(crate) fn read_exif(path: impl AsRef<Path>) -> Result<Option<Exif>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let exif = match exif::Reader::new().read_from_container(&mut reader) {
+        Ok(exif) => exif,
+        Err(exif::Error::NotFound(_)) => return Ok(None),
+        Err(error) => return Err(error.into()),
+    };
+
+    let field = |tag| exif.get_field(tag, In::PRIMARY);
+    let text = |tag| field(tag).and_then(ascii);
+    let rational = |tag| {
+        field(tag).and_then(|field| match &field.value {
+            Value::Rational(values) => values.first().map(|value| value.to_f64()),
+            _ => None,
+        })
+    };
+
+    let camera = match (text(Tag::Make), text(Tag::Model)) {
+        (Some(make), Some(model)) if model.starts_with(&make) => Some(model),
+        (Some(make), Some(model)) => Some(format!("{make} {model}")),
+        (make, model) => make.or(model),
+    };
+
+    Ok(Some(Exif {
+        date_taken: field(Tag::DateTimeOriginal)
+            .or_else(|| field(Tag::DateTime))
+            .and_then(date_time),
+        camera,
+        lens: text(Tag::LensModel),
+        exposure_time: field(Tag::ExposureTime).and_then(|field| match &field.value {
+            Value::Rational(values) => values.first().map(exposure_time),
+            _ => None,
+        }),
+        f_number: rational(Tag::FNumber).map(|value| value as f32),
+        iso: field(Tag::PhotographicSensitivity).and_then(|field| field.value.get_uint(0)),
+        focal_length: rational(Tag::FocalLength).map(|value| value as f32),
+        latitude: coordinate(&exif, Tag::GPSLatitude, Tag::GPSLatitudeRef, "S"),
+        longitude: coordinate(&exif, Tag::GPSLongitude, Tag::GPSLongitudeRef, "W"),
+    })
+    .filter(|exif| !exif.is_empty()))
+}
+
+fn ascii(field: &exif::Field) -> Option<String> {
+    match &field.value {
+        Value::Ascii(values) => values
+            .first()
+            .map(|value| String::from_utf8_lossy(value).trim().to_string())
+            .filter(|value| !value.is_empty()),
+        _ => None,
+    }
+}
+
+/// Formats exposure times below one second as fractions, e.g. `1/250`.
+fn exposure_time(value: &exif::Rational) -> String {
+    if value.num == 0 || value.num >= value.denom {
+        value.to_f64().to_string()
+    } else {
+        format!("1/{}", (value.denom as f64 / value.num as f64).round())
+    }
+}
+
+fn date_time(field: &exif::Field) -> Option<String> {
+    let Value::Ascii(values) = &field.value else {
+        return None;
+    };
+    let date_time = exif::DateTime::from_ascii(values.first()?).ok()?;
+    Some(format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+        date_time.year,
+        date_time.month,
+        date_time.day,
+        date_time.hour,
+        date_time.minute,
+        date_time.second
+    ))
+}
+
+/// Converts GPS degrees, minutes and seconds into degrees, negative for the given reference.
+fn coordinate(exif: &exif::Exif, tag: Tag, reference_tag: Tag, negative: &str) -> Option<f64> {
+    let Value::Rational(values) = &exif.get_field(tag, In::PRIMARY)?.value else {
+        return None;
+    };
+    let [degrees, minutes, seconds] = values.get(..3)? else {
+        return None;
+    };
+    let value = degrees.to_f64() + minutes.to_f64() / 60.0 + seconds.to_f64() / 3600.0;
+    let reference = exif.get_field(reference_tag, In::PRIMARY).and_then(ascii);
+    Some(if reference.as_deref() == Some(negative) {
+        -value
+    } else {
+        value
+    })
 }
 
 pub(crate) struct ImageCache {
+    /// All images that were found in the order they are browsed in.
+    all_paths: Vec<PathBuf>,
+
+    /// Keys of all images that were browsed so far, including images that were not found
+    /// but browsed in a custom sequence.
+    keys: HashMap<PathBuf, usize>,
+
+    /// Images that are browsed, which can be a subset of all images.
     paths: Vec<(usize, PathBuf)>,
+
+    /// Whether `paths` are in a custom order given by [ImageCache::set_sequence]
+    /// instead of the order of `all_paths`.
+    custom_sequence: bool,
     current_image: Option<isize>,
-    values: LruCache<usize, RetainedImage>,
+
+    /// Key of the image that was shown last, see [ImageCache::current_image].
+    shown: Option<usize>,
+
+    /// Loaded images, including images that could not be loaded so they are not tried again
+    /// until another image is shown.
+    values: LruCache<usize, std::result::Result<RetainedImage, ChannelError>>,
     pool: ThreadPool,
 
     /// Contains connections to all images that are currently being loaded.
-    processing: HashMap<usize, Receiver<std::result::Result<RetainedImage, ChannelError>>>,
+    processing: HashMap<usize, Loading>,
+}
+
+/// Image that is being loaded in the thread pool.
+struct Loading {
+    receiver: Receiver<std::result::Result<RetainedImage, ChannelError>>,
+
+    /// Cleared when the image is not needed anymore, so it is skipped if loading did not start yet.
+    wanted: Arc<AtomicBool>,
 }
 
 impl ImageCache {
@@ -72,63 +322,313 @@ impl ImageCache {
         self.move_by(-1);
     }
 
+    /// Moves forward to the next image for which `accept` returns true.
+    pub(crate) fn forward_to(&mut self, accept: impl FnMut(&Path) -> bool) {
+        self.move_to(1, accept);
+    }
+
+    /// Moves back to the previous image for which `accept` returns true.
+    pub(crate) fn back_to(&mut self, accept: impl FnMut(&Path) -> bool) {
+        self.move_to(-1, accept);
+    }
+
+    /// Moves by `step` until an image is reached for which `accept` returns true.
+    ///
+    /// Stays at the current image if no other image is accepted.
+    fn move_to(&mut self, step: isize, mut accept: impl FnMut(&Path) -> bool) {
+        let Some(start) = self.current_image else {
+            return;
+        };
+        let len = self.paths.len() as isize;
+        self.current_image = (1..len)
+            .map(|distance| (start + step * distance).rem_euclid(len))
+            .find(|&index| accept(&self.paths[index as usize].1))
+            .or(self.current_image);
+    }
+
     pub(crate) fn move_by(&mut self, step: isize) {
         self.current_image = self
             .current_image
             .and_then(|index| (index + step).checked_rem_euclid(self.paths.len() as isize));
     }
 
+    /// Restricts the browsed images to the images for which `filter` returns true.
+    ///
+    /// The current image stays selected if it passes the filter.
+    /// Otherwise the next image that passes the filter gets selected.
+    pub(crate) fn set_filter(&mut self, mut filter: impl FnMut(&Path) -> bool) {
+        let current_path = self.current_image_path().cloned();
+        let current_position = current_path
+            .and_then(|current_path| self.all_paths.iter().position(|path| *path == current_path));
+
+        let (positions, paths): (Vec<_>, Vec<_>) = self
+            .all_paths
+            .iter()
+            .enumerate()
+            .filter(|(_, path)| filter(path))
+            .map(|(position, path)| (position, (self.keys[path], path.clone())))
+            .unzip();
+        self.paths = paths;
+        self.custom_sequence = false;
+
+        self.current_image = (!self.paths.is_empty()).then(|| {
+            current_position
+                .and_then(|current_position| {
+                    positions
+                        .iter()
+                        .position(|position| *position >= current_position)
+                })
+                .unwrap_or(0) as isize
+        });
+    }
+
+    /// Changes the order in which the found images are browsed.
+    ///
+    /// Images browsed in a custom sequence keep their order. The current image stays selected.
+    /// Sorting by date uses the given dates, see [dates_taken].
+    pub(crate) fn sort(&mut self, order: SortOrder, reverse: bool, dates: &Dates) {
+        match order {
+            SortOrder::Name => self
+                .all_paths
+                .sort_by(|a, b| natord::compare(&a.to_string_lossy(), &b.to_string_lossy())),
+            SortOrder::DateTaken => self
+                .all_paths
+                .sort_by_key(|path| dates.get(path).copied().flatten()),
+            SortOrder::Modified => self.all_paths.sort_by_cached_key(|path| modified(path)),
+            SortOrder::Size => self
+                .all_paths
+                .sort_by_cached_key(|path| fs::metadata(path).map_or(0, |metadata| metadata.len())),
+            SortOrder::Random => {
+                let state = RandomState::new();
+                self.all_paths
+                    .sort_by_cached_key(|path| state.hash_one(path));
+            }
+        }
+        if reverse {
+            self.all_paths.reverse();
+        }
+
+        if self.custom_sequence {
+            return;
+        }
+        let current_path = self.current_image_path().cloned();
+        let positions: HashMap<_, _> = self
+            .all_paths
+            .iter()
+            .enumerate()
+            .map(|(position, path)| (path, position))
+            .collect();
+        self.paths.sort_by_key(|(_, path)| positions[path]);
+        self.current_image = current_path.and_then(|current_path| {
+            self.paths
+                .iter()
+                .position(|(_, path)| *path == current_path)
+                .map(|index| index as isize)
+        });
+    }
+
+    /// Browses the given images in the given order.
+    ///
+    /// The current image stays selected if it is part of the sequence.
+    pub(crate) fn set_sequence(&mut self, sequence: Vec<PathBuf>) {
+        let current_path = self.current_image_path().cloned();
+
+        self.paths = sequence
+            .into_iter()
+            .map(|path| {
+                let next_key = self.keys.len();
+                let key = *self.keys.entry(path.clone()).or_insert(next_key);
+                (key, path)
+            })
+            .collect();
+
+        self.current_image = (!self.paths.is_empty()).then(|| {
+            current_path
+                .and_then(|current_path| {
+                    self.paths
+                        .iter()
+                        .position(|(_, path)| *path == current_path)
+                })
+                .unwrap_or(0) as isize
+        });
+        self.custom_sequence = true;
+    }
+
+    /// Selects the first of the given images that is browsed.
+    ///
+    /// Returns false and keeps the current image if none of them is browsed.
+    pub(crate) fn select_first(&mut self, candidates: &[&Path]) -> bool {
+        let indices: HashMap<&Path, usize> = self
+            .paths
+            .iter()
+            .enumerate()
+            .map(|(index, (_, path))| (path.as_path(), index))
+            .collect();
+        let index = candidates
+            .iter()
+            .find_map(|candidate| indices.get(candidate).copied());
+        if let Some(index) = index {
+            self.current_image = Some(index as isize);
+        }
+        index.is_some()
+    }
+
+    /// Updates the paths of images that were moved, keeping their order and loaded images.
+    pub(crate) fn rename(&mut self, renames: &[(PathBuf, PathBuf)]) {
+        let renames: HashMap<&PathBuf, &PathBuf> =
+            renames.iter().map(|(from, to)| (from, to)).collect();
+        for path in self
+            .all_paths
+            .iter_mut()
+            .chain(self.paths.iter_mut().map(|(_, path)| path))
+        {
+            if let Some(to) = renames.get(path) {
+                *path = (*to).clone();
+            }
+        }
+        for (from, to) in renames {
+            if let Some(key) = self.keys.remove(from) {
+                self.keys.insert(to.clone(), key);
+            }
+        }
+    }
+
+    pub(crate) fn remove_filter(&mut self) {
+        self.set_filter(|_| true);
+    }
+
+    /// All images that were found, including images that are not browsed because of a filter.
+    pub(crate) fn all_paths(&self) -> &[PathBuf] {
+        &self.all_paths
+    }
+
+    /// Browsed images in the order they are browsed.
+    pub(crate) fn paths(&self) -> impl Iterator<Item = &PathBuf> {
+        self.paths.iter().map(|(_, path)| path)
+    }
+
+    /// Number of browsed images.
+    pub(crate) fn len(&self) -> usize {
+        self.paths.len()
+    }
+
+    /// Number of images found, including images that are not browsed because of a filter.
+    pub(crate) fn total_len(&self) -> usize {
+        self.all_paths.len()
+    }
+
     pub(crate) fn current_image_path(&mut self) -> Option<&PathBuf> {
         self.current_image
             .map(|index| &self.paths[index as usize].1)
     }
 
-    pub(crate) fn current_image(&mut self) -> Result<Option<&RetainedImage>> {
+    /// Returns the current image without waiting for it to be loaded.
+    ///
+    /// Returns `None` if there is no current image or it is still being loaded, see
+    /// [ImageCache::is_loading]. A repaint of `ctx` is requested when an image finished loading.
+    pub(crate) fn current_image(&mut self, ctx: &Context) -> Result<Option<&RetainedImage>> {
         let Some(index) = self.current_image else {
             return Ok(None);
         };
+        self.receive_loaded_images();
 
-        let (key, path) = &self.paths[index as usize];
-        let key = *key;
-
-        if self.values.get(&key).is_none() {
-            if !self.processing.contains_key(&key) {
-                self.start_loading_image(key, path.clone());
-            }
-            let image = self.processing[&key]
-                .recv()
-                .expect("channel empty or disconnected")?;
-            self.values.put(key, image);
-            self.processing.remove(&key);
+        let key = self.paths[index as usize].0;
+        // Errors are kept while the image is shown, so loading is not retried every frame.
+        if self.shown.replace(key) != Some(key) {
+            self.forget_errors();
+        }
+        if !self.values.contains(&key) && !self.processing.contains_key(&key) {
+            self.start_loading_image(key, self.paths[index as usize].1.clone(), ctx);
         }
 
         // Start preloading after retrieving requested image to make loading the requested image a priority.
-        self.preload(index);
+        self.preload(index, ctx);
+
+        match self.values.get(&key) {
+            Some(Ok(image)) => Ok(Some(image)),
+            Some(Err(error)) => Err(Error::DisplayImage(error.to_string())),
+            None => Ok(None),
+        }
+    }
+
+    /// Removes the images that could not be loaded, so they are loaded again when they are needed.
+    fn forget_errors(&mut self) {
+        let failed: Vec<_> = self
+            .values
+            .iter()
+            .filter_map(|(key, image)| image.is_err().then_some(*key))
+            .collect();
+        for key in failed {
+            self.values.pop(&key);
+        }
+    }
 
-        Ok(self.values.get(&key))
+    /// Whether the current image is still being loaded.
+    pub(crate) fn is_loading(&self) -> bool {
+        self.current_image
+            .is_some_and(|index| self.processing.contains_key(&self.paths[index as usize].0))
+    }
+
+    fn receive_loaded_images(&mut self) {
+        let loaded: Vec<_> = self
+            .processing
+            .iter()
+            .filter_map(|(key, loading)| Some((*key, loading.receiver.try_recv().ok()?)))
+            .collect();
+        for (key, image) in loaded {
+            self.processing.remove(&key);
+            self.values.put(key, image);
+        }
     }
 
-    fn preload(&mut self, around: isize) {
+    /// Starts loading the images around the current one and stops loading images
+    /// that are not around it anymore, so browsing quickly does not queue up images.
+    fn preload(&mut self, around: isize, ctx: &Context) {
+        let mut keys = Vec::new();
         for index in (around - Self::PRELOAD_BEFORE)..(around + Self::PRELOAD_AFTER) {
             let Some(index) = index.checked_rem_euclid(self.paths.len() as isize) else {
                 continue;
             };
             let (key, path) = &self.paths[index as usize];
+            keys.push(*key);
 
-            if self.values.get(key).is_none() && !self.processing.contains_key(key) {
-                self.start_loading_image(*key, path.clone());
+            if !self.values.contains(key) && !self.processing.contains_key(key) {
+                self.start_loading_image(*key, path.clone(), ctx);
             }
         }
+
+        self.processing.retain(|key, loading| {
+            let wanted = keys.contains(key);
+            if !wanted {
+                loading.wanted.store(false, Ordering::Relaxed);
+            }
+            wanted
+        });
     }
 
-    fn start_loading_image(&mut self, key: usize, path: PathBuf) {
+    fn start_loading_image(&mut self, key: usize, path: PathBuf, ctx: &Context) {
         let (sender, receiver) = bounded(1);
-        self.processing.insert(key, receiver);
+        let wanted = Arc::new(AtomicBool::new(true));
+        self.processing.insert(
+            key,
+            Loading {
+                receiver,
+                wanted: wanted.clone(),
+            },
+        );
 
+        let ctx = ctx.clone();
         self.pool.spawn(move || {
+            if !wanted.load(Ordering::Relaxed) {
+                return;
+            }
             let image = load_image(path);
             let image = image.map_err(|error| ChannelError(format!("{error}"))); // `std::error:Error` does not implement `Send`.
-            sender.send(image).expect("channel disconnected");
+
+            // The receiver is dropped if the image is not needed anymore.
+            if sender.send(image).is_ok() {
+                ctx.request_repaint();
+            }
         });
     }
 }
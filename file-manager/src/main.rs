@@ -1,12 +1,23 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")] // hide console window on Windows in release
 
 mod arguments;
+mod cli;
+mod embed;
+mod gallery;
 mod gui;
 mod images;
+mod rename;
+mod server;
+mod timeline;
 
-use std::path::{Path, PathBuf};
+use std::{
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    process::ExitCode,
+    sync::Arc,
+};
 
-use arguments::Arguments;
+use arguments::{Arguments, Command};
 
 use clap::Parser;
 use directories::ProjectDirs;
@@ -16,32 +27,76 @@ use thiserror::Error;
 
 const REPOSITORY_PATH: &str = ".meta";
 
-fn main() {
+fn main() -> ExitCode {
+    attach_parent_console();
     let arguments = Arguments::parse();
-    if let Err(error) = file_manager(&arguments) {
-        eprintln!("Encountered error in file manager: {}", error);
+    let folder_cache = arguments.folder_cache;
+    match file_manager(arguments.command(), folder_cache) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(error) => {
+            eprintln!("Encountered error in file manager: {}", error);
+            ExitCode::FAILURE
+        }
     }
 }
 
-fn file_manager(arguments: &Arguments) -> Result<()> {
-    let folder_path = &arguments.folder.canonicalize()?;
+/// Runs the command. Returns `false` if the command found problems, see [Command::Check].
+fn file_manager(command: Command, folder_cache: NonZeroUsize) -> Result<bool> {
+    let meta = meta::Repository::load_or_create(meta_path())?;
+    meta.set_folder_cache_size(folder_cache)?;
+    match command {
+        Command::Gui { folder, serve } => gui(&folder, serve.as_deref(), meta)?,
+        Command::Tag { tag, files } => cli::tag(&meta, &tag, &files)?,
+        Command::Untag { tag, files } => cli::untag(&meta, &tag, &files)?,
+        Command::Query { query, folder } => cli::query(&meta, &query, &folder)?,
+        Command::Persons(command) => cli::persons(&meta, &command)?,
+        Command::Export(arguments) => cli::export(&meta, &arguments)?,
+        Command::Check => return cli::check(&meta),
+    }
+    Ok(true)
+}
+
+fn gui(folder: &Path, serve: Option<&str>, meta: meta::Repository) -> Result<()> {
+    let folder_path = &folder.canonicalize()?;
     let images = images::find(folder_path)?;
-    let mut meta = meta::Repository::load_or_create(meta_path())?;
-    let meta_current_folder = meta.root_folders_mut().get_or_create(folder_path)?;
+    let meta_current_folder =
+        meta.edit_root_folders(|root_folders| root_folders.get_or_create(folder_path))?;
+    let meta = Arc::new(meta);
+    let server = serve.map(server::Server::bind).transpose()?;
 
-    let app = Box::new(gui::FileManagerApp::new(images, meta, meta_current_folder));
     eframe::run_native(
         "JP File Manager",
         Default::default(),
-        Box::new(|creation_context| {
+        Box::new(move |creation_context| {
             apply_style(creation_context);
-            app
+            let api_calls = server
+                .map(|server| server.spawn(creation_context.egui_ctx.clone(), Arc::clone(&meta)));
+            let mut app = gui::FileManagerApp::new(images, meta, meta_current_folder, api_calls);
+            app.index_all(&creation_context.egui_ctx);
+            Box::new(app)
         }),
     )?;
 
     Ok(())
 }
 
+/// Lets the commands print to the console they are started from on Windows.
+///
+/// Release builds use the GUI subsystem, so no console window opens when the GUI is started from
+/// the Explorer, but then they are not connected to the console of the shell either.
+#[cfg(windows)]
+fn attach_parent_console() {
+    use windows_sys::Win32::System::Console::{AttachConsole, ATTACH_PARENT_PROCESS};
+
+    // SAFETY: Only connects the standard streams to the console of the parent process, it fails
+    // without side effects if there is none or the process already has a console.
+    unsafe { AttachConsole(ATTACH_PARENT_PROCESS) };
+}
+
+#[cfg(not(windows))]
+fn attach_parent_console() {}
+
 fn meta_path() -> PathBuf {
     let dirs = ProjectDirs::from("net", "JP", "JP File Manager");
     if let Some(dirs) = dirs.filter(|_| !cfg!(debug_assertions)) {
@@ -66,9 +121,45 @@ pub enum Error {
     #[error("could not read image file: {0}")]
     LoadImage(#[from] std::io::Error),
 
+    #[error("could not write file: {0}")]
+    WriteFile(std::io::Error),
+
+    #[error("{0} already exists, move or rename it first")]
+    FileExists(PathBuf),
+
+    #[error("could not read EXIF data: {0}")]
+    ExifError(#[from] exif::Error),
+
+    #[error("could not embed meta data: {0}")]
+    InvalidJpeg(&'static str),
+
     #[error("could not decode image: {0}")]
     DisplayImage(String),
 
+    #[error("could not write image: {0}")]
+    WriteImage(#[from] image::ImageError),
+
+    #[error("could not export gallery: {0}")]
+    ExportGallery(String),
+
+    #[error("could not rename files: {0}")]
+    InvalidTemplate(String),
+
+    #[error("new path {0} collides with another file")]
+    RenameCollision(PathBuf),
+
+    #[error("no known root folder contains {0}, add it with --root")]
+    NoRootFolder(PathBuf),
+
+    #[error("unknown person: {0}")]
+    UnknownPerson(String),
+
+    #[error("several persons are named {0}, use the id instead")]
+    AmbiguousPerson(String),
+
+    #[error("could not start server: {0}")]
+    Server(String),
+
     #[error("UI: {0}")]
     UIError(#[from] eframe::Error),
 
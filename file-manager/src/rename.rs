@@ -0,0 +1,493 @@
+use crate::{embed, images, Error, Result};
+
+use std::{
+    collections::{HashMap, HashSet},
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use chrono::{Datelike, Timelike};
+use meta::{
+    model::{RootFolderId, EVENT_TAG},
+    xmp, Repository,
+};
+
+/// Template for new paths relative to the root folder, e.g. `{year}/{month}-{event}/{date}_{seq}.{ext}`.
+///
+/// Supported variables:
+///
+/// - `{year}`, `{month}`, `{day}`, `{hour}`, `{minute}`, `{second}`, `{date}` (`YYYY-MM-DD`) and
+///   `{time}` (`HH-MM-SS`) of the date taken, falling back to the modification time.
+/// - `{name}` and `{ext}` of the original file name, `{folder}` of its parent folder.
+/// - `{seq}` for the position in the renamed files, zero-padded to at least three digits.
+/// - `{event}`, `{person}` (first by name), `{persons}` (all names) and `{tag:key}` (value of the tag).
+/// - `{camera}` and `{rating}`.
+///
+/// Missing values are left empty. Separators `-`, `_` and spaces at the start and end of each
+/// path component are removed afterwards, as are empty components.
+pub(crate) struct Template {
+    parts: Vec<Part>,
+}
+
+enum Part {
+    Text(String),
+    Variable(Variable),
+}
+
+#[derive(PartialEq, Eq)]
+enum Variable {
+    Year,
+    Month,
+    Day,
+    Hour,
+    Minute,
+    Second,
+    Date,
+    Time,
+    Name,
+    Extension,
+    Folder,
+    Sequence,
+    Event,
+    Person,
+    Persons,
+    Tag(String),
+    Camera,
+    Rating,
+}
+
+/// Planned move of a file.
+pub(crate) struct Rename {
+    pub(crate) from: PathBuf,
+    pub(crate) to: PathBuf,
+
+    /// Whether another file is already at the new path or is moved there as well,
+    /// including the [companions] of the file.
+    pub(crate) collision: bool,
+}
+
+impl Template {
+    pub(crate) fn parse(template: &str) -> Result<Self> {
+        let mut parts = Vec::new();
+        let mut rest = template;
+        while !rest.is_empty() {
+            let start = rest.find('{').unwrap_or(rest.len());
+            if rest[..start].contains('}') {
+                return Err(Error::InvalidTemplate("unexpected closing brace".into()));
+            }
+            if start > 0 {
+                parts.push(Part::Text(rest[..start].to_string()));
+            }
+            rest = &rest[start..];
+            if rest.is_empty() {
+                break;
+            }
+            let end = rest
+                .find('}')
+                .ok_or_else(|| Error::InvalidTemplate("missing closing brace".into()))?;
+            parts.push(Part::Variable(Self::variable(&rest[1..end])?));
+            rest = &rest[end + 1..];
+        }
+        if parts.is_empty() {
+            return Err(Error::InvalidTemplate("empty template".into()));
+        }
+        Ok(Self { parts })
+    }
+
+    fn variable(name: &str) -> Result<Variable> {
+        Ok(match name {
+            "year" => Variable::Year,
+            "month" => Variable::Month,
+            "day" => Variable::Day,
+            "hour" => Variable::Hour,
+            "minute" => Variable::Minute,
+            "second" => Variable::Second,
+            "date" => Variable::Date,
+            "time" => Variable::Time,
+            "name" => Variable::Name,
+            "ext" => Variable::Extension,
+            "folder" => Variable::Folder,
+            "seq" => Variable::Sequence,
+            "event" => Variable::Event,
+            "person" => Variable::Person,
+            "persons" => Variable::Persons,
+            "camera" => Variable::Camera,
+            "rating" => Variable::Rating,
+            _ => match name.strip_prefix("tag:") {
+                Some(key) if !key.is_empty() => Variable::Tag(key.to_string()),
+                _ => {
+                    return Err(Error::InvalidTemplate(format!(
+                        "unknown variable {{{name}}}"
+                    )))
+                }
+            },
+        })
+    }
+
+    fn uses(&self, variable: &Variable) -> bool {
+        self.parts
+            .iter()
+            .any(|part| matches!(part, Part::Variable(used) if used == variable))
+    }
+
+    /// Computes the new paths of the files in the given order, without moving anything.
+    pub(crate) fn plan(
+        &self,
+        meta: &Repository,
+        root_folder: &Path,
+        paths: &[PathBuf],
+    ) -> Result<Vec<Rename>> {
+        let digits = paths.len().to_string().len().max(3);
+        let uses_date = [
+            Variable::Year,
+            Variable::Month,
+            Variable::Day,
+            Variable::Hour,
+            Variable::Minute,
+            Variable::Second,
+            Variable::Date,
+            Variable::Time,
+        ]
+        .iter()
+        .any(|variable| self.uses(variable));
+
+        let all_persons = meta.persons();
+        let mut renames = Vec::with_capacity(paths.len());
+        for (index, path) in paths.iter().enumerate() {
+            let file = meta.load_file(path)?;
+            let file = file.as_ref();
+            let exif = file.and_then(|file| file.exif.clone());
+            let rating = file.and_then(|file| file.rating);
+            let tags = file.map(|file| file.tags.clone()).unwrap_or_default();
+            let person_ids: Vec<_> = file
+                .map(|file| file.persons.iter().copied().collect())
+                .unwrap_or_default();
+            let mut persons: Vec<_> = person_ids
+                .iter()
+                .filter_map(|id| all_persons.person(id))
+                .map(|person| person.name.clone())
+                .collect();
+            persons.sort();
+
+            let date = uses_date
+                .then(|| images::date_taken_or_modified(meta, path))
+                .flatten();
+            let camera = exif.and_then(|exif| exif.camera).or_else(|| {
+                self.uses(&Variable::Camera)
+                    .then(|| images::read_exif(path).ok().flatten()?.camera)
+                    .flatten()
+            });
+            let tag = |key: &str| tags.get(key).and_then(|values| values.first()).cloned();
+
+            let mut rendered = String::new();
+            for part in &self.parts {
+                let variable = match part {
+                    Part::Text(text) => {
+                        rendered.push_str(text);
+                        continue;
+                    }
+                    Part::Variable(variable) => variable,
+                };
+                let value = match variable {
+                    Variable::Year => date.map(|date| format!("{:04}", date.year())),
+                    Variable::Month => date.map(|date| format!("{:02}", date.month())),
+                    Variable::Day => date.map(|date| format!("{:02}", date.day())),
+                    Variable::Hour => date.map(|date| format!("{:02}", date.hour())),
+                    Variable::Minute => date.map(|date| format!("{:02}", date.minute())),
+                    Variable::Second => date.map(|date| format!("{:02}", date.second())),
+                    Variable::Date => date.map(|date| date.format("%Y-%m-%d").to_string()),
+                    Variable::Time => date.map(|date| date.format("%H-%M-%S").to_string()),
+                    Variable::Name => path
+                        .file_stem()
+                        .map(|stem| stem.to_string_lossy().into_owned()),
+                    Variable::Extension => path
+                        .extension()
+                        .map(|extension| extension.to_string_lossy().into_owned()),
+                    Variable::Folder => path
+                        .parent()
+                        .and_then(|folder| folder.file_name())
+                        .map(|name| name.to_string_lossy().into_owned()),
+                    Variable::Sequence => Some(format!("{:0digits$}", index + 1)),
+                    Variable::Event => tag(EVENT_TAG),
+                    Variable::Person => persons.first().cloned(),
+                    Variable::Persons => Some(persons.join(", ")),
+                    Variable::Tag(key) => tag(key),
+                    Variable::Camera => camera.clone(),
+                    Variable::Rating => rating.map(|rating| rating.to_string()),
+                };
+                rendered.push_str(&sanitize(&value.unwrap_or_default()));
+            }
+
+            renames.push(Rename {
+                from: path.clone(),
+                to: root_folder.join(clean(&rendered)),
+                collision: false,
+            });
+        }
+
+        mark_collisions(&mut renames)?;
+        Ok(renames)
+    }
+}
+
+/// Makes a value usable inside a file name.
+fn sanitize(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect()
+}
+
+/// Removes separators around path components and empty components, e.g. of missing values.
+fn clean(path: &str) -> PathBuf {
+    path.split('/')
+        .map(|component| component.trim_matches(|c| c == '-' || c == '_' || c == ' '))
+        .filter(|component| !component.is_empty() && *component != "." && *component != "..")
+        .collect()
+}
+
+/// Files that are moved along with an image: its XMP sidecar and the backup of the original.
+fn companions(path: &Path) -> [PathBuf; 2] {
+    [xmp::sidecar_path(path), embed::backup_path(path)]
+}
+
+fn mark_collisions(renames: &mut [Rename]) -> Result<()> {
+    let sources: HashSet<_> = renames
+        .iter()
+        .flat_map(|rename| {
+            [rename.from.clone()]
+                .into_iter()
+                .chain(companions(&rename.from))
+        })
+        .collect();
+    let mut targets: HashMap<String, usize> = HashMap::new();
+    // Compare case-insensitively, as file systems often are.
+    for rename in renames.iter() {
+        let target = rename.to.to_string_lossy().to_lowercase();
+        *targets.entry(target).or_default() += 1;
+    }
+    for rename in renames {
+        let shared = targets[&rename.to.to_string_lossy().to_lowercase()] > 1;
+        let mut occupied = false;
+        if rename.to != rename.from {
+            for target in [rename.to.clone()]
+                .into_iter()
+                .chain(companions(&rename.to))
+            {
+                occupied |= !sources.contains(&target) && target.try_exists()?;
+            }
+        }
+        rename.collision = shared || occupied || rename.to.file_name().is_none();
+    }
+    Ok(())
+}
+
+/// Moves the files to their new paths, along with their [companions] and meta data.
+///
+/// Nothing is moved if any of the renames has a collision. Files are first moved to temporary
+/// names, so files can take the paths of other renamed files. If moving a file or its meta data
+/// fails, the files are moved back. Returns the renames that were done.
+pub(crate) fn execute(
+    meta: &Repository,
+    root_folder_id: &RootFolderId,
+    renames: &[Rename],
+) -> Result<Vec<(PathBuf, PathBuf)>> {
+    if let Some(rename) = renames.iter().find(|rename| rename.collision) {
+        return Err(Error::RenameCollision(rename.to.clone()));
+    }
+
+    let renames: Vec<_> = renames
+        .iter()
+        .filter(|rename| rename.from != rename.to)
+        .collect();
+    let mut moves = Vec::with_capacity(renames.len());
+    for rename in &renames {
+        moves.push((rename.from.clone(), rename.to.clone()));
+        for (from, to) in companions(&rename.from)
+            .into_iter()
+            .zip(companions(&rename.to))
+        {
+            if from.try_exists().map_err(Error::WriteFile)? {
+                moves.push((from, to));
+            }
+        }
+    }
+
+    let mut temporary = Vec::with_capacity(moves.len());
+    for (index, (from, _)) in moves.iter().enumerate() {
+        let name = from.file_name().unwrap_or_default().to_string_lossy();
+        let path = from.with_file_name(format!(".renaming-{index}-{name}"));
+        if let Err(error) = fs::rename(from, &path) {
+            restore(&moves, &temporary, 0);
+            return Err(Error::WriteFile(error));
+        }
+        temporary.push(path);
+    }
+
+    for (index, ((_, to), path)) in moves.iter().zip(&temporary).enumerate() {
+        let moved = to
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| fs::rename(path, to));
+        if let Err(error) = moved {
+            restore(&moves, &temporary, index);
+            return Err(Error::WriteFile(error));
+        }
+    }
+
+    // The meta data is only moved once all files are in place. It is left unchanged on errors,
+    // so only the files have to be moved back.
+    let done: Vec<_> = renames
+        .iter()
+        .map(|rename| (rename.from.clone(), rename.to.clone()))
+        .collect();
+    if let Err(error) = meta.move_files(root_folder_id, &done) {
+        restore(&moves, &temporary, moves.len());
+        return Err(error.into());
+    }
+    Ok(done)
+}
+
+/// Moves files back to their original paths after an error, the first `moved` files from their
+/// new paths and the rest from their temporary paths.
+///
+/// Moved files go through their temporary paths again, as their original paths may be taken by
+/// other moved files. Existing files are never overwritten.
+fn restore(moves: &[(PathBuf, PathBuf)], temporary: &[PathBuf], moved: usize) {
+    for ((_, to), path) in moves.iter().zip(temporary).take(moved) {
+        if let Err(error) = fs::rename(to, path) {
+            eprintln!("Could not restore {}: {error}", to.display());
+        }
+    }
+    for ((from, _), path) in moves.iter().zip(temporary) {
+        let result = match from.try_exists() {
+            Ok(false) => fs::rename(path, from),
+            Ok(true) => Err(io::ErrorKind::AlreadyExists.into()),
+            Err(error) => Err(error),
+        };
+        if let Err(error) = result {
+            eprintln!("Could not restore {}: {error}", from.display());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use meta::test_support::TempFolder;
+
+    fn rename(from: impl Into<PathBuf>, to: impl Into<PathBuf>) -> Rename {
+        Rename {
+            from: from.into(),
+            to: to.into(),
+            collision: false,
+        }
+    }
+
+    #[test]
+    fn parse_templates() {
+        let template = Template::parse("{year}/{month}-{event}/{date}_{seq}.{ext}").unwrap();
+        assert_eq!(11, template.parts.len());
+        assert!(template.uses(&Variable::Event));
+        assert!(!template.uses(&Variable::Name));
+        let template = Template::parse("{tag:location} {name}").unwrap();
+        assert!(template.uses(&Variable::Tag("location".into())));
+
+        for invalid in ["", "{year", "year}", "{unknown}", "{tag:}", "{}"] {
+            assert!(
+                matches!(Template::parse(invalid), Err(Error::InvalidTemplate(_))),
+                "{invalid}"
+            );
+        }
+    }
+
+    #[test]
+    fn clean_removes_separators_and_empty_components() {
+        assert_eq!(PathBuf::from("2023/05/001.jpg"), clean("2023/05-/_001.jpg"));
+        assert_eq!(PathBuf::from("a/b.jpg"), clean("../a/./ - /b.jpg"));
+        assert_eq!(PathBuf::from("Anna, Ben.jpg"), clean("Anna, Ben.jpg"));
+        assert_eq!(PathBuf::new(), clean("/-/"));
+    }
+
+    #[test]
+    fn sanitize_replaces_separators_and_control_characters() {
+        assert_eq!("a_b_c_", sanitize("a/b:c?"));
+        assert_eq!("line_break", sanitize("line\nbreak"));
+        assert_eq!("Zoë", sanitize("Zoë"));
+    }
+
+    #[test]
+    fn mark_collisions_of_targets_and_existing_files() {
+        let folder =
+            TempFolder::new("collisions").with_files(&["a.jpg", "b.jpg", "c.jpg", "d.jpg.xmp"]);
+        let mut renames = vec![
+            // Swapped files do not collide, as both are moved.
+            rename(folder.join("a.jpg"), folder.join("b.jpg")),
+            rename(folder.join("b.jpg"), folder.join("a.jpg")),
+            rename(folder.join("x.jpg"), folder.join("c.jpg")),
+            rename(folder.join("y.jpg"), folder.join("d.jpg")),
+            rename(folder.join("z.jpg"), folder.join("e.jpg")),
+            rename(folder.join("Z.jpg"), folder.join("E.JPG")),
+            rename(folder.join("w.jpg"), "/"),
+        ];
+        mark_collisions(&mut renames).unwrap();
+        let collisions: Vec<_> = renames.iter().map(|rename| rename.collision).collect();
+        assert_eq!(vec![false, false, true, true, true, true, true], collisions);
+    }
+
+    #[test]
+    fn execute_moves_companions_and_meta_data() {
+        let folder = TempFolder::new("execute").with_files(&[
+            "library/a.jpg",
+            "library/a.jpg.xmp",
+            "library/a.jpg_original",
+            "library/b.jpg",
+        ]);
+        let library = folder.join("library");
+        let meta = Repository::load_or_create(folder.join("data")).unwrap();
+        let root_folder_id = meta
+            .edit_root_folders(|root_folders| root_folders.get_or_create(&library))
+            .unwrap();
+        meta.edit_file(&root_folder_id, library.join("a.jpg"), |file| {
+            file.rating = Some(4)
+        })
+        .unwrap();
+        let rating = |path: PathBuf| meta.load_file(path).unwrap().and_then(|file| file.rating);
+
+        // Fails as notes.txt is not a folder, so everything is moved back.
+        fs::write(library.join("notes.txt"), "notes").unwrap();
+        let renames = vec![
+            rename(library.join("a.jpg"), library.join("c.jpg")),
+            rename(library.join("b.jpg"), library.join("notes.txt/b.jpg")),
+        ];
+        assert!(execute(&meta, &root_folder_id, &renames).is_err());
+        for file in ["a.jpg", "a.jpg.xmp", "a.jpg_original", "b.jpg"] {
+            assert_eq!(file, fs::read_to_string(library.join(file)).unwrap());
+        }
+        assert_eq!(Some(4), rating(library.join("a.jpg")));
+
+        let mut renames = vec![
+            rename(library.join("a.jpg"), library.join("b.jpg")),
+            rename(library.join("b.jpg"), library.join("a.jpg")),
+        ];
+        mark_collisions(&mut renames).unwrap();
+        let done = execute(&meta, &root_folder_id, &renames).unwrap();
+        assert_eq!(2, done.len());
+        for (from, to) in [
+            ("a.jpg", "b.jpg"),
+            ("a.jpg.xmp", "b.jpg.xmp"),
+            ("a.jpg_original", "b.jpg_original"),
+            ("b.jpg", "a.jpg"),
+        ] {
+            assert_eq!(from, fs::read_to_string(library.join(to)).unwrap());
+        }
+        assert!(!library.join("a.jpg.xmp").exists());
+        assert_eq!(Some(4), rating(library.join("b.jpg")));
+        assert_eq!(None, rating(library.join("a.jpg")));
+    }
+}
@@ -0,0 +1,387 @@
+use crate::{cli, Error, Result};
+
+use std::{
+    io::Read,
+    net::{SocketAddr, ToSocketAddrs},
+    path::PathBuf,
+    sync::Arc,
+    thread,
+    time::Duration,
+};
+
+use crossbeam_channel::{Receiver, Sender};
+use eframe::egui::Context;
+use meta::Repository;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tiny_http::{Header, Method, Response};
+
+/// Time to wait for the GUI to answer a request, e.g. while a window is being moved.
+const REPLY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Largest accepted request body in bytes.
+const MAX_BODY_SIZE: usize = 1024 * 1024;
+
+/// Local HTTP server for other tools to read and change meta data while the GUI runs.
+///
+/// Requests are answered with the repository shared with the GUI, only the current image is asked
+/// from the GUI with a [Call]. Changed tags are saved and the GUI is told with [Event::TagsChanged].
+/// Requests and responses are JSON:
+///
+/// - `GET /persons` returns the persons as `[{"id": 0, "name": "Anna", "pronouns": null}]`.
+/// - `POST /query` with `{"query": "person:Anna"}` returns the matching paths of all root folders.
+/// - `POST /tag` and `POST /untag` with `{"paths": ["/a.jpg"], "tag": "key=value"}` change tags.
+/// - `GET /current` returns the path and meta data of the image shown in the GUI.
+///
+/// Errors are returned as `{"error": "message"}`. There is no authentication, so the server only
+/// listens on loopback addresses or a Unix socket. Requests with a body have to be sent as
+/// `Content-Type: application/json` and requests with an `Origin` header are rejected, so web
+/// pages in a browser cannot send requests. Requests for another `Host` than `localhost`,
+/// `127.0.0.1` or `[::1]` are rejected too, so pages cannot read answers by rebinding their
+/// domain name to a loopback address.
+pub(crate) struct Server {
+    server: tiny_http::Server,
+}
+
+/// Request for the current image that the GUI has to answer with [Call::reply].
+pub(crate) struct Call {
+    reply: Sender<std::result::Result<Value, String>>,
+}
+
+/// Message from the server to the GUI.
+pub(crate) enum Event {
+    /// Request for the current image.
+    Call(Call),
+
+    /// Tags of files were changed, e.g. so counts shown in the GUI get updated.
+    TagsChanged,
+}
+
+enum Request {
+    /// Request that is answered with the repository.
+    Meta(MetaRequest),
+
+    /// Request that is answered by the GUI, see [Call].
+    CurrentImage,
+}
+
+enum MetaRequest {
+    Persons,
+    Query(String),
+    Tag {
+        paths: Vec<PathBuf>,
+        tag: String,
+        remove: bool,
+    },
+}
+
+#[derive(Deserialize)]
+struct QueryBody {
+    query: String,
+}
+
+#[derive(Deserialize)]
+struct TagBody {
+    paths: Vec<PathBuf>,
+    tag: String,
+}
+
+impl Server {
+    /// Listens on the address, either a loopback address like `127.0.0.1:7878` or `localhost:7878`
+    /// or the path of a Unix socket prefixed with `unix:`.
+    pub(crate) fn bind(address: &str) -> Result<Self> {
+        let server = match address.strip_prefix("unix:") {
+            #[cfg(unix)]
+            Some(path) => tiny_http::Server::http_unix(path.as_ref()),
+            #[cfg(not(unix))]
+            Some(_) => return Err(Error::Server("Unix sockets are not supported".into())),
+            None => {
+                let addresses: Vec<SocketAddr> = address
+                    .to_socket_addrs()
+                    .map_err(|error| Error::Server(format!("invalid address {address}: {error}")))?
+                    .collect();
+                if addresses.is_empty() || !addresses.iter().all(|socket| socket.ip().is_loopback())
+                {
+                    return Err(Error::Server(format!(
+                        "{address} is not a loopback address like 127.0.0.1:7878"
+                    )));
+                }
+                tiny_http::Server::http(&addresses[..])
+            }
+        };
+        Ok(Self {
+            server: server.map_err(|error| Error::Server(error.to_string()))?,
+        })
+    }
+
+    /// Answers requests in a background thread that runs as long as the application.
+    pub(crate) fn spawn(self, ctx: Context, meta: Arc<Repository>) -> Receiver<Event> {
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        thread::spawn(move || {
+            for mut request in self.server.incoming_requests() {
+                let (status, body) = match Self::parse(&mut request) {
+                    Ok(Request::CurrentImage) => match Self::call(&sender, &ctx) {
+                        Some(Ok(value)) => (200, value),
+                        Some(Err(error)) => (400, json!({ "error": error })),
+                        None => (503, json!({ "error": "the application is not responding" })),
+                    },
+                    Ok(Request::Meta(request)) => match Self::answer(request, &meta, &sender, &ctx)
+                    {
+                        Ok(value) => (200, value),
+                        Err(error) => (400, json!({ "error": error.to_string() })),
+                    },
+                    Err((status, error)) => (status, json!({ "error": error })),
+                };
+                let response = Response::from_string(body.to_string())
+                    .with_status_code(status)
+                    .with_header(Header::from_bytes("Content-Type", "application/json").unwrap());
+                if let Err(error) = request.respond(response) {
+                    eprintln!("Encountered error while answering request: {}", error);
+                }
+            }
+        });
+        receiver
+    }
+
+    fn parse(request: &mut tiny_http::Request) -> std::result::Result<Request, (u16, String)> {
+        let header = |name: &'static str| {
+            request
+                .headers()
+                .iter()
+                .find(|header| header.field.equiv(name))
+                .map(|header| header.value.as_str().to_string())
+        };
+        if header("Origin").is_some() {
+            return Err((403, "requests from web pages are not allowed".into()));
+        }
+        if !header("Host").is_some_and(|host| is_local_host(&host)) {
+            return Err((
+                403,
+                "the host has to be localhost, 127.0.0.1 or [::1]".into(),
+            ));
+        }
+        if *request.method() == Method::Post {
+            let json = header("Content-Type").is_some_and(|content_type| {
+                let media_type = content_type.split(';').next().unwrap_or_default();
+                media_type.trim().eq_ignore_ascii_case("application/json")
+            });
+            if !json {
+                return Err((415, "the body has to be sent as application/json".into()));
+            }
+        }
+        if request
+            .body_length()
+            .is_some_and(|length| length > MAX_BODY_SIZE)
+        {
+            return Err((413, "the body is too large".into()));
+        }
+        let mut body = String::new();
+        request
+            .as_reader()
+            .take(MAX_BODY_SIZE as u64 + 1)
+            .read_to_string(&mut body)
+            .map_err(|error| (400, error.to_string()))?;
+        if body.len() > MAX_BODY_SIZE {
+            return Err((413, "the body is too large".into()));
+        }
+        let invalid = |error: serde_json::Error| (400, error.to_string());
+
+        match (request.method(), request.url()) {
+            (Method::Get, "/persons") => Ok(Request::Meta(MetaRequest::Persons)),
+            (Method::Get, "/current") => Ok(Request::CurrentImage),
+            (Method::Post, "/query") => {
+                let body: QueryBody = serde_json::from_str(&body).map_err(invalid)?;
+                Ok(Request::Meta(MetaRequest::Query(body.query)))
+            }
+            (Method::Post, url @ ("/tag" | "/untag")) => {
+                let body: TagBody = serde_json::from_str(&body).map_err(invalid)?;
+                Ok(Request::Meta(MetaRequest::Tag {
+                    paths: body.paths,
+                    tag: body.tag,
+                    remove: url == "/untag",
+                }))
+            }
+            (_, url) => Err((404, format!("unknown endpoint {url}"))),
+        }
+    }
+
+    fn answer(
+        request: MetaRequest,
+        meta: &Repository,
+        sender: &Sender<Event>,
+        ctx: &Context,
+    ) -> Result<Value> {
+        Ok(match request {
+            MetaRequest::Persons => {
+                let persons = meta.persons();
+                let mut persons: Vec<_> = persons.entries().iter().collect();
+                persons.sort_by_key(|(id, _)| id.0);
+                persons
+                    .into_iter()
+                    .map(|(id, person)| {
+                        json!({ "id": id.0, "name": person.name, "pronouns": person.pronouns })
+                    })
+                    .collect()
+            }
+            MetaRequest::Query(query) => json!(cli::find(meta, &query, &[])?),
+            MetaRequest::Tag { paths, tag, remove } => {
+                // Check all paths first, so either all files get changed or none.
+                let files = cli::root_folders_of(meta, &paths, None)?;
+                cli::change_tag(meta, &files, &tag, remove)?;
+                meta.save()?;
+                // The GUI may have been closed already.
+                let _ = sender.send(Event::TagsChanged);
+                ctx.request_repaint();
+                json!({ "changed": files.len() })
+            }
+        })
+    }
+
+    /// Asks the GUI for the current image and waits for the answer. Returns `None` on timeout.
+    fn call(sender: &Sender<Event>, ctx: &Context) -> Option<std::result::Result<Value, String>> {
+        let (reply, answer) = crossbeam_channel::bounded(1);
+        sender.send(Event::Call(Call { reply })).ok()?;
+        // The GUI only handles calls while updating, which it does not do when idle.
+        ctx.request_repaint();
+        answer.recv_timeout(REPLY_TIMEOUT).ok()
+    }
+}
+
+impl Call {
+    pub(crate) fn reply(self, result: std::result::Result<Value, String>) {
+        // The server stops waiting after a timeout, so the answer may be dropped.
+        let _ = self.reply.send(result);
+    }
+}
+
+/// Whether the value of a `Host` header names the loopback interface, with or without a port.
+fn is_local_host(host: &str) -> bool {
+    let (name, port) = match host.rsplit_once(':') {
+        Some((name, port)) if !name.is_empty() && !port.contains(']') => (name, Some(port)),
+        _ => (host, None),
+    };
+    let valid_port =
+        port.is_none_or(|port| !port.is_empty() && port.bytes().all(|byte| byte.is_ascii_digit()));
+    valid_port && (name.eq_ignore_ascii_case("localhost") || name == "127.0.0.1" || name == "[::1]")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{io::Write, net::TcpStream};
+
+    use meta::test_support::TempFolder;
+
+    /// Sends the raw HTTP request and returns the status code and body of the response.
+    fn send(address: SocketAddr, request: &str) -> (u16, String) {
+        let mut stream = TcpStream::connect(address).unwrap();
+        stream.write_all(request.as_bytes()).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        let status = response[9..12].parse().unwrap();
+        let body = response.split_once("\r\n\r\n").unwrap().1.to_string();
+        (status, body)
+    }
+
+    /// POST request for `localhost`, unless the headers name another host.
+    fn post(path: &str, headers: &str, body: &str) -> String {
+        let host = if headers.contains("Host:") {
+            ""
+        } else {
+            "Host: localhost\r\n"
+        };
+        format!(
+            "POST {path} HTTP/1.1\r\nConnection: close\r\n{host}{headers}Content-Length: {}\r\n\r\n{body}",
+            body.len()
+        )
+    }
+
+    #[test]
+    fn bind_only_to_loopback_addresses() {
+        assert!(Server::bind("127.0.0.1:0").is_ok());
+        for address in ["0.0.0.0:0", "192.0.2.1:7878", "no address"] {
+            assert!(
+                matches!(Server::bind(address), Err(Error::Server(_))),
+                "{address}"
+            );
+        }
+    }
+
+    #[test]
+    fn local_hosts() {
+        for host in [
+            "localhost",
+            "LocalHost:7878",
+            "127.0.0.1",
+            "127.0.0.1:80",
+            "[::1]",
+            "[::1]:7878",
+        ] {
+            assert!(is_local_host(host), "{host}");
+        }
+        for host in [
+            "",
+            "example.com",
+            "localhost.example.com",
+            "127.0.0.1:",
+            "::1",
+            "[::1]x",
+            "localhost:a",
+        ] {
+            assert!(!is_local_host(host), "{host}");
+        }
+    }
+
+    #[test]
+    fn reject_requests_from_browsers_and_large_bodies() {
+        let folder = TempFolder::new("server");
+        let meta = Arc::new(Repository::load_or_create(folder.join("data")).unwrap());
+        let server = Server::bind("127.0.0.1:0").unwrap();
+        let address = server.server.server_addr().to_ip().unwrap();
+        let _calls = server.spawn(Context::default(), meta);
+
+        let json = "Content-Type: application/json; charset=utf-8\r\n";
+        let query = r#"{"query": ""}"#;
+        assert_eq!(
+            (200, "[]".to_string()),
+            send(address, &post("/query", json, query))
+        );
+        assert_eq!(415, send(address, &post("/query", "", query)).0);
+        let origin = format!("{json}Origin: https://example.com\r\n");
+        assert_eq!(403, send(address, &post("/query", &origin, query)).0);
+        let rebound = format!("{json}Host: attacker.example:{}\r\n", address.port());
+        assert_eq!(403, send(address, &post("/query", &rebound, query)).0);
+        let get = "GET /persons HTTP/1.1\r\nHost: attacker.example\r\nConnection: close\r\n\r\n";
+        assert_eq!(403, send(address, get).0);
+        let large = format!(r#"{{"query": "{}"}}"#, "a".repeat(MAX_BODY_SIZE));
+        assert_eq!(413, send(address, &post("/query", json, &large)).0);
+        let get = "GET /persons HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n";
+        assert_eq!((200, "[]".to_string()), send(address, get));
+    }
+
+    #[test]
+    fn tagging_saves_and_notifies_the_gui() {
+        let folder = TempFolder::new("server-tag").with_files(&["library/a.jpg"]);
+        let library = folder.join("library");
+        let meta = Arc::new(Repository::load_or_create(folder.join("data")).unwrap());
+        meta.edit_root_folders(|root_folders| root_folders.get_or_create(&library))
+            .unwrap();
+        let server = Server::bind("127.0.0.1:0").unwrap();
+        let address = server.server.server_addr().to_ip().unwrap();
+        let events = server.spawn(Context::default(), meta);
+
+        let body = json!({ "paths": [library.join("a.jpg")], "tag": "holiday" }).to_string();
+        let json = "Content-Type: application/json\r\n";
+        assert_eq!(200, send(address, &post("/tag", json, &body)).0);
+        assert!(matches!(events.try_recv(), Ok(Event::TagsChanged)));
+
+        // A new repository only sees the tag if it was saved.
+        let meta = Repository::load_or_create(folder.join("data")).unwrap();
+        let file = meta.load_file(library.join("a.jpg")).unwrap().unwrap();
+        assert_eq!(
+            vec![("holiday", None)],
+            file.tag_values().collect::<Vec<_>>()
+        );
+    }
+}
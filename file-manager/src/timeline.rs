@@ -0,0 +1,143 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use chrono::{Datelike, NaiveDateTime};
+
+use crate::images::Dates;
+
+/// Images grouped by year, month and day they were taken.
+#[derive(Default)]
+pub(crate) struct Timeline {
+    pub(crate) years: BTreeMap<i32, Year>,
+
+    /// Images without date, which can only happen if their modification time can not be read.
+    pub(crate) undated: Vec<PathBuf>,
+}
+
+#[derive(Default)]
+pub(crate) struct Year {
+    pub(crate) months: BTreeMap<u32, Month>,
+}
+
+#[derive(Default)]
+pub(crate) struct Month {
+    pub(crate) days: BTreeMap<u32, Day>,
+}
+
+/// Images taken at one day, sorted by the time they were taken.
+#[derive(Default)]
+pub(crate) struct Day {
+    pub(crate) images: Vec<(NaiveDateTime, PathBuf)>,
+}
+
+/// Groups the images by the dates they were taken, see [crate::images::dates_taken].
+pub(crate) fn build(dates: &Dates) -> Timeline {
+    let mut dated: Vec<_> = dates
+        .iter()
+        .map(|(path, date)| (*date, path.clone()))
+        .collect();
+    dated.sort();
+
+    let mut timeline = Timeline::default();
+    for (date, path) in dated {
+        let Some(date) = date else {
+            timeline.undated.push(path);
+            continue;
+        };
+        timeline
+            .years
+            .entry(date.year())
+            .or_default()
+            .months
+            .entry(date.month())
+            .or_default()
+            .days
+            .entry(date.day())
+            .or_default()
+            .images
+            .push((date, path));
+    }
+    timeline
+}
+
+impl Year {
+    pub(crate) fn len(&self) -> usize {
+        self.months.values().map(Month::len).sum()
+    }
+
+    pub(crate) fn paths(&self) -> impl Iterator<Item = &Path> {
+        self.months.values().flat_map(Month::paths)
+    }
+}
+
+impl Month {
+    pub(crate) fn len(&self) -> usize {
+        self.days.values().map(|day| day.images.len()).sum()
+    }
+
+    pub(crate) fn paths(&self) -> impl Iterator<Item = &Path> {
+        self.days.values().flat_map(Day::paths)
+    }
+}
+
+impl Day {
+    pub(crate) fn paths(&self) -> impl Iterator<Item = &Path> {
+        self.images.iter().map(|(_, path)| path.as_path())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(date: &str) -> Option<NaiveDateTime> {
+        Some(NaiveDateTime::parse_from_str(date, "%Y-%m-%d %H:%M").unwrap())
+    }
+
+    #[test]
+    fn groups_images_by_year_month_and_day() {
+        let dates: Dates = [
+            ("evening.jpg", date("2023-07-14 20:00")),
+            ("morning.jpg", date("2023-07-14 08:30")),
+            ("next-day.jpg", date("2023-07-15 12:00")),
+            ("august.jpg", date("2023-08-01 00:00")),
+            ("new-year.jpg", date("2024-01-01 00:01")),
+            ("undated.jpg", None),
+        ]
+        .into_iter()
+        .map(|(path, date)| (PathBuf::from(path), date))
+        .collect();
+
+        let timeline = build(&dates);
+        assert_eq!(
+            vec![2023, 2024],
+            timeline.years.keys().copied().collect::<Vec<_>>()
+        );
+        let year = &timeline.years[&2023];
+        assert_eq!(4, year.len());
+        assert_eq!(vec![7, 8], year.months.keys().copied().collect::<Vec<_>>());
+        let july = &year.months[&7];
+        assert_eq!(3, july.len());
+        assert_eq!(
+            vec![Path::new("morning.jpg"), Path::new("evening.jpg")],
+            july.days[&14].paths().collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec!["morning.jpg", "evening.jpg", "next-day.jpg"],
+            july.paths()
+                .map(|path| path.to_str().unwrap())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(1, timeline.years[&2024].len());
+        assert_eq!(vec![PathBuf::from("undated.jpg")], timeline.undated);
+    }
+
+    #[test]
+    fn empty_timeline() {
+        let timeline = build(&Dates::new());
+        assert!(timeline.years.is_empty());
+        assert!(timeline.undated.is_empty());
+    }
+}
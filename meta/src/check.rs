@@ -0,0 +1,54 @@
+use crate::model::PersonId;
+
+use std::{fmt, path::PathBuf};
+
+/// Inconsistency in the meta data found by [crate::Repository::check].
+#[derive(Debug, PartialEq)]
+pub enum Problem {
+    /// A root folder does not exist anymore.
+    MissingRootFolder(PathBuf),
+
+    /// Meta data exists for a file that does not exist anymore.
+    MissingFile(PathBuf),
+
+    /// A file references a person that was removed.
+    UnknownPerson { path: PathBuf, person: PersonId },
+
+    /// A file of an album cannot be found, not even by its hash.
+    UnresolvedAlbumFile { album: String, path: PathBuf },
+
+    /// The query of a smart album cannot be parsed.
+    InvalidSmartAlbum { name: String, error: String },
+
+    /// A folder or its folder data cannot be read, so its files are skipped.
+    UnreadableFolder { path: PathBuf, error: String },
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Problem::MissingRootFolder(path) => {
+                write!(f, "root folder {} does not exist", path.display())
+            }
+            Problem::MissingFile(path) => {
+                write!(f, "meta data of missing file {}", path.display())
+            }
+            Problem::UnknownPerson { path, person } => {
+                write!(f, "unknown person {} in {}", person.0, path.display())
+            }
+            Problem::UnresolvedAlbumFile { album, path } => {
+                write!(
+                    f,
+                    "album \"{album}\" contains missing file {}",
+                    path.display()
+                )
+            }
+            Problem::InvalidSmartAlbum { name, error } => {
+                write!(f, "smart album \"{name}\" has an invalid query: {error}")
+            }
+            Problem::UnreadableFolder { path, error } => {
+                write!(f, "could not read {}: {error}", path.display())
+            }
+        }
+    }
+}
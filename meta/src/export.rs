@@ -0,0 +1,183 @@
+use crate::Result;
+
+use std::{
+    borrow::Cow,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use serde::Serialize;
+
+/// Flat description of a file's meta data for reports, see [crate::Repository::report].
+#[derive(Debug, Serialize)]
+pub struct ReportRow {
+    pub path: PathBuf,
+    pub hash: Option<String>,
+
+    /// Names of the persons, sorted.
+    pub persons: Vec<String>,
+
+    /// Tags formatted as `key=value`, or `key` for tags without value, sorted.
+    pub tags: Vec<String>,
+    pub rating: Option<u8>,
+
+    /// Date and time when the image was taken in the format `YYYY-MM-DDTHH:MM:SS`.
+    pub date_taken: Option<String>,
+
+    /// Modification time of the file in local time in the same format as `date_taken`.
+    pub modified: Option<String>,
+}
+
+/// File format of an export.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// One path per line, see [write_file_list].
+    FileList,
+    /// Comma separated values with a header line. Lists are separated by `; `.
+    Csv,
+    /// One JSON object per line.
+    JsonLines,
+}
+
+impl Format {
+    pub const ALL: [Format; 3] = [Format::FileList, Format::Csv, Format::JsonLines];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Format::FileList => "File List",
+            Format::Csv => "CSV",
+            Format::JsonLines => "JSON Lines",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Format::FileList => "txt",
+            Format::Csv => "csv",
+            Format::JsonLines => "jsonl",
+        }
+    }
+}
+
+/// Writes the given paths as file list with one path per line.
+pub fn write_file_list(
+    paths: impl IntoIterator<Item = impl AsRef<Path>>,
+    mut writer: impl Write,
+) -> Result<()> {
+    for path in paths {
+        writeln!(writer, "{}", path.as_ref().display())?;
+    }
+    Ok(writer.flush()?)
+}
+
+/// Writes the rows in the given format.
+pub fn write_report(rows: &[ReportRow], format: Format, mut writer: impl Write) -> Result<()> {
+    match format {
+        Format::FileList => return write_file_list(rows.iter().map(|row| &row.path), writer),
+        Format::Csv => {
+            writeln!(writer, "path,hash,persons,tags,rating,date_taken,modified")?;
+            for row in rows {
+                let fields = [
+                    row.path.to_string_lossy(),
+                    row.hash.as_deref().unwrap_or_default().into(),
+                    row.persons.join("; ").into(),
+                    row.tags.join("; ").into(),
+                    row.rating
+                        .map(|rating| rating.to_string())
+                        .unwrap_or_default()
+                        .into(),
+                    row.date_taken.as_deref().unwrap_or_default().into(),
+                    row.modified.as_deref().unwrap_or_default().into(),
+                ];
+                write_csv_line(&mut writer, &fields)?;
+            }
+        }
+        Format::JsonLines => {
+            for row in rows {
+                serde_json::to_writer(&mut writer, row)?;
+                writeln!(writer)?;
+            }
+        }
+    }
+    Ok(writer.flush()?)
+}
+
+/// Writes a CSV table with one line per person and file, sorted by person name and path.
+///
+/// Files without persons are left out.
+pub fn write_person_report(rows: &[ReportRow], mut writer: impl Write) -> Result<()> {
+    let mut entries: Vec<(&str, &Path)> = rows
+        .iter()
+        .flat_map(|row| {
+            row.persons
+                .iter()
+                .map(|person| (person.as_str(), row.path.as_path()))
+        })
+        .collect();
+    entries.sort();
+
+    writeln!(writer, "person,path")?;
+    for (person, path) in entries {
+        write_csv_line(&mut writer, &[person.into(), path.to_string_lossy()])?;
+    }
+    Ok(writer.flush()?)
+}
+
+fn write_csv_line(writer: &mut impl Write, fields: &[Cow<str>]) -> Result<()> {
+    let line: Vec<_> = fields
+        .iter()
+        .map(|field| {
+            if field.contains([',', '"', '\n', '\r']) {
+                Cow::Owned(format!("\"{}\"", field.replace('"', "\"\"")))
+            } else {
+                Cow::Borrowed(field.as_ref())
+            }
+        })
+        .collect();
+    writeln!(writer, "{}", line.join(","))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(path: &str, persons: &[&str]) -> ReportRow {
+        ReportRow {
+            path: path.into(),
+            hash: None,
+            persons: persons.iter().map(|person| person.to_string()).collect(),
+            tags: vec!["location=Rome, Italy".into(), "holiday".into()],
+            rating: Some(3),
+            date_taken: Some("2023-05-01T12:00:00".into()),
+            modified: None,
+        }
+    }
+
+    #[test]
+    fn csv_quotes_fields() {
+        let mut output = Vec::new();
+        write_report(
+            &[row("/a \"b\".jpg", &["Anna", "Ben"])],
+            Format::Csv,
+            &mut output,
+        )
+        .unwrap();
+        assert_eq!(
+            "path,hash,persons,tags,rating,date_taken,modified\n\
+             \"/a \"\"b\"\".jpg\",,Anna; Ben,\"location=Rome, Italy; holiday\",3,2023-05-01T12:00:00,\n",
+            String::from_utf8(output).unwrap()
+        );
+    }
+
+    #[test]
+    fn person_report_sorted() {
+        let rows = [row("/b.jpg", &["Ben", "Anna"]), row("/a.jpg", &["Anna"])];
+        let mut output = Vec::new();
+        write_person_report(&rows, &mut output).unwrap();
+        assert_eq!(
+            "person,path\nAnna,/a.jpg\nAnna,/b.jpg\nBen,/b.jpg\n",
+            String::from_utf8(output).unwrap()
+        );
+    }
+}
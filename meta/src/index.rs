@@ -0,0 +1,216 @@
+use crate::{
+    model::{MetaFile, PersonCollection, PersonId},
+    query::{PersonMatcher, Property, Query},
+    text,
+};
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    path::{Path, PathBuf},
+};
+
+/// Inverted index from persons and tag keys to the absolute paths of the files that have them.
+///
+/// Contains the files of all folders in the cache of the [Repository](crate::Repository) and of
+/// the folders that were evicted from it, so queries do not have to read all folder data again.
+#[derive(Debug, Default)]
+pub(crate) struct FileIndex {
+    persons: HashMap<PersonId, BTreeSet<PathBuf>>,
+    tags: HashMap<String, BTreeSet<PathBuf>>,
+}
+
+/// Persons and tag keys of a file, used to update the [FileIndex] when a file changes.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Entry {
+    persons: Vec<PersonId>,
+    tags: Vec<String>,
+}
+
+impl FileIndex {
+    pub(crate) fn insert(&mut self, path: &Path, entry: &Entry) {
+        for id in &entry.persons {
+            self.persons
+                .entry(*id)
+                .or_default()
+                .insert(path.to_path_buf());
+        }
+        for key in &entry.tags {
+            self.tags
+                .entry(key.clone())
+                .or_default()
+                .insert(path.to_path_buf());
+        }
+    }
+
+    pub(crate) fn remove(&mut self, path: &Path, entry: &Entry) {
+        for id in &entry.persons {
+            remove_path(&mut self.persons, id, path);
+        }
+        for key in &entry.tags {
+            remove_path(&mut self.tags, key, path);
+        }
+    }
+
+    /// Paths of the files that may match the query, or `None` if the query cannot be answered
+    /// with the index, e.g. because it also matches files without persons and tags.
+    ///
+    /// Only `person:`, `tag:` and `has:tags` terms and their combinations with `and` and `or`
+    /// are looked up. The files still have to be checked with [Query::matches].
+    pub(crate) fn candidates(
+        &self,
+        query: &Query,
+        persons: &PersonCollection,
+    ) -> Option<BTreeSet<PathBuf>> {
+        match query {
+            // Removed persons are not indexed anymore, see [FileIndex::retain_persons].
+            Query::Person(PersonMatcher::Id(id)) => persons
+                .person(id)
+                .map(|_| self.persons.get(id).cloned().unwrap_or_default()),
+            Query::Person(PersonMatcher::Name(name)) => Some(
+                persons
+                    .entries()
+                    .iter()
+                    .filter(|(_, person)| text::equals_ignore_case(&person.name, name))
+                    .filter_map(|(id, _)| self.persons.get(id))
+                    .flatten()
+                    .cloned()
+                    .collect(),
+            ),
+            Query::Tag { key, .. } => {
+                Some(self.tags_matching(|tag_key| text::equals_ignore_case(tag_key, key)))
+            }
+            Query::Has(Property::Tags) => Some(self.tags_matching(|_| true)),
+            Query::And(a, b) => match (self.candidates(a, persons), self.candidates(b, persons)) {
+                (Some(a), Some(b)) => Some(a.intersection(&b).cloned().collect()),
+                (a, b) => a.or(b),
+            },
+            Query::Or(a, b) => {
+                let mut a = self.candidates(a, persons)?;
+                a.append(&mut self.candidates(b, persons)?);
+                Some(a)
+            }
+            _ => None,
+        }
+    }
+
+    /// Forgets the files of the persons that are not kept, e.g. after they were removed.
+    pub(crate) fn retain_persons(&mut self, keep: impl Fn(&PersonId) -> bool) {
+        self.persons.retain(|id, _| keep(id));
+    }
+
+    fn tags_matching(&self, matches: impl Fn(&str) -> bool) -> BTreeSet<PathBuf> {
+        self.tags
+            .iter()
+            .filter(|(key, _)| matches(key))
+            .flat_map(|(_, paths)| paths.iter().cloned())
+            .collect()
+    }
+}
+
+impl Entry {
+    pub(crate) fn of(file: &MetaFile) -> Self {
+        let mut persons: Vec<_> = file.persons.iter().copied().collect();
+        persons.sort_by_key(|id| id.0);
+        let mut tags: Vec<_> = file.tags.keys().cloned().collect();
+        tags.sort();
+        Self { persons, tags }
+    }
+}
+
+fn remove_path<K: Eq + std::hash::Hash>(
+    index: &mut HashMap<K, BTreeSet<PathBuf>>,
+    key: &K,
+    path: &Path,
+) {
+    if let Some(paths) = index.get_mut(key) {
+        paths.remove(path);
+        if paths.is_empty() {
+            index.remove(key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::model::Person;
+
+    fn file(persons: &[usize], tags: &[&str]) -> MetaFile {
+        let mut file = MetaFile::default();
+        file.persons.extend(persons.iter().map(|id| PersonId(*id)));
+        for tag in tags {
+            file.add_tag(tag, None);
+        }
+        file
+    }
+
+    fn persons() -> PersonCollection {
+        let mut persons = PersonCollection::default();
+        for name in ["Anna", "Ben", "Carl"] {
+            persons.add(Person::new(name.into(), None));
+        }
+        persons
+    }
+
+    fn candidates(index: &FileIndex, query: &str) -> Option<Vec<PathBuf>> {
+        let query = Query::parse(query).unwrap();
+        let candidates = index.candidates(&query, &persons())?;
+        Some(candidates.into_iter().collect())
+    }
+
+    fn paths(paths: &[&str]) -> Option<Vec<PathBuf>> {
+        Some(paths.iter().map(PathBuf::from).collect())
+    }
+
+    #[test]
+    fn finds_files_by_person_and_tag() {
+        let mut index = FileIndex::default();
+        index.insert(Path::new("/b.jpg"), &Entry::of(&file(&[0], &["Trip"])));
+        index.insert(Path::new("/a.jpg"), &Entry::of(&file(&[0, 1], &[])));
+        index.insert(Path::new("/c.jpg"), &Entry::of(&file(&[], &["holiday"])));
+
+        assert_eq!(
+            paths(&["/a.jpg", "/b.jpg"]),
+            candidates(&index, "person:anna")
+        );
+        assert_eq!(paths(&["/a.jpg"]), candidates(&index, "person:#1"));
+        assert_eq!(paths(&["/b.jpg"]), candidates(&index, "tag:trip=rome"));
+        assert_eq!(paths(&["/b.jpg", "/c.jpg"]), candidates(&index, "has:tags"));
+        assert_eq!(paths(&[]), candidates(&index, "person:Carl"));
+        assert_eq!(paths(&[]), candidates(&index, "person:Dora"));
+        assert_eq!(
+            paths(&["/b.jpg"]),
+            candidates(&index, "person:anna and rating:3 and tag:trip")
+        );
+        assert_eq!(
+            paths(&["/a.jpg", "/b.jpg", "/c.jpg"]),
+            candidates(&index, "person:anna or tag:holiday")
+        );
+        for query in [
+            "",
+            "not person:anna",
+            "person:anna or rating:3",
+            "person:#7",
+        ] {
+            assert_eq!(None, candidates(&index, query), "{query}");
+        }
+    }
+
+    #[test]
+    fn removes_changed_files() {
+        let mut index = FileIndex::default();
+        let before = Entry::of(&file(&[1], &["trip"]));
+        index.insert(Path::new("/a.jpg"), &before);
+        index.remove(Path::new("/a.jpg"), &before);
+        index.insert(Path::new("/a.jpg"), &Entry::of(&file(&[2], &[])));
+
+        assert_eq!(paths(&[]), candidates(&index, "person:#1"));
+        assert_eq!(paths(&[]), candidates(&index, "tag:trip"));
+        assert_eq!(paths(&["/a.jpg"]), candidates(&index, "person:#2"));
+        assert!(!index.persons.contains_key(&PersonId(1)));
+
+        index.retain_persons(|id| *id != PersonId(2));
+        assert!(index.persons.is_empty());
+    }
+}
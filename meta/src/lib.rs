@@ -1,14 +1,50 @@
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeSet, HashMap, HashSet},
     fs::{self, File},
     io::{self, BufReader, BufWriter, Write},
+    num::NonZeroUsize,
     path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex, MutexGuard, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard,
+    },
 };
 
-use model::{Folder, MetaFile, PersonCollection, RootFolderCollection, RootFolderId};
+use check::Problem;
+use export::ReportRow;
+use index::FileIndex;
+use lru::LruCache;
+use model::{
+    AlbumCollection, AlbumId, FileReference, Folder, MetaFile, PersonCollection,
+    RootFolderCollection, RootFolderId, SmartAlbumCollection, SmartAlbumId,
+};
+use query::Query;
+use rayon::prelude::*;
 use serde::Serialize;
+use sha2::{Digest, Sha256};
+use takeout::TakeoutImport;
+use view::ViewUpdate;
+use xmp::Xmp;
 
+pub mod check;
+pub mod export;
+mod index;
 pub mod model;
+pub mod query;
+pub mod takeout;
+#[cfg(any(test, feature = "test-support"))]
+pub mod test_support;
+pub mod text;
+mod time;
+pub mod view;
+pub mod xmp;
+
+/// Hex encoded SHA-256 hash of the content of the file, used to relink moved files.
+pub fn hash_file(path: impl AsRef<Path>) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut BufReader::new(File::open(path)?), &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
 
 fn read_or_create<T>(path: &Path, file_name: impl AsRef<Path>) -> Result<T>
 where
@@ -35,13 +71,65 @@ where
     Ok(writer.flush()?)
 }
 
+/// Locks for reading. Data is only changed by complete assignments or inserts,
+/// so it stays usable if a thread panicked while holding the lock.
+fn lock_read<T>(lock: &RwLock<T>) -> RwLockReadGuard<'_, T> {
+    lock.read().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Locks for writing, see [lock_read].
+fn lock_write<T>(lock: &RwLock<T>) -> RwLockWriteGuard<'_, T> {
+    lock.write().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Locks the mutex, see [lock_read].
+fn lock<T>(lock: &Mutex<T>) -> MutexGuard<'_, T> {
+    lock.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Meta data of persons, albums and files.
+///
+/// The repository can be shared between threads, e.g. in an [Arc]. Collections are read as
+/// snapshots (e.g. [Repository::persons]) and changed with closures (e.g. [Repository::edit_persons]).
+/// The data of each folder has its own lock, so files in different folders can be read and
+/// changed at the same time. Closures passed to the repository must not use the repository
+/// themselves, as the locks are not reentrant.
 pub struct Repository {
     data_path: PathBuf,
-    persons: PersonCollection,
-    root_folders: RootFolderCollection,
+    persons: RwLock<Arc<PersonCollection>>,
+    root_folders: RwLock<Arc<RootFolderCollection>>,
+    smart_albums: RwLock<Arc<SmartAlbumCollection>>,
+    albums: RwLock<Arc<AlbumCollection>>,
 
     /// Cache of folder data. Not all folder data is loaded when setting up the repository.
-    folders: HashMap<PathBuf, Folder>,
+    ///
+    /// Holds at most [Repository::set_folder_cache_size] folders that are not in use, the least
+    /// recently used ones are written if they were changed and removed from the cache.
+    folders: Mutex<LruCache<PathBuf, Arc<RwLock<Folder>>>>,
+    folder_cache_size: AtomicUsize,
+
+    /// Folders containing folder data per root folder that was indexed.
+    ///
+    /// Finding all folder data of a root folder is expensive, so it is only done
+    /// once per root folder. Folders removed from the cache are read again when needed.
+    index: RwLock<HashMap<RootFolderId, RootFolderIndex>>,
+
+    /// Persons and tags of the files of all folders read so far, see [Repository::query].
+    file_index: RwLock<FileIndex>,
+}
+
+/// Folders of a root folder found by [Repository::index] or created in this session.
+#[derive(Default)]
+struct RootFolderIndex {
+    /// Paths of the folders containing folder data.
+    folders: BTreeSet<PathBuf>,
+
+    /// Folders or folder data that could not be read with the error, see [Problem::UnreadableFolder].
+    unreadable: Vec<(PathBuf, String)>,
+
+    /// Whether the root folder was searched for folder data, otherwise only the folders created
+    /// in this session are known.
+    complete: bool,
 }
 
 // TODO: Add write-lock file to data_path.
@@ -51,125 +139,1055 @@ pub struct Repository {
 impl Repository {
     const PERSONS_FILENAME: &str = "persons.json";
     const ROOT_FOLDERS_FILENAME: &str = "root_folders.json";
+    const SMART_ALBUMS_FILENAME: &str = "smart_albums.json";
+    const ALBUMS_FILENAME: &str = "albums.json";
     const FOLDER_FILENAME: &str = ".jpfolder.json";
 
+    /// Default number of folders kept in the cache, see [Repository::set_folder_cache_size].
+    pub const DEFAULT_FOLDER_CACHE_SIZE: NonZeroUsize = match NonZeroUsize::new(4096) {
+        Some(size) => size,
+        None => unreachable!(),
+    };
+
     pub fn load_or_create(data_path: PathBuf) -> Result<Self> {
         fs::create_dir_all(&data_path)?;
 
         let persons = read_or_create(&data_path, Self::PERSONS_FILENAME)?;
         let root_folders = read_or_create(&data_path, Self::ROOT_FOLDERS_FILENAME)?;
+        let smart_albums = read_or_create(&data_path, Self::SMART_ALBUMS_FILENAME)?;
+        let albums = read_or_create(&data_path, Self::ALBUMS_FILENAME)?;
 
         Ok(Self {
             data_path,
-            persons,
-            root_folders,
-            folders: HashMap::with_capacity(0),
+            persons: RwLock::new(Arc::new(persons)),
+            root_folders: RwLock::new(Arc::new(root_folders)),
+            smart_albums: RwLock::new(Arc::new(smart_albums)),
+            albums: RwLock::new(Arc::new(albums)),
+            folders: Mutex::new(LruCache::unbounded()),
+            folder_cache_size: AtomicUsize::new(Self::DEFAULT_FOLDER_CACHE_SIZE.get()),
+            index: RwLock::default(),
+            file_index: RwLock::default(),
         })
     }
 
     pub fn save(&self) -> Result<()> {
         self.save_persons()?;
         self.save_root_folders()?;
+        self.save_smart_albums()?;
+        self.save_albums()?;
         self.save_file_data()?;
         Ok(())
     }
 
-    pub fn persons(&self) -> &PersonCollection {
-        &self.persons
+    /// Snapshot of the persons that is not affected by later changes.
+    pub fn persons(&self) -> Arc<PersonCollection> {
+        lock_read(&self.persons).clone()
     }
 
-    pub fn persons_mut(&mut self) -> &mut PersonCollection {
-        &mut self.persons
+    /// Changes the persons. Snapshots taken before are not affected.
+    pub fn edit_persons<R>(&self, edit: impl FnOnce(&mut PersonCollection) -> R) -> R {
+        let mut persons = lock_write(&self.persons);
+        let ids: Vec<_> = persons.entries().keys().copied().collect();
+        let result = edit(Arc::make_mut(&mut persons));
+        if ids.iter().any(|id| persons.person(id).is_none()) {
+            lock_write(&self.file_index).retain_persons(|id| persons.person(id).is_some());
+        }
+        result
     }
 
     pub fn save_persons(&self) -> Result<()> {
-        write(&self.data_path, Self::PERSONS_FILENAME, &self.persons)
+        write(&self.data_path, Self::PERSONS_FILENAME, &*self.persons())
     }
 
-    pub fn root_folders(&self) -> &RootFolderCollection {
-        &self.root_folders
+    /// Snapshot of the root folders that is not affected by later changes.
+    pub fn root_folders(&self) -> Arc<RootFolderCollection> {
+        lock_read(&self.root_folders).clone()
     }
 
-    pub fn root_folders_mut(&mut self) -> &mut RootFolderCollection {
-        &mut self.root_folders
+    /// Changes the root folders. Snapshots taken before are not affected.
+    pub fn edit_root_folders<R>(&self, edit: impl FnOnce(&mut RootFolderCollection) -> R) -> R {
+        edit(Arc::make_mut(&mut lock_write(&self.root_folders)))
     }
 
     pub fn save_root_folders(&self) -> Result<()> {
         write(
             &self.data_path,
             Self::ROOT_FOLDERS_FILENAME,
-            &self.root_folders,
+            &*self.root_folders(),
+        )
+    }
+
+    /// Snapshot of the smart albums that is not affected by later changes.
+    pub fn smart_albums(&self) -> Arc<SmartAlbumCollection> {
+        lock_read(&self.smart_albums).clone()
+    }
+
+    /// Changes the smart albums. Snapshots taken before are not affected.
+    pub fn edit_smart_albums<R>(&self, edit: impl FnOnce(&mut SmartAlbumCollection) -> R) -> R {
+        edit(Arc::make_mut(&mut lock_write(&self.smart_albums)))
+    }
+
+    pub fn save_smart_albums(&self) -> Result<()> {
+        write(
+            &self.data_path,
+            Self::SMART_ALBUMS_FILENAME,
+            &*self.smart_albums(),
         )
     }
 
+    /// Snapshot of the albums that is not affected by later changes.
+    pub fn albums(&self) -> Arc<AlbumCollection> {
+        lock_read(&self.albums).clone()
+    }
+
+    /// Changes the albums. Snapshots taken before are not affected.
+    pub fn edit_albums<R>(&self, edit: impl FnOnce(&mut AlbumCollection) -> R) -> R {
+        edit(Arc::make_mut(&mut lock_write(&self.albums)))
+    }
+
+    pub fn save_albums(&self) -> Result<()> {
+        write(&self.data_path, Self::ALBUMS_FILENAME, &*self.albums())
+    }
+
+    /// Creates a reference to the file at the given path for use in an [Album](model::Album).
+    pub fn file_reference(
+        &self,
+        root_folder_id: &RootFolderId,
+        path: impl AsRef<Path>,
+    ) -> Result<FileReference> {
+        let root_folder = self.root_folder(root_folder_id)?;
+        let absolute_path = path.as_ref().canonicalize()?;
+        let relative_path = absolute_path
+            .strip_prefix(root_folder)
+            .map_err(|_| Error::FileNotInRootFolder)?;
+
+        let hash = match self
+            .read_file(&absolute_path, |file| file.hash.clone())
+            .flatten()
+        {
+            Some(hash) => hash,
+            None => {
+                let hash = hash_file(&absolute_path)?;
+                self.edit_file(root_folder_id, &absolute_path, |file| {
+                    file.hash = Some(hash.clone())
+                })?;
+                hash
+            }
+        };
+
+        Ok(FileReference {
+            root_folder: *root_folder_id,
+            path: relative_path.to_path_buf(),
+            hash: Some(hash),
+        })
+    }
+
+    /// Returns the absolute path of the referenced file.
+    ///
+    /// If the file does not exist at its path anymore, the indexed files of its root folder
+    /// are searched for an existing file with the same hash. If none is found, the media files
+    /// of the root folder without a known hash are hashed and compared.
+    /// Returns `None` if the file cannot be found.
+    pub fn resolve(&self, file: &FileReference) -> Result<Option<PathBuf>> {
+        let root_folder = self.root_folder(&file.root_folder)?;
+        let path = root_folder.join(&file.path);
+        if path.try_exists()? {
+            return Ok(Some(path));
+        }
+
+        let Some(hash) = &file.hash else {
+            return Ok(None);
+        };
+        self.index(&file.root_folder)?;
+        let mut hashed = HashSet::new();
+        for path in self.folder_paths(&file.root_folder) {
+            let Some(folder) = self.folder(&path)? else {
+                continue;
+            };
+            let folder = lock_read(&folder);
+            for (file_name, meta_file) in &folder.files {
+                let Some(file_hash) = &meta_file.hash else {
+                    continue;
+                };
+                let path = folder.path.join(file_name);
+                if file_hash == hash && path.try_exists()? {
+                    return Ok(Some(path));
+                }
+                hashed.insert(path);
+            }
+        }
+
+        // The file was moved without this tool, so its meta data still has the old path.
+        let mut media_files = Vec::new();
+        find_media_files(&root_folder, &mut media_files);
+        Ok(media_files.into_iter().find(|path| {
+            !hashed.contains(path) && hash_file(path).is_ok_and(|file_hash| file_hash == *hash)
+        }))
+    }
+
+    /// Returns the absolute paths of the files of the album that can be found (see [Self::resolve]).
+    ///
+    /// Files that were relinked by their hash are stored with their new path in the album.
+    pub fn resolve_album(&self, id: &AlbumId) -> Result<Vec<PathBuf>> {
+        let album = self.albums().album(id).ok_or(Error::InvalidAlbum)?.clone();
+        let mut paths = Vec::with_capacity(album.files.len());
+        let mut relinked = Vec::new();
+        for file in &album.files {
+            let Some(path) = self.resolve(file)? else {
+                continue;
+            };
+            let root_folder = self.root_folder(&file.root_folder)?;
+            if let Ok(relative_path) = path.strip_prefix(&root_folder) {
+                if relative_path != file.path {
+                    let mut moved = file.clone();
+                    moved.path = relative_path.to_path_buf();
+                    relinked.push((file.clone(), moved));
+                }
+            }
+            paths.push(path);
+        }
+
+        if !relinked.is_empty() {
+            self.edit_albums(|albums| {
+                let Some(album) = albums.album_mut(id) else {
+                    return;
+                };
+                for file in album.files.iter_mut().chain(album.cover.as_mut()) {
+                    if let Some((_, moved)) = relinked.iter().find(|(old, _)| old == file) {
+                        *file = moved.clone();
+                    }
+                }
+            });
+        }
+        Ok(paths)
+    }
+
+    /// Writes the data of all folders in the cache that were changed.
     pub fn save_file_data(&self) -> Result<()> {
-        for folder in self.folders.values() {
-            write(&folder.path, Self::FOLDER_FILENAME, folder)?;
+        let folders: Vec<_> = lock(&self.folders)
+            .iter()
+            .map(|(_, folder)| folder.clone())
+            .collect();
+        for folder in folders {
+            let mut folder = lock_write(&folder);
+            if folder.dirty {
+                write(&folder.path, Self::FOLDER_FILENAME, &*folder)?;
+                folder.dirty = false;
+            }
         }
         Ok(())
     }
 
-    pub fn load_or_create_file(
-        &mut self,
+    /// Limits the number of folders kept in the cache. Folders that do not fit anymore
+    /// are written if they were changed and read again when needed.
+    pub fn set_folder_cache_size(&self, folders: NonZeroUsize) -> Result<()> {
+        self.folder_cache_size
+            .store(folders.get(), Ordering::Relaxed);
+        self.evict()
+    }
+
+    /// Changes the meta data of the file at the given path, which gets created if it does not exist.
+    ///
+    /// Only the folder of the file is locked while `edit` runs. The folder is only written
+    /// again if the meta data was created or changed.
+    pub fn edit_file<R>(
+        &self,
         root_folder_id: &RootFolderId,
         path: impl AsRef<Path>,
-    ) -> Result<&mut MetaFile> {
-        let root_folder = self
-            .root_folders
-            .root_folder(root_folder_id)
-            .ok_or(Error::InvalidRootFolder)?;
+        edit: impl FnOnce(&mut MetaFile) -> R,
+    ) -> Result<R> {
+        let root_folder = self.root_folder(root_folder_id)?;
         let absolute_path = path.as_ref().canonicalize()?;
         if !absolute_path.starts_with(root_folder) {
             return Err(Error::FileNotInRootFolder);
         }
 
         let folder_path = absolute_path.parent().ok_or(Error::InvalidRootFolder)?;
-        let folder = Self::load_or_create_folder(&mut self.folders, folder_path, root_folder_id)?;
-
-        let file_name = absolute_path
-            .file_name()
-            .ok_or(Error::InvalidFilePath)?
-            .to_os_string();
-        Ok(folder
-            .files
-            .entry(file_name.into())
-            .or_insert_with(|| MetaFile {
-                hash: None,
-                persons: HashSet::with_capacity(0),
-                tags: HashMap::with_capacity(0),
-            }))
+        let file_name = absolute_path.file_name().ok_or(Error::InvalidFilePath)?;
+        let folder = self.load_or_create_folder(folder_path, root_folder_id)?;
+        let mut folder = lock_write(&folder);
+        let existed = folder.files.contains_key(Path::new(file_name));
+        let file = folder.files.entry(file_name.into()).or_default();
+        let unchanged = file.clone();
+        let result = edit(file);
+        let (before, after) = (index::Entry::of(&unchanged), index::Entry::of(file));
+        folder.dirty |= !existed || folder.files[Path::new(file_name)] != unchanged;
+        if before != after {
+            let mut file_index = lock_write(&self.file_index);
+            file_index.remove(&absolute_path, &before);
+            file_index.insert(&absolute_path, &after);
+        }
+        Ok(result)
     }
 
-    fn load_or_create_folder<'a>(
-        folder_cache: &'a mut HashMap<PathBuf, Folder>,
+    /// Writes the meta data of the file at the given absolute path into an XMP sidecar next to it.
+    ///
+    /// Returns the path of the sidecar, or `None` if the file has no meta data.
+    /// Existing sidecars written by other applications are not overwritten.
+    pub fn export_xmp(&self, path: impl AsRef<Path>) -> Result<Option<PathBuf>> {
+        let path = path.as_ref();
+        let Some(file) = self.load_file(path)? else {
+            return Ok(None);
+        };
+        let sidecar = xmp::sidecar_path(path);
+        if sidecar.try_exists()? && !xmp::is_own_sidecar(&fs::read_to_string(&sidecar)?) {
+            return Err(Error::ForeignSidecar(sidecar));
+        }
+        let xml = Xmp::from_meta_file(&file, &self.persons()).to_xml();
+        fs::write(&sidecar, xml)?;
+        Ok(Some(sidecar))
+    }
+
+    /// Merges the XMP sidecar of the file at the given path into its meta data (see [Xmp::apply]).
+    ///
+    /// Persons are matched by name and created if they do not exist.
+    /// Returns `false` if the file has no sidecar.
+    pub fn import_xmp(
+        &self,
+        root_folder_id: &RootFolderId,
         path: impl AsRef<Path>,
+    ) -> Result<bool> {
+        let Some(sidecar) = xmp::find_sidecar(&path)? else {
+            return Ok(false);
+        };
+        let xmp = Xmp::parse(&fs::read_to_string(sidecar)?)?;
+        let person_ids = self.edit_persons(|persons| xmp.person_ids(persons));
+        self.edit_file(root_folder_id, path, |file| xmp.apply(file, &person_ids))?;
+        Ok(true)
+    }
+
+    /// Imports the JSON sidecars of a Google Takeout export in the root folder into the meta data
+    /// of the images they belong to. Images in album folders get tagged with the album title
+    /// (see [takeout::ALBUM_TAG]).
+    ///
+    /// Persons are matched by name and created if they do not exist.
+    pub fn import_takeout(&self, root_folder_id: &RootFolderId) -> Result<TakeoutImport> {
+        let root_folder = self.root_folder(root_folder_id)?;
+        let mut folders = Vec::new();
+        takeout::read_folders(&root_folder, &mut folders)?;
+
+        // All persons are created at once, as every edit of the persons updates the file index.
+        let person_ids: Vec<_> = self.edit_persons(|persons| {
+            folders
+                .iter()
+                .map(|folder| folder.person_ids(persons))
+                .collect()
+        });
+        let mut import = TakeoutImport::default();
+        for (folder, person_ids) in folders.iter_mut().zip(person_ids) {
+            for (path, sidecar) in folder.images() {
+                self.edit_file(root_folder_id, path, |file| {
+                    folder.apply(sidecar, file, &person_ids[sidecar])
+                })?;
+                import.imported += 1;
+            }
+            import.unmatched.append(&mut folder.unmatched);
+        }
+        Ok(import)
+    }
+
+    /// Collects the meta data of the files at the given absolute paths for a report,
+    /// e.g. the result of [Repository::query]. Files without meta data get empty rows.
+    pub fn report(&self, paths: &[PathBuf]) -> Result<Vec<ReportRow>> {
+        let persons = self.persons();
+        let mut rows = Vec::with_capacity(paths.len());
+        for path in paths {
+            let modified = fs::metadata(path)
+                .and_then(|metadata| metadata.modified())
+                .ok()
+                .map(time::format_local_time);
+            let mut row = ReportRow {
+                path: path.clone(),
+                hash: None,
+                persons: Vec::new(),
+                tags: Vec::new(),
+                rating: None,
+                date_taken: None,
+                modified,
+            };
+
+            if let Some(file) = self.load_file(path)? {
+                row.hash = file.hash.clone();
+                row.persons = file
+                    .persons
+                    .iter()
+                    .filter_map(|id| persons.person(id))
+                    .map(|person| person.name.clone())
+                    .collect();
+                row.persons.sort();
+                row.tags = file
+                    .tag_values()
+                    .map(|(key, value)| match value {
+                        Some(value) => format!("{key}={value}"),
+                        None => key.to_string(),
+                    })
+                    .collect();
+                row.tags.sort();
+                row.rating = file.rating;
+                row.date_taken = file.exif.as_ref().and_then(|exif| exif.date_taken.clone());
+            }
+            rows.push(row);
+        }
+        Ok(rows)
+    }
+
+    /// Creates or updates a tree of links to the files of the root folder, with a folder per
+    /// person and per tag (see [view::PERSON_FOLDER] and [view::TAG_FOLDER]), e.g. for media servers.
+    ///
+    /// Symbolic links are used if possible, otherwise hard links or copies. With `incremental`,
+    /// existing links are kept and only links of files that were untagged or removed get deleted.
+    /// Otherwise the person and tag folders of the view are created from scratch. Fails without
+    /// changing anything if they contain files that were not created for the view.
+    /// The view folder must not be inside the root folder, as the links would be found as images.
+    pub fn update_view(
+        &self,
         root_folder_id: &RootFolderId,
-    ) -> Result<&'a mut Folder> {
+        view: impl AsRef<Path>,
+        incremental: bool,
+    ) -> Result<ViewUpdate> {
+        let root_folder = self.root_folder(root_folder_id)?;
+        fs::create_dir_all(&view)?;
+        let view = view.as_ref().canonicalize()?;
+        if view.starts_with(&root_folder) {
+            return Err(Error::ViewInRootFolder);
+        }
+
+        self.index(root_folder_id)?;
+        let persons = self.persons();
+        let mut files = Vec::new();
+        for path in self.folder_paths(root_folder_id) {
+            let Some(folder) = self.folder(&path)? else {
+                continue;
+            };
+            let folder = lock_read(&folder);
+            for (file_name, file) in &folder.files {
+                let persons = file
+                    .persons
+                    .iter()
+                    .filter_map(|id| persons.person(id))
+                    .map(|person| person.name.clone())
+                    .collect();
+                let tags = file
+                    .tag_values()
+                    .map(|(key, value)| (key.to_string(), value.map(str::to_string)))
+                    .collect();
+                files.push((folder.path.join(file_name), persons, tags));
+            }
+        }
+        let mut existing = Vec::with_capacity(files.len());
+        for file in files {
+            if file.0.try_exists()? {
+                existing.push(file);
+            }
+        }
+
+        let links = view::plan(existing);
+        view::update(&view, &links, incremental)
+    }
+
+    /// Moves the meta data of a file that was moved or renamed on disk to its new path,
+    /// see [Repository::move_files].
+    pub fn move_file(
+        &self,
+        root_folder_id: &RootFolderId,
+        from: impl AsRef<Path>,
+        to: impl AsRef<Path>,
+    ) -> Result<()> {
+        self.move_files(root_folder_id, &[(from, to)])
+    }
+
+    /// Moves the meta data of files that were moved or renamed on disk to their new paths.
+    ///
+    /// All paths have to be inside the root folder and the files have to be at their new paths
+    /// already, otherwise nothing is changed. The meta data of all files is taken before it is
+    /// stored at the new paths, so files can swap their paths. References to the files in albums
+    /// are updated as well.
+    pub fn move_files(
+        &self,
+        root_folder_id: &RootFolderId,
+        moves: &[(impl AsRef<Path>, impl AsRef<Path>)],
+    ) -> Result<()> {
+        let root_folder = self.root_folder(root_folder_id)?;
+        let mut resolved = Vec::with_capacity(moves.len());
+        let mut relative_paths = HashMap::with_capacity(moves.len());
+        for (from, to) in moves {
+            let from = from.as_ref();
+            let from_name = from.file_name().ok_or(Error::InvalidFilePath)?;
+            let from_folder = from
+                .parent()
+                .ok_or(Error::InvalidFilePath)?
+                .canonicalize()?;
+            let from = from_folder.join(from_name);
+            let to = to.as_ref().canonicalize()?;
+            let to_folder = to.parent().ok_or(Error::InvalidFilePath)?;
+            let from_relative = from
+                .strip_prefix(&root_folder)
+                .map_err(|_| Error::FileNotInRootFolder)?;
+            let to_relative = to
+                .strip_prefix(&root_folder)
+                .map_err(|_| Error::FileNotInRootFolder)?;
+            relative_paths.insert(from_relative.to_path_buf(), to_relative.to_path_buf());
+
+            // Folders are loaded before anything is changed, so loading them cannot fail halfway.
+            let Some(source) = self.folder(&from_folder)? else {
+                continue;
+            };
+            if !lock_read(&source).files.contains_key(Path::new(from_name)) {
+                continue;
+            }
+            let target = self.load_or_create_folder(to_folder, root_folder_id)?;
+            resolved.push((source, from, target, to));
+        }
+
+        // Only one folder is locked at a time, so moves in opposite directions cannot deadlock.
+        let mut taken = Vec::with_capacity(resolved.len());
+        for (source, from, target, to) in resolved {
+            let mut folder = lock_write(&source);
+            let Some(file) = folder
+                .files
+                .remove(Path::new(from.file_name().unwrap_or_default()))
+            else {
+                continue;
+            };
+            folder.dirty = true;
+            drop(folder);
+            let entry = index::Entry::of(&file);
+            lock_write(&self.file_index).remove(&from, &entry);
+            taken.push((file, entry, target, to));
+        }
+        for (file, entry, target, to) in taken {
+            let mut folder = lock_write(&target);
+            folder.dirty = true;
+            folder
+                .files
+                .insert(to.file_name().unwrap_or_default().into(), file);
+            drop(folder);
+            lock_write(&self.file_index).insert(&to, &entry);
+        }
+
+        self.edit_albums(|albums| {
+            for album in albums.albums.values_mut() {
+                for reference in album.files.iter_mut().chain(album.cover.iter_mut()) {
+                    if reference.root_folder != *root_folder_id {
+                        continue;
+                    }
+                    if let Some(to) = relative_paths.get(&reference.path) {
+                        reference.path = to.clone();
+                    }
+                }
+            }
+        });
+        Ok(())
+    }
+
+    /// Returns the paths of all files in the given root folders that match the query.
+    ///
+    /// Like in [Repository::matches], files without meta data are treated like files that have no
+    /// data set. If the query matches such files (e.g. `not has:persons`), the root folders are
+    /// searched for media files without meta data (see [is_media_file]). Otherwise queries for
+    /// persons and tags only read the folder data of the files found in the file index.
+    pub fn query(&self, query: &Query, root_folder_ids: &[RootFolderId]) -> Result<Vec<PathBuf>> {
+        let persons = self.persons();
+        let matches_empty = query.matches(&MetaFile::default(), &persons);
+        let mut paths = Vec::new();
+        for root_folder_id in root_folder_ids {
+            self.index(root_folder_id)?;
+            let candidates = lock_read(&self.file_index).candidates(query, &persons);
+            if let Some(candidates) = candidates.filter(|_| !matches_empty) {
+                let root_folder = self.root_folder(root_folder_id)?;
+                paths.extend(candidates.into_iter().filter(|path| {
+                    path.starts_with(&root_folder)
+                        && self
+                            .read_file(path, |file| query.matches(file, &persons))
+                            .unwrap_or(false)
+                }));
+                continue;
+            }
+
+            let mut with_meta = HashSet::new();
+            for path in self.folder_paths(root_folder_id) {
+                let Some(folder) = self.folder(&path)? else {
+                    continue;
+                };
+                let folder = lock_read(&folder);
+                for (file_name, file) in &folder.files {
+                    let path = folder.path.join(file_name);
+                    if query.matches(file, &persons) {
+                        paths.push(path.clone());
+                    }
+                    if matches_empty {
+                        with_meta.insert(path);
+                    }
+                }
+            }
+
+            if matches_empty {
+                let mut files = Vec::new();
+                find_media_files(&self.root_folder(root_folder_id)?, &mut files);
+                paths.extend(files.into_iter().filter(|path| !with_meta.contains(path)));
+            }
+        }
+
+        paths.sort();
+        Ok(paths)
+    }
+
+    /// Like [Repository::query] but ranks the results by how well the captions
+    /// match the text terms of the query (see [Query::text_tokens]), best match first.
+    pub fn search(&self, query: &Query, root_folder_ids: &[RootFolderId]) -> Result<Vec<PathBuf>> {
+        let paths = self.query(query, root_folder_ids)?;
+        let tokens = query.text_tokens();
+        if tokens.is_empty() {
+            return Ok(paths);
+        }
+
+        let mut ranked: Vec<_> = paths
+            .into_iter()
+            .map(|path| (self.caption_score(&tokens, &path), path))
+            .collect();
+        ranked.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+        Ok(ranked.into_iter().map(|(_, path)| path).collect())
+    }
+
+    /// How well the caption of the file at the given absolute path matches the text tokens
+    /// (see [text::score]), `0.0` if the file has no caption.
+    pub fn caption_score(&self, tokens: &[String], path: impl AsRef<Path>) -> f32 {
+        self.read_file(path, |file| {
+            file.caption
+                .as_deref()
+                .map_or(0.0, |caption| text::score(tokens, caption))
+        })
+        .unwrap_or(0.0)
+    }
+
+    /// Returns the paths of all files in the given root folders that are in the smart album.
+    pub fn evaluate_smart_album(
+        &self,
+        id: &SmartAlbumId,
+        root_folder_ids: &[RootFolderId],
+    ) -> Result<Vec<PathBuf>> {
+        let query = self
+            .smart_albums()
+            .album(id)
+            .ok_or(Error::InvalidSmartAlbum)?
+            .parse_query()?;
+        self.query(&query, root_folder_ids)
+    }
+
+    /// Looks for inconsistencies in the meta data of all root folders and albums.
+    ///
+    /// Problems are sorted by root folder and path, followed by the problems of albums.
+    pub fn check(&self) -> Result<Vec<Problem>> {
+        let mut root_folders: Vec<_> = self
+            .root_folders()
+            .entries()
+            .iter()
+            .map(|(id, path)| (*id, path.clone()))
+            .collect();
+        root_folders.sort_by(|(_, a), (_, b)| a.cmp(b));
+
+        let persons = self.persons();
+        let mut problems = Vec::new();
+        let mut missing_root_folders = Vec::new();
+        for (root_folder_id, root_folder) in root_folders {
+            if !root_folder.try_exists()? {
+                problems.push(Problem::MissingRootFolder(root_folder));
+                missing_root_folders.push(root_folder_id);
+                continue;
+            }
+            self.index(&root_folder_id)?;
+            let mut folder_problems: Vec<_> = lock_read(&self.index)
+                .get(&root_folder_id)
+                .map(|index| index.unreadable.clone())
+                .unwrap_or_default()
+                .into_iter()
+                .map(|(path, error)| Problem::UnreadableFolder { path, error })
+                .collect();
+            for path in self.folder_paths(&root_folder_id) {
+                let Some(folder) = self.folder(&path)? else {
+                    continue;
+                };
+                let folder = lock_read(&folder);
+                for (file_name, file) in &folder.files {
+                    let path = folder.path.join(file_name);
+                    if !path.try_exists()? {
+                        folder_problems.push(Problem::MissingFile(path.clone()));
+                    }
+                    let mut unknown: Vec<_> = file
+                        .persons
+                        .iter()
+                        .chain(file.regions.keys())
+                        .filter(|id| persons.person(id).is_none())
+                        .copied()
+                        .collect();
+                    unknown.sort_by_key(|id| id.0);
+                    unknown.dedup();
+                    folder_problems.extend(unknown.into_iter().map(|person| {
+                        Problem::UnknownPerson {
+                            path: path.clone(),
+                            person,
+                        }
+                    }));
+                }
+            }
+            folder_problems.sort_by(|a, b| problem_path(a).cmp(&problem_path(b)));
+            problems.append(&mut folder_problems);
+        }
+
+        let known_root_folders = self.root_folders();
+        let mut albums: Vec<_> = self
+            .albums()
+            .entries()
+            .values()
+            .map(|album| (album.title.clone(), album.files.clone()))
+            .collect();
+        albums.sort_by(|(a, _), (b, _)| a.cmp(b));
+        for (title, files) in albums {
+            for file in files {
+                let known_root_folder = known_root_folders.root_folder(&file.root_folder).is_some()
+                    && !missing_root_folders.contains(&file.root_folder);
+                if !known_root_folder || self.resolve(&file)?.is_none() {
+                    problems.push(Problem::UnresolvedAlbumFile {
+                        album: title.clone(),
+                        path: file.path,
+                    });
+                }
+            }
+        }
+
+        let smart_albums = self.smart_albums();
+        let mut smart_albums: Vec<_> = smart_albums.entries().values().collect();
+        smart_albums.sort_by(|a, b| a.name.cmp(&b.name));
+        for album in smart_albums {
+            if let Err(error) = album.parse_query() {
+                problems.push(Problem::InvalidSmartAlbum {
+                    name: album.name.clone(),
+                    error: error.to_string(),
+                });
+            }
+        }
+        Ok(problems)
+    }
+
+    /// Checks if the file at the given absolute path matches the query.
+    ///
+    /// Files without meta data are treated like files that have no data set,
+    /// see [Repository::read_file].
+    pub fn matches(&self, query: &Query, path: impl AsRef<Path>) -> bool {
+        let persons = self.persons();
+        self.read_file(path, |file| query.matches(file, &persons))
+            .unwrap_or_else(|| query.matches(&MetaFile::default(), &persons))
+    }
+
+    /// Passes the meta data of the file at the given absolute path to `read` if it exists,
+    /// without copying it.
+    ///
+    /// The path is not canonicalized. Loads the folder data from disk if necessary. Folder data
+    /// that cannot be read is treated as missing, [Repository::load_file] returns the error instead.
+    pub fn read_file<R>(
+        &self,
+        path: impl AsRef<Path>,
+        read: impl FnOnce(&MetaFile) -> R,
+    ) -> Option<R> {
         let path = path.as_ref();
-        let owned_path = path.to_path_buf();
-
-        if folder_cache.contains_key(&owned_path) {
-            return Ok(folder_cache.get_mut(&owned_path).unwrap());
-        }
-
-        let meta_path = path.join(Self::FOLDER_FILENAME);
-        if meta_path.try_exists()? {
-            let meta_file = File::open(meta_path)?;
-            let meta_reader = BufReader::new(meta_file);
-            let folder = serde_json::from_reader(meta_reader)?;
-            folder_cache.insert(owned_path.clone(), folder);
-        } else {
-            folder_cache.insert(
-                owned_path.clone(),
-                Folder {
-                    path: owned_path.clone(),
-                    root_folder: *root_folder_id,
-                    files: HashMap::new(),
-                },
-            );
+        let folder = self.folder(path.parent()?).ok().flatten()?;
+        let folder = lock_read(&folder);
+        folder.files.get(Path::new(path.file_name()?)).map(read)
+    }
+
+    /// Returns a copy of the meta data of the file at the given absolute path if it exists.
+    ///
+    /// The path is not canonicalized. See [Repository::read_file] for how errors are handled.
+    pub fn file(&self, path: impl AsRef<Path>) -> Option<MetaFile> {
+        self.read_file(path, MetaFile::clone)
+    }
+
+    /// Returns a copy of the meta data of the file at the given absolute path if it exists.
+    ///
+    /// Loads the folder data from disk if necessary. Unlike [Repository::edit_file]
+    /// this does not create meta data for the file.
+    pub fn load_file(&self, path: impl AsRef<Path>) -> Result<Option<MetaFile>> {
+        let path = path.as_ref();
+        let folder_path = path.parent().ok_or(Error::InvalidFilePath)?;
+        let file_name = path.file_name().ok_or(Error::InvalidFilePath)?;
+        let Some(folder) = self.folder(folder_path)? else {
+            return Ok(None);
+        };
+        let folder = lock_read(&folder);
+        Ok(folder.files.get(Path::new(file_name)).cloned())
+    }
+
+    /// Reads the folder data of the whole root folder in parallel, if this was not done before.
+    ///
+    /// The persons and tags of its files are kept in the file index afterwards, while the folder
+    /// data is kept as far as it fits into the cache. Use [Repository::clear_index]
+    /// if folders were created outside of this repository.
+    ///
+    /// Folders that cannot be read and folder data that cannot be parsed are skipped,
+    /// so a single broken folder does not hide the rest. [Repository::check] reports them.
+    pub fn index(&self, root_folder_id: &RootFolderId) -> Result<()> {
+        let complete = lock_read(&self.index)
+            .get(root_folder_id)
+            .is_some_and(|index| index.complete);
+        if complete {
+            return Ok(());
+        }
+
+        let root_folder = self.root_folder(root_folder_id)?;
+        let mut folder_paths = Vec::new();
+        let mut unreadable = Vec::new();
+        find_folders(&root_folder, &mut folder_paths, &mut unreadable);
+        let failed: Vec<_> = folder_paths
+            .par_iter()
+            .filter_map(|path| {
+                let error = self.folder(path).err()?;
+                Some((path.clone(), error.to_string()))
+            })
+            .collect();
+        let mut folders: BTreeSet<_> = folder_paths.into_iter().collect();
+        for (path, _) in &failed {
+            folders.remove(path);
+        }
+        unreadable.extend(failed);
+
+        // Folders created in this session are only found on disk after they were saved,
+        // so they are kept. Another thread may have finished indexing in the meantime.
+        let mut index = lock_write(&self.index);
+        let index = index.entry(*root_folder_id).or_default();
+        if !index.complete {
+            index.folders.append(&mut folders);
+            index.unreadable = unreadable;
+            index.complete = true;
+        }
+        Ok(())
+    }
+
+    /// Indexes all root folders that exist, e.g. at startup, so queries for persons and tags
+    /// can be answered without reading the folder data (see [Repository::query]).
+    pub fn index_all(&self) -> Result<()> {
+        let root_folder_ids: Vec<_> = self
+            .root_folders()
+            .entries()
+            .iter()
+            .filter(|(_, path)| path.exists())
+            .map(|(id, _)| *id)
+            .collect();
+        root_folder_ids
+            .par_iter()
+            .try_for_each(|root_folder_id| self.index(root_folder_id))
+    }
+
+    /// Forgets which root folders were indexed, so the next [Repository::index] looks for
+    /// folder data again. Folder data in the cache is kept.
+    pub fn clear_index(&self) {
+        for index in lock_write(&self.index).values_mut() {
+            index.complete = false;
+        }
+    }
+
+    fn root_folder(&self, root_folder_id: &RootFolderId) -> Result<PathBuf> {
+        Ok(self
+            .root_folders()
+            .root_folder(root_folder_id)
+            .ok_or(Error::InvalidRootFolder)?
+            .clone())
+    }
+
+    /// Paths of the folders with folder data in the root folder, which has to be indexed.
+    fn folder_paths(&self, root_folder_id: &RootFolderId) -> Vec<PathBuf> {
+        lock_read(&self.index)
+            .get(root_folder_id)
+            .map_or_else(Vec::new, |index| index.folders.iter().cloned().collect())
+    }
+
+    /// Returns the folder data at the given path from the cache or from disk, if it exists.
+    fn folder(&self, path: &Path) -> Result<Option<Arc<RwLock<Folder>>>> {
+        if let Some(folder) = lock(&self.folders).get(path) {
+            return Ok(Some(folder.clone()));
+        }
+        if !path.join(Self::FOLDER_FILENAME).try_exists()? {
+            return Ok(None);
+        }
+
+        let meta_file = File::open(path.join(Self::FOLDER_FILENAME))?;
+        let mut folder: Folder = serde_json::from_reader(BufReader::new(meta_file))?;
+        folder.path = path.to_path_buf();
+        Ok(Some(self.cache_folder(folder)))
+    }
+
+    fn load_or_create_folder(
+        &self,
+        path: &Path,
+        root_folder_id: &RootFolderId,
+    ) -> Result<Arc<RwLock<Folder>>> {
+        if let Some(folder) = self.folder(path)? {
+            return Ok(folder);
+        }
+        let folder = self.cache_folder(Folder {
+            path: path.to_path_buf(),
+            root_folder: *root_folder_id,
+            files: HashMap::new(),
+            dirty: false,
+        });
+        // Recorded even if the root folder is being indexed, as the folder data may be written
+        // by the cache only after the root folder was searched.
+        lock_write(&self.index)
+            .entry(*root_folder_id)
+            .or_default()
+            .folders
+            .insert(path.to_path_buf());
+        Ok(folder)
+    }
+
+    /// Adds the folder to the cache and its files to the file index,
+    /// unless another thread added it in the meantime.
+    fn cache_folder(&self, folder: Folder) -> Arc<RwLock<Folder>> {
+        let mut folders = lock(&self.folders);
+        if let Some(cached) = folders.get(&folder.path) {
+            return cached.clone();
+        }
+
+        let mut file_index = lock_write(&self.file_index);
+        for (file_name, file) in &folder.files {
+            file_index.insert(&folder.path.join(file_name), &index::Entry::of(file));
+        }
+        drop(file_index);
+
+        let path = folder.path.clone();
+        let folder = Arc::new(RwLock::new(folder));
+        folders.put(path, folder.clone());
+        drop(folders);
+        // Folders that cannot be written stay in the cache, so loading this folder does not fail
+        // because of them. Their error is returned by the next [Repository::save].
+        let _ = self.evict();
+        folder
+    }
+
+    /// Removes the least recently used folders from the cache until it fits
+    /// its size (see [Repository::set_folder_cache_size]). Changed folders are written first,
+    /// without locking the cache.
+    ///
+    /// Folders that are used by other threads are kept, so their changes are not lost.
+    /// They are removed once they are not used anymore and the cache is full again.
+    /// Folders that cannot be written are kept as well and the first error is returned.
+    fn evict(&self) -> Result<()> {
+        let changed = {
+            let mut folders = lock(&self.folders);
+            let size = self.folder_cache_size.load(Ordering::Relaxed);
+            let unused: Vec<_> = folders
+                .iter()
+                .rev()
+                .filter(|(_, folder)| Arc::strong_count(folder) == 1)
+                .take(folders.len().saturating_sub(size))
+                .map(|(path, folder)| (path.clone(), folder.clone()))
+                .collect();
+            let mut changed = Vec::new();
+            for (path, folder) in unused {
+                // No other thread can lock the folder, as only the cache and this thread have it.
+                if lock_read(&folder).dirty {
+                    changed.push((path, folder));
+                } else {
+                    folders.pop(&path);
+                }
+            }
+            changed
+        };
+
+        let mut result = Ok(());
+        for (path, folder) in changed {
+            let mut data = lock_write(&folder);
+            if data.dirty {
+                if let Err(error) = write(&data.path, Self::FOLDER_FILENAME, &*data) {
+                    result = result.and(Err(error));
+                    continue;
+                }
+                data.dirty = false;
+            }
+            drop(data);
+
+            // The folder may have been used and changed again while it was written.
+            let mut folders = lock(&self.folders);
+            let cached = folders
+                .peek(&path)
+                .is_some_and(|cached| Arc::ptr_eq(cached, &folder));
+            if cached && Arc::strong_count(&folder) == 2 && !lock_read(&folder).dirty {
+                folders.pop(&path);
+            }
         }
+        result
+    }
+}
+
+/// Collects the folders containing folder data below the path.
+///
+/// Folders that cannot be read are collected with the error in `unreadable` and skipped.
+fn find_folders(path: &Path, found: &mut Vec<PathBuf>, unreadable: &mut Vec<(PathBuf, String)>) {
+    let mut subfolders = Vec::new();
+    let result = path
+        .join(Repository::FOLDER_FILENAME)
+        .try_exists()
+        .and_then(|has_folder_data| {
+            if has_folder_data {
+                found.push(path.to_path_buf());
+            }
+            read_folder(path, &mut subfolders)
+        });
+    if let Err(error) = result {
+        unreadable.push((path.to_path_buf(), error.to_string()));
+    }
+    for subfolder in subfolders {
+        find_folders(&subfolder, found, unreadable);
+    }
+}
 
-        Ok(folder_cache.get_mut(&owned_path).unwrap())
+/// Collects the subfolders of the folder, also the ones read before an error occurred.
+fn read_folder(path: &Path, subfolders: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            subfolders.push(entry.path());
+        }
+    }
+    Ok(())
+}
+
+/// Checks the extension for image and video formats, which are the files the meta data is about.
+pub fn is_media_file(path: impl AsRef<Path>) -> bool {
+    const EXTENSIONS: [&str; 18] = [
+        "jpg", "jpeg", "png", "gif", "webp", "tif", "tiff", "bmp", "heic", "heif", "avif", "raw",
+        "dng", "cr2", "nef", "mp4", "mov", "avi",
+    ];
+    path.as_ref()
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| {
+            EXTENSIONS
+                .iter()
+                .any(|known| extension.eq_ignore_ascii_case(known))
+        })
+}
+
+/// Collects the media files below the path. Folders that cannot be read are skipped
+/// like in [find_folders].
+fn find_media_files(path: &Path, found: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(path) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        match entry.file_type() {
+            Ok(file_type) if file_type.is_dir() => find_media_files(&entry.path(), found),
+            Ok(_) if is_media_file(entry.path()) => found.push(entry.path()),
+            _ => {}
+        }
+    }
+}
+
+fn problem_path(problem: &Problem) -> Option<&Path> {
+    match problem {
+        Problem::MissingFile(path)
+        | Problem::UnknownPerson { path, .. }
+        | Problem::UnreadableFolder { path, .. } => Some(path),
+        _ => None,
     }
 }
 
@@ -192,5 +1210,462 @@ pub enum Error {
 
     #[error("given path does not contain a file name")]
     InvalidFilePath,
+
+    #[error("given smart album does not exist")]
+    InvalidSmartAlbum,
+
+    #[error("given album does not exist")]
+    InvalidAlbum,
+
+    #[error("view folder must not be inside the root folder")]
+    ViewInRootFolder,
+
+    #[error("{0} was not created for the view, move or remove it first")]
+    ForeignFileInView(PathBuf),
+
+    #[error("xmp parsing error: {0}")]
+    XmlError(#[from] roxmltree::Error),
+
+    #[error("xmp prefix {0} is already used for another namespace")]
+    XmpPrefixConflict(String),
+
+    #[error("{0} was written by another application, import it instead of overwriting it")]
+    ForeignSidecar(PathBuf),
+
+    #[error("invalid query: {0}")]
+    InvalidQuery(String),
 }
 pub type Result<T> = std::result::Result<T, Error>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use model::{Album, PersonId, SmartAlbum};
+    use test_support::TempFolder;
+
+    /// Repository in `data` with the folder `library` as root folder.
+    fn repository(folder: &TempFolder) -> (Repository, RootFolderId) {
+        let meta = Repository::load_or_create(folder.join("data")).unwrap();
+        let root_folder_id = meta
+            .edit_root_folders(|root_folders| root_folders.get_or_create(folder.join("library")))
+            .unwrap();
+        (meta, root_folder_id)
+    }
+
+    #[test]
+    fn repository_is_shareable() {
+        fn assert_shareable<T: Send + Sync>() {}
+        assert_shareable::<Repository>();
+    }
+
+    #[test]
+    fn query_treats_files_without_meta_data_like_matches() {
+        let folder = TempFolder::new("query").with_files(&[
+            "library/a.jpg",
+            "library/b/c.JPG",
+            "library/b/d.jpg",
+            "library/notes.txt",
+        ]);
+        let (meta, root_folder_id) = repository(&folder);
+        let library = folder.join("library");
+        meta.edit_file(&root_folder_id, library.join("a.jpg"), |file| {
+            file.persons.insert(PersonId(0))
+        })
+        .unwrap();
+        meta.edit_file(&root_folder_id, library.join("b/d.jpg"), |file| {
+            file.rating = Some(3)
+        })
+        .unwrap();
+
+        for (query, expected) in [
+            ("has:persons", vec!["a.jpg"]),
+            ("not has:persons", vec!["b/c.JPG", "b/d.jpg"]),
+            ("rating:<3", vec!["a.jpg", "b/c.JPG"]),
+            ("", vec!["a.jpg", "b/c.JPG", "b/d.jpg"]),
+        ] {
+            let query = Query::parse(query).unwrap();
+            let paths = meta.query(&query, &[root_folder_id]).unwrap();
+            let expected: Vec<_> = expected.iter().map(|path| library.join(path)).collect();
+            assert_eq!(expected, paths, "{query:?}");
+            for path in &paths {
+                assert!(meta.matches(&query, path), "{query:?} {path:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn resolve_album_relinks_moved_files_by_hash() {
+        let folder = TempFolder::new("relink").with_files(&["library/b.jpg"]);
+        fs::write(folder.join("library/a.jpg"), "a").unwrap();
+        let (meta, root_folder_id) = repository(&folder);
+        let library = folder.join("library");
+        let file = meta
+            .file_reference(&root_folder_id, library.join("a.jpg"))
+            .unwrap();
+        assert_eq!(Some(hash_file(library.join("a.jpg")).unwrap()), file.hash);
+        assert_eq!(file.hash, meta.file(library.join("a.jpg")).unwrap().hash);
+        let id = meta.edit_albums(|albums| {
+            let id = albums.add(model::Album::new("Album".into()));
+            albums.album_mut(&id).unwrap().add(file);
+            id
+        });
+
+        fs::create_dir(library.join("moved")).unwrap();
+        fs::rename(library.join("a.jpg"), library.join("moved/a.jpg")).unwrap();
+
+        assert_eq!(
+            vec![library.join("moved/a.jpg")],
+            meta.resolve_album(&id).unwrap()
+        );
+        let albums = meta.albums();
+        let album = albums.album(&id).unwrap();
+        let moved = meta
+            .file_reference(&root_folder_id, library.join("moved/a.jpg"))
+            .unwrap();
+        assert_eq!(Some(0), album.position(&moved));
+        assert_eq!(Some(&moved), album.cover.as_ref());
+    }
+
+    #[test]
+    fn export_xmp_keeps_sidecars_of_other_applications() {
+        let folder = TempFolder::new("xmp").with_files(&["library/a.jpg", "library/b.jpg"]);
+        let (meta, root_folder_id) = repository(&folder);
+        let library = folder.join("library");
+        for name in ["a.jpg", "b.jpg"] {
+            meta.edit_file(&root_folder_id, library.join(name), |file| {
+                file.rating = Some(2)
+            })
+            .unwrap();
+        }
+        let darktable = r#"<x:xmpmeta xmlns:x="adobe:ns:meta/" x:xmptk="XMP Core 4.4.0-Exiv2"/>"#;
+        fs::write(library.join("b.jpg.xmp"), darktable).unwrap();
+
+        let sidecar = meta.export_xmp(library.join("a.jpg")).unwrap().unwrap();
+        meta.edit_file(&root_folder_id, library.join("a.jpg"), |file| {
+            file.rating = Some(3)
+        })
+        .unwrap();
+        assert_eq!(
+            Some(sidecar.clone()),
+            meta.export_xmp(library.join("a.jpg")).unwrap()
+        );
+        let xmp = Xmp::parse(&fs::read_to_string(sidecar).unwrap()).unwrap();
+        assert_eq!(Some(3), xmp.rating);
+
+        assert!(matches!(
+            meta.export_xmp(library.join("b.jpg")),
+            Err(Error::ForeignSidecar(_))
+        ));
+        assert_eq!(
+            darktable,
+            fs::read_to_string(library.join("b.jpg.xmp")).unwrap()
+        );
+    }
+
+    #[test]
+    fn update_view_refuses_to_remove_foreign_files() {
+        let folder = TempFolder::new("view").with_files(&["library/a.jpg"]);
+        let (meta, root_folder_id) = repository(&folder);
+        let library = folder.join("library");
+        fs::write(library.join("a.jpg"), "image").unwrap();
+        meta.edit_file(&root_folder_id, library.join("a.jpg"), |file| {
+            file.add_tag("holiday", None)
+        })
+        .unwrap();
+        let view = folder.join("view");
+        let update = meta.update_view(&root_folder_id, &view, false).unwrap();
+        assert_eq!(1, update.created);
+
+        // Copies created for the view are recognized by their content.
+        let link = view.join("by-tag/holiday/a.jpg");
+        fs::remove_file(&link).unwrap();
+        fs::write(&link, "image").unwrap();
+        let update = meta.update_view(&root_folder_id, &view, true).unwrap();
+        assert_eq!((0, 1), (update.created, update.unchanged));
+
+        let notes = view.join("by-tag/holiday/notes.txt");
+        fs::write(&notes, "keep me").unwrap();
+        for incremental in [false, true] {
+            assert!(matches!(
+                meta.update_view(&root_folder_id, &view, incremental),
+                Err(Error::ForeignFileInView(path)) if path == notes
+            ));
+        }
+        assert!(link.exists());
+        assert_eq!("keep me", fs::read_to_string(&notes).unwrap());
+    }
+
+    #[test]
+    fn check_finds_inconsistencies() {
+        let folder = TempFolder::new("check").with_files(&["library/a.jpg", "gone/c.jpg"]);
+        let (meta, root_folder_id) = repository(&folder);
+        let library = folder.join("library");
+        fs::write(library.join("b.jpg"), "b").unwrap();
+        meta.edit_file(&root_folder_id, library.join("a.jpg"), |file| {
+            file.persons.insert(PersonId(7))
+        })
+        .unwrap();
+        meta.edit_file(&root_folder_id, library.join("b.jpg"), |file| {
+            file.rating = Some(1)
+        })
+        .unwrap();
+        let mut album = Album::new("Trip".into());
+        for name in ["a.jpg", "b.jpg"] {
+            album.add(
+                meta.file_reference(&root_folder_id, library.join(name))
+                    .unwrap(),
+            );
+        }
+        meta.edit_albums(|albums| albums.add(album));
+        meta.edit_smart_albums(|albums| {
+            albums.add(SmartAlbum::new("Broken".into(), "(person:a".into()))
+        });
+        let gone = folder.join("gone");
+        meta.edit_root_folders(|root_folders| root_folders.get_or_create(&gone))
+            .unwrap();
+        fs::remove_dir_all(&gone).unwrap();
+        fs::remove_file(library.join("b.jpg")).unwrap();
+
+        let problems = meta.check().unwrap();
+        assert_eq!(5, problems.len(), "{problems:?}");
+        assert_eq!(Problem::MissingRootFolder(gone), problems[0]);
+        assert_eq!(
+            Problem::UnknownPerson {
+                path: library.join("a.jpg"),
+                person: PersonId(7)
+            },
+            problems[1]
+        );
+        assert_eq!(Problem::MissingFile(library.join("b.jpg")), problems[2]);
+        assert_eq!(
+            Problem::UnresolvedAlbumFile {
+                album: "Trip".into(),
+                path: "b.jpg".into()
+            },
+            problems[3]
+        );
+        assert!(
+            matches!(&problems[4], Problem::InvalidSmartAlbum { name, .. } if name == "Broken")
+        );
+    }
+
+    #[test]
+    fn concurrent_edits_queries_and_moves_stay_consistent() {
+        let names: Vec<_> = (0..40).map(|index| format!("{index}.jpg")).collect();
+        let files: Vec<_> = ["a", "b"]
+            .iter()
+            .flat_map(|folder| {
+                names
+                    .iter()
+                    .map(move |name| format!("library/{folder}/{name}"))
+            })
+            .collect();
+        let files: Vec<_> = files.iter().map(String::as_str).collect();
+        let folder = TempFolder::new("concurrent").with_files(&files);
+        fs::create_dir(folder.join("library/c")).unwrap();
+        let (meta, root_folder_id) = repository(&folder);
+        // Folders get evicted and loaded again while they are used.
+        meta.set_folder_cache_size(NonZeroUsize::new(1).unwrap())
+            .unwrap();
+        let library = folder.join("library");
+        let rated = Query::parse("rating:3").unwrap();
+
+        std::thread::scope(|scope| {
+            for subfolder in ["a", "b"] {
+                let (meta, names, library) = (&meta, &names, &library);
+                scope.spawn(move || {
+                    for name in names {
+                        let path = library.join(subfolder).join(name);
+                        meta.edit_file(&root_folder_id, path, |file| file.rating = Some(3))
+                            .unwrap();
+                    }
+                });
+            }
+            scope.spawn(|| {
+                for _ in 0..20 {
+                    let paths = meta.query(&rated, &[root_folder_id]).unwrap();
+                    assert!(paths.len() <= names.len() * 2);
+                    assert!(paths.iter().all(|path| meta.matches(&rated, path)));
+                }
+            });
+        });
+        assert_eq!(
+            names.len() * 2,
+            meta.query(&rated, &[root_folder_id]).unwrap().len()
+        );
+
+        let persons = meta.persons();
+        std::thread::scope(|scope| {
+            scope.spawn(|| {
+                for name in &names {
+                    let (from, to) = (library.join("a").join(name), library.join("c").join(name));
+                    fs::rename(&from, &to).unwrap();
+                    meta.move_file(&root_folder_id, &from, &to).unwrap();
+                }
+            });
+            scope.spawn(|| {
+                for name in &names {
+                    meta.edit_file(&root_folder_id, library.join("b").join(name), |file| {
+                        file.add_tag("moved", None)
+                    })
+                    .unwrap();
+                }
+            });
+            scope.spawn(|| {
+                for name in &names {
+                    meta.edit_persons(|persons| persons.get_or_create(name));
+                }
+            });
+        });
+
+        // Snapshots taken before are not affected by changes.
+        assert!(persons.entries().is_empty());
+        assert_eq!(names.len(), meta.persons().entries().len());
+
+        meta.save().unwrap();
+        let (meta, root_folder_id) = repository(&folder);
+        let mut expected = Vec::new();
+        for subfolder in ["b", "c"] {
+            expected.extend(names.iter().map(|name| library.join(subfolder).join(name)));
+        }
+        expected.sort();
+        let mut paths = meta.query(&rated, &[root_folder_id]).unwrap();
+        paths.sort();
+        assert_eq!(expected, paths);
+        let tagged = Query::parse("tag:moved").unwrap();
+        assert_eq!(
+            names.len(),
+            meta.query(&tagged, &[root_folder_id]).unwrap().len()
+        );
+        assert_eq!(names.len(), meta.persons().entries().len());
+    }
+
+    #[test]
+    fn query_persons_and_tags_with_the_file_index() {
+        let folder = TempFolder::new("file-index").with_files(&[
+            "library/a/1.jpg",
+            "library/a/2.jpg",
+            "library/b/3.jpg",
+        ]);
+        let (meta, root_folder_id) = repository(&folder);
+        let library = folder.join("library");
+        let anna = meta.edit_persons(|persons| persons.get_or_create("Anna"));
+        meta.edit_file(&root_folder_id, library.join("a/1.jpg"), |file| {
+            file.persons.insert(anna);
+        })
+        .unwrap();
+        meta.edit_file(&root_folder_id, library.join("b/3.jpg"), |file| {
+            file.persons.insert(anna);
+            file.add_tag("Trip", Some("Rome"));
+        })
+        .unwrap();
+        meta.save().unwrap();
+
+        let (meta, root_folder_id) = repository(&folder);
+        meta.set_folder_cache_size(NonZeroUsize::new(1).unwrap())
+            .unwrap();
+        let query = |query: &str| {
+            let query = Query::parse(query).unwrap();
+            let paths = meta.query(&query, &[root_folder_id]).unwrap();
+            let paths: Vec<_> = paths
+                .iter()
+                .map(|path| path.strip_prefix(&library).unwrap().to_path_buf())
+                .collect();
+            paths
+        };
+        let paths = |paths: &[&str]| -> Vec<PathBuf> { paths.iter().map(PathBuf::from).collect() };
+        assert_eq!(paths(&["a/1.jpg", "b/3.jpg"]), query("person:anna"));
+        assert_eq!(paths(&["b/3.jpg"]), query("has:tags and person:Anna"));
+        assert_eq!(paths(&["b/3.jpg"]), query("tag:trip=rome"));
+        assert_eq!(paths(&[]), query("tag:trip=paris"));
+
+        // Files keep the id of removed persons, see [Repository::check].
+        meta.edit_persons(|persons| persons.remove(&anna));
+        assert_eq!(paths(&[]), query("person:anna"));
+        assert_eq!(
+            paths(&["a/1.jpg", "b/3.jpg"]),
+            query(&format!("person:#{}", anna.0))
+        );
+    }
+
+    #[test]
+    fn unchanged_files_are_not_written() {
+        let folder = TempFolder::new("unchanged").with_files(&["library/a.jpg"]);
+        let (meta, root_folder_id) = repository(&folder);
+        let path = folder.join("library/a.jpg");
+        let dirty = || lock_read(&meta.folder(path.parent().unwrap()).unwrap().unwrap()).dirty;
+
+        meta.edit_file(&root_folder_id, &path, |_| ()).unwrap();
+        assert!(dirty(), "new meta data has to be written");
+        meta.save().unwrap();
+        meta.edit_file(&root_folder_id, &path, |file| file.rating = None)
+            .unwrap();
+        assert!(!dirty());
+        meta.edit_file(&root_folder_id, &path, |file| file.rating = Some(2))
+            .unwrap();
+        assert!(dirty());
+    }
+
+    #[test]
+    fn folders_that_cannot_be_written_stay_in_the_cache() {
+        let folder = TempFolder::new("evict").with_files(&["library/a/a.jpg", "library/b/b.jpg"]);
+        let (meta, root_folder_id) = repository(&folder);
+        meta.set_folder_cache_size(NonZeroUsize::new(1).unwrap())
+            .unwrap();
+        let library = folder.join("library");
+        meta.edit_file(&root_folder_id, library.join("a/a.jpg"), |file| {
+            file.rating = Some(1)
+        })
+        .unwrap();
+        // The folder data of `a` cannot be written anymore.
+        fs::remove_dir_all(library.join("a")).unwrap();
+
+        for _ in 0..2 {
+            meta.edit_file(&root_folder_id, library.join("b/b.jpg"), |file| {
+                file.rating = Some(2)
+            })
+            .unwrap();
+        }
+        assert_eq!(
+            Some(1),
+            meta.file(library.join("a/a.jpg"))
+                .and_then(|file| file.rating)
+        );
+        assert!(meta.save().is_err());
+
+        fs::create_dir(library.join("a")).unwrap();
+        meta.save().unwrap();
+        let (meta, _) = repository(&folder);
+        for (path, rating) in [("a/a.jpg", 1), ("b/b.jpg", 2)] {
+            let file = meta.load_file(library.join(path)).unwrap().unwrap();
+            assert_eq!(Some(rating), file.rating);
+        }
+    }
+
+    #[test]
+    fn index_skips_unreadable_folder_data() {
+        let folder =
+            TempFolder::new("unreadable").with_files(&["library/a/a.jpg", "library/b/b.jpg"]);
+        let (meta, root_folder_id) = repository(&folder);
+        let library = folder.join("library");
+        meta.edit_file(&root_folder_id, library.join("a/a.jpg"), |file| {
+            file.rating = Some(1)
+        })
+        .unwrap();
+        meta.save().unwrap();
+        fs::write(library.join("b").join(Repository::FOLDER_FILENAME), "{").unwrap();
+
+        let (meta, root_folder_id) = repository(&folder);
+        let query = Query::parse("rating:1").unwrap();
+        assert_eq!(
+            vec![library.join("a/a.jpg")],
+            meta.query(&query, &[root_folder_id]).unwrap()
+        );
+        let problems = meta.check().unwrap();
+        assert!(
+            matches!(&problems[..], [Problem::UnreadableFolder { path, .. }] if *path == library.join("b")),
+            "{problems:?}"
+        );
+    }
+}
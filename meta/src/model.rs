@@ -1,4 +1,4 @@
-use crate::Result;
+use crate::{query::Query, text, Result};
 
 use std::{
     collections::{HashMap, HashSet},
@@ -8,11 +8,14 @@ use std::{
 use serde::{Deserialize, Serialize};
 use serde_with::{serde_as, skip_serializing_none};
 
+/// Tag key under which the events of a [MetaFile] are stored.
+pub const EVENT_TAG: &str = "event";
+
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Hash, Default, Copy, Clone)]
 pub struct PersonId(pub usize);
 
 #[skip_serializing_none]
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Person {
     pub name: String,
     pub pronouns: Option<String>,
@@ -23,7 +26,7 @@ pub struct Person {
     pub tags: HashMap<String, Vec<String>>,
 }
 
-#[derive(Debug, Default, Serialize, Deserialize)]
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
 pub struct PersonCollection {
     pub(crate) next_id: PersonId,
     pub(crate) persons: HashMap<PersonId, Person>,
@@ -38,7 +41,7 @@ pub struct PersonCollection {
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Hash, Default, Copy, Clone)]
 pub struct RootFolderId(usize);
 
-#[derive(Debug, Default, Serialize, Deserialize)]
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
 pub struct RootFolderCollection {
     pub(crate) next_id: RootFolderId,
     pub(crate) root_folders: HashMap<RootFolderId, PathBuf>,
@@ -46,6 +49,58 @@ pub struct RootFolderCollection {
     pub file_tags: HashSet<String>,
 }
 
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Hash, Default, Copy, Clone)]
+pub struct SmartAlbumId(pub usize);
+
+/// Named query that gets evaluated on demand.
+#[skip_serializing_none]
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SmartAlbum {
+    pub name: String,
+
+    /// Query in the syntax of [Query].
+    pub query: String,
+    pub comment: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+pub struct SmartAlbumCollection {
+    pub(crate) next_id: SmartAlbumId,
+    pub(crate) albums: HashMap<SmartAlbumId, SmartAlbum>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Hash, Default, Copy, Clone)]
+pub struct AlbumId(pub usize);
+
+/// Reference to a file that can be relinked by its hash if the file was moved.
+#[skip_serializing_none]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct FileReference {
+    pub root_folder: RootFolderId,
+
+    /// Path relative to the root folder.
+    pub path: PathBuf,
+    pub hash: Option<String>,
+}
+
+/// Hand-curated album of files in a custom order.
+#[skip_serializing_none]
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Album {
+    pub title: String,
+    pub cover: Option<FileReference>,
+    pub comment: Option<String>,
+
+    #[serde(default)]
+    pub files: Vec<FileReference>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+pub struct AlbumCollection {
+    pub(crate) next_id: AlbumId,
+    pub(crate) albums: HashMap<AlbumId, Album>,
+}
+
 #[serde_as]
 #[skip_serializing_none]
 #[derive(Debug, Serialize, Deserialize)]
@@ -54,10 +109,14 @@ pub(crate) struct Folder {
     pub(crate) root_folder: RootFolderId,
     #[serde_as(as = "Vec<(_, _)>")]
     pub(crate) files: HashMap<PathBuf, MetaFile>,
+
+    /// Set when files were changed since the folder data was last written.
+    #[serde(skip)]
+    pub(crate) dirty: bool,
 }
 
 #[skip_serializing_none]
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq, Clone)]
 pub struct MetaFile {
     pub hash: Option<String>,
 
@@ -68,6 +127,198 @@ pub struct MetaFile {
     #[serde(default)]
     #[serde(skip_serializing_if = "HashMap::is_empty")]
     pub tags: HashMap<String, Vec<String>>,
+
+    /// Star rating from 0 to [MetaFile::MAX_RATING]. Files without rating are unrated.
+    pub rating: Option<u8>,
+    pub flag: Option<Flag>,
+    pub label: Option<ColorLabel>,
+
+    /// Free-text description of the file.
+    pub caption: Option<String>,
+
+    /// Areas of the image that show persons of [MetaFile::persons].
+    #[serde(default)]
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub regions: HashMap<PersonId, Vec<Region>>,
+
+    /// Copy of meta data embedded in the file, so it can be queried without reading the file.
+    pub exif: Option<Exif>,
+}
+
+/// Selected fields of the EXIF meta data embedded in an image.
+#[skip_serializing_none]
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq, Clone)]
+pub struct Exif {
+    /// Date and time when the image was taken in the format `YYYY-MM-DDTHH:MM:SS`.
+    pub date_taken: Option<String>,
+    pub camera: Option<String>,
+    pub lens: Option<String>,
+
+    /// Exposure time in seconds, e.g. `1/250`.
+    pub exposure_time: Option<String>,
+    pub f_number: Option<f32>,
+    pub iso: Option<u32>,
+
+    /// Focal length in millimetres.
+    pub focal_length: Option<f32>,
+
+    /// GPS coordinates in degrees, positive for north and east.
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+}
+
+/// Rectangle inside of an image.
+///
+/// Coordinates are relative to the size of the image and range from 0 to 1,
+/// with the origin in the top left corner.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Copy, Clone)]
+pub struct Region {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Hash, Copy, Clone)]
+#[serde(rename_all = "lowercase")]
+pub enum Flag {
+    Favourite,
+    Pick,
+    Reject,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Hash, Copy, Clone)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorLabel {
+    Red,
+    Yellow,
+    Green,
+    Blue,
+    Purple,
+}
+
+impl MetaFile {
+    pub const MAX_RATING: u8 = 5;
+
+    /// Pairs of tag key and value. Tags without values are returned once with `None` as value.
+    pub fn tag_values(&self) -> impl Iterator<Item = (&str, Option<&str>)> {
+        self.tags.iter().flat_map(|(key, values)| {
+            let values: Vec<_> = if values.is_empty() {
+                vec![None]
+            } else {
+                values.iter().map(|value| Some(value.as_str())).collect()
+            };
+            values.into_iter().map(move |value| (key.as_str(), value))
+        })
+    }
+
+    /// Adds the tag, with the value if one is given and the tag does not have it yet.
+    pub fn add_tag(&mut self, key: &str, value: Option<&str>) {
+        let values = self.tags.entry(key.to_string()).or_default();
+        if let Some(value) = value {
+            if !values.iter().any(|existing| existing == value) {
+                values.push(value.to_string());
+            }
+        }
+    }
+
+    /// Removes the value of the tag, or the whole tag if no value is given.
+    ///
+    /// Removing the last value removes the whole tag, so it does not become a tag without value.
+    pub fn remove_tag(&mut self, key: &str, value: Option<&str>) {
+        let Some(value) = value else {
+            self.tags.remove(key);
+            return;
+        };
+        if let Some(values) = self.tags.get_mut(key) {
+            values.retain(|existing| existing != value);
+            if values.is_empty() {
+                self.tags.remove(key);
+            }
+        }
+    }
+
+    /// Adds the person to the file and marks the region as showing the person.
+    pub fn add_person_region(&mut self, person: PersonId, region: Region) {
+        self.persons.insert(person);
+        self.regions.entry(person).or_default().push(region);
+    }
+
+    /// Removes the region of the person, keeping the person in the file.
+    ///
+    /// Returns the removed region, or `None` if the person has no region at this index.
+    pub fn remove_person_region(&mut self, person: PersonId, index: usize) -> Option<Region> {
+        let regions = self.regions.get_mut(&person)?;
+        if index >= regions.len() {
+            return None;
+        }
+        let region = regions.remove(index);
+        if regions.is_empty() {
+            self.regions.remove(&person);
+        }
+        Some(region)
+    }
+}
+
+impl Exif {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    pub fn coordinates(&self) -> Option<(f64, f64)> {
+        self.latitude.zip(self.longitude)
+    }
+}
+
+impl Region {
+    /// Creates the region spanned by two corners, clamped to the image.
+    pub fn from_corners(a: (f32, f32), b: (f32, f32)) -> Self {
+        let (x0, x1) = (a.0.min(b.0).clamp(0.0, 1.0), a.0.max(b.0).clamp(0.0, 1.0));
+        let (y0, y1) = (a.1.min(b.1).clamp(0.0, 1.0), a.1.max(b.1).clamp(0.0, 1.0));
+        Self {
+            x: x0,
+            y: y0,
+            width: x1 - x0,
+            height: y1 - y0,
+        }
+    }
+
+    /// Whether the point with coordinates relative to the image lies inside of the region.
+    pub fn contains(&self, (x, y): (f32, f32)) -> bool {
+        (self.x..=self.x + self.width).contains(&x) && (self.y..=self.y + self.height).contains(&y)
+    }
+}
+
+impl Flag {
+    pub const ALL: [Flag; 3] = [Flag::Favourite, Flag::Pick, Flag::Reject];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Flag::Favourite => "favourite",
+            Flag::Pick => "pick",
+            Flag::Reject => "reject",
+        }
+    }
+}
+
+impl ColorLabel {
+    pub const ALL: [ColorLabel; 5] = [
+        ColorLabel::Red,
+        ColorLabel::Yellow,
+        ColorLabel::Green,
+        ColorLabel::Blue,
+        ColorLabel::Purple,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ColorLabel::Red => "red",
+            ColorLabel::Yellow => "yellow",
+            ColorLabel::Green => "green",
+            ColorLabel::Blue => "blue",
+            ColorLabel::Purple => "purple",
+        }
+    }
 }
 
 impl PersonCollection {
@@ -93,6 +344,18 @@ impl PersonCollection {
     pub fn person_mut(&mut self, id: &mut PersonId) -> Option<&mut Person> {
         self.persons.get_mut(id)
     }
+
+    /// Returns the id of the person with the given name, adding the person if no one has this name.
+    ///
+    /// Names are compared ignoring case, so `anna` from another application matches `Anna`.
+    pub fn get_or_create(&mut self, name: &str) -> PersonId {
+        let existing = self
+            .persons
+            .iter()
+            .find(|(_, person)| text::equals_ignore_case(&person.name, name))
+            .map(|(id, _)| *id);
+        existing.unwrap_or_else(|| self.add(Person::new(name.to_string(), None)))
+    }
 }
 
 impl Person {
@@ -106,6 +369,122 @@ impl Person {
     }
 }
 
+impl SmartAlbumCollection {
+    pub fn entries(&self) -> &HashMap<SmartAlbumId, SmartAlbum> {
+        &self.albums
+    }
+
+    pub fn add(&mut self, album: SmartAlbum) -> SmartAlbumId {
+        let id = self.next_id;
+        self.albums.insert(id, album);
+        self.next_id.0 += 1;
+        id
+    }
+
+    pub fn remove(&mut self, id: &SmartAlbumId) -> Option<SmartAlbum> {
+        self.albums.remove(id)
+    }
+
+    pub fn album(&self, id: &SmartAlbumId) -> Option<&SmartAlbum> {
+        self.albums.get(id)
+    }
+
+    pub fn album_mut(&mut self, id: &SmartAlbumId) -> Option<&mut SmartAlbum> {
+        self.albums.get_mut(id)
+    }
+}
+
+impl SmartAlbum {
+    pub fn new(name: String, query: String) -> Self {
+        Self {
+            name,
+            query,
+            comment: None,
+        }
+    }
+
+    pub fn parse_query(&self) -> Result<Query> {
+        Query::parse(&self.query)
+    }
+}
+
+impl AlbumCollection {
+    pub fn entries(&self) -> &HashMap<AlbumId, Album> {
+        &self.albums
+    }
+
+    pub fn add(&mut self, album: Album) -> AlbumId {
+        let id = self.next_id;
+        self.albums.insert(id, album);
+        self.next_id.0 += 1;
+        id
+    }
+
+    pub fn remove(&mut self, id: &AlbumId) -> Option<Album> {
+        self.albums.remove(id)
+    }
+
+    pub fn album(&self, id: &AlbumId) -> Option<&Album> {
+        self.albums.get(id)
+    }
+
+    pub fn album_mut(&mut self, id: &AlbumId) -> Option<&mut Album> {
+        self.albums.get_mut(id)
+    }
+}
+
+impl Album {
+    pub fn new(title: String) -> Self {
+        Self {
+            title,
+            cover: None,
+            comment: None,
+            files: Vec::new(),
+        }
+    }
+
+    pub fn position(&self, file: &FileReference) -> Option<usize> {
+        self.files
+            .iter()
+            .position(|entry| entry.root_folder == file.root_folder && entry.path == file.path)
+    }
+
+    /// Appends the file to the album if it is not already contained.
+    ///
+    /// The first file that gets added becomes the cover of the album.
+    pub fn add(&mut self, file: FileReference) {
+        if self.position(&file).is_some() {
+            return;
+        }
+        if self.cover.is_none() {
+            self.cover = Some(file.clone());
+        }
+        self.files.push(file);
+    }
+
+    /// Removes the file from the album.
+    ///
+    /// If the file was the cover, the first remaining file becomes the cover.
+    pub fn remove(&mut self, file: &FileReference) -> Option<FileReference> {
+        let position = self.position(file)?;
+        let removed = self.files.remove(position);
+        if self.cover.as_ref().is_some_and(|cover| {
+            cover.root_folder == removed.root_folder && cover.path == removed.path
+        }) {
+            self.cover = self.files.first().cloned();
+        }
+        Some(removed)
+    }
+
+    /// Moves the file at index `from` to index `to`, shifting the files in between.
+    pub fn move_file(&mut self, from: usize, to: usize) {
+        if from < self.files.len() && to < self.files.len() {
+            let file = self.files.remove(from);
+            self.files.insert(to, file);
+        }
+    }
+}
+
 impl RootFolderCollection {
     pub fn entries(&self) -> &HashMap<RootFolderId, PathBuf> {
         &self.root_folders
@@ -113,12 +492,7 @@ impl RootFolderCollection {
 
     pub fn get_or_create(&mut self, path: impl AsRef<Path>) -> Result<RootFolderId> {
         let path = path.as_ref().canonicalize()?;
-        let root_folder = self
-            .root_folders
-            .iter()
-            .find_map(|(&id, root_folder)| path.starts_with(root_folder).then_some(id));
-
-        Ok(root_folder.unwrap_or_else(|| {
+        Ok(self.find(&path).unwrap_or_else(|| {
             let id = self.next_id;
             self.root_folders.insert(id, path);
             self.next_id.0 += 1;
@@ -126,6 +500,13 @@ impl RootFolderCollection {
         }))
     }
 
+    /// Returns the root folder containing the given absolute path.
+    pub fn find(&self, path: impl AsRef<Path>) -> Option<RootFolderId> {
+        self.root_folders
+            .iter()
+            .find_map(|(&id, root_folder)| path.as_ref().starts_with(root_folder).then_some(id))
+    }
+
     pub fn remove(&mut self, id: &RootFolderId) -> Option<PathBuf> {
         self.root_folders.remove(id)
     }
@@ -138,3 +519,123 @@ impl RootFolderCollection {
         self.root_folders.get_mut(id)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn region(x: f32, y: f32, width: f32, height: f32) -> Region {
+        Region {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    #[test]
+    fn region_from_corners() {
+        assert_eq!(
+            region(0.25, 0.5, 0.5, 0.25),
+            Region::from_corners((0.25, 0.5), (0.75, 0.75))
+        );
+        assert_eq!(
+            region(0.25, 0.5, 0.5, 0.25),
+            Region::from_corners((0.75, 0.75), (0.25, 0.5))
+        );
+        assert_eq!(
+            region(0.25, 0.5, 0.5, 0.25),
+            Region::from_corners((0.75, 0.5), (0.25, 0.75))
+        );
+    }
+
+    #[test]
+    fn region_from_corners_is_clamped_to_image() {
+        assert_eq!(
+            region(0.0, 0.5, 0.75, 0.5),
+            Region::from_corners((-0.5, 1.5), (0.75, 0.5))
+        );
+        assert_eq!(
+            region(1.0, 0.0, 0.0, 0.0),
+            Region::from_corners((1.5, -1.0), (2.0, -0.5))
+        );
+    }
+
+    #[test]
+    fn regions_survive_serialization() {
+        let mut file = MetaFile::default();
+        file.add_person_region(PersonId(3), region(0.25, 0.5, 0.5, 0.25));
+        file.add_person_region(PersonId(3), region(0.0, 0.0, 0.125, 0.125));
+        file.add_person_region(PersonId(12), region(0.5, 0.5, 0.5, 0.5));
+
+        let json = serde_json::to_string(&file).unwrap();
+        let read: MetaFile = serde_json::from_str(&json).unwrap();
+        assert_eq!(file.persons, read.persons);
+        assert_eq!(file.regions, read.regions);
+    }
+
+    #[test]
+    fn remove_person_region() {
+        let mut file = MetaFile::default();
+        file.add_person_region(PersonId(1), region(0.0, 0.0, 0.5, 0.5));
+        file.add_person_region(PersonId(1), region(0.5, 0.5, 0.5, 0.5));
+
+        assert_eq!(None, file.remove_person_region(PersonId(1), 2));
+        assert_eq!(None, file.remove_person_region(PersonId(2), 0));
+        assert_eq!(
+            Some(region(0.0, 0.0, 0.5, 0.5)),
+            file.remove_person_region(PersonId(1), 0)
+        );
+        assert_eq!(vec![region(0.5, 0.5, 0.5, 0.5)], file.regions[&PersonId(1)]);
+        file.remove_person_region(PersonId(1), 0);
+        assert!(file.regions.is_empty());
+        assert!(file.persons.contains(&PersonId(1)));
+    }
+
+    #[test]
+    fn get_or_create_ignores_case() {
+        let mut persons = PersonCollection::default();
+        let anna = persons.add(Person::new("Anna".into(), None));
+        assert_eq!(anna, persons.get_or_create("anna"));
+        assert_eq!(anna, persons.get_or_create("ANNA"));
+        let ben = persons.get_or_create("Ben");
+        assert_ne!(anna, ben);
+        assert_eq!(2, persons.entries().len());
+    }
+
+    #[test]
+    fn removing_the_cover_moves_it_to_the_first_file() {
+        let reference = |path: &str| FileReference {
+            root_folder: RootFolderId(0),
+            path: path.into(),
+            hash: None,
+        };
+        let mut album = Album::new("Holiday".into());
+        for path in ["a.jpg", "b.jpg", "c.jpg"] {
+            album.add(reference(path));
+        }
+        album.cover = Some(reference("b.jpg"));
+
+        album.remove(&reference("a.jpg"));
+        assert_eq!(
+            Some("b.jpg".into()),
+            album.cover.as_ref().map(|cover| cover.path.clone())
+        );
+        album.remove(&reference("b.jpg"));
+        assert_eq!(
+            Some("c.jpg".into()),
+            album.cover.as_ref().map(|cover| cover.path.clone())
+        );
+        album.remove(&reference("c.jpg"));
+        assert!(album.cover.is_none());
+    }
+
+    #[test]
+    fn region_contains_points() {
+        let region = region(0.25, 0.25, 0.5, 0.25);
+        assert!(region.contains((0.25, 0.25)));
+        assert!(region.contains((0.5, 0.4)));
+        assert!(!region.contains((0.8, 0.4)));
+        assert!(!region.contains((0.5, 0.6)));
+    }
+}
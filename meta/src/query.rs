@@ -0,0 +1,686 @@
+use crate::{
+    model::{ColorLabel, Flag, MetaFile, PersonCollection, PersonId, EVENT_TAG},
+    text, Error, Result,
+};
+
+use std::{
+    iter::Peekable,
+    str::{Chars, FromStr},
+};
+
+/// Boolean expression over the meta data of a file.
+///
+/// Queries are parsed from strings such as
+/// `person:Anna and person:Ben and not person:Carl and tag:location=Rome`.
+/// The following terms are supported:
+///
+/// - `person:Name` or `person:#3` matches files containing the given person by name or id.
+/// - `tag:key` matches files with the given tag key, `tag:key=value` additionally checks the value.
+/// - `event:Name` matches files with the given event (stored as tag with the key `event`).
+/// - `hash:value` matches files with the given hash.
+/// - `rating:3` matches files with the given star rating. The rating can be prefixed with
+///   `<`, `<=`, `>=` or `>` for comparisons, e.g. `rating:>=3`. Unrated files count as 0 stars.
+/// - `flag:favourite`, `flag:pick` and `flag:reject` match files with the given flag.
+/// - `label:red` matches files with the given colour label.
+/// - `caption:sunset` (or `text:sunset`) matches files whose caption contains words starting
+///   with every word of the value, e.g. `caption:"sun beach"`.
+/// - `date:2023-05` matches files taken at the given date (or month or year), using the EXIF
+///   data stored in the meta data. Supports the same comparisons as ratings, e.g. `date:<2020`.
+/// - `camera:canon` and `lens:50mm` match files whose camera or lens contain the value.
+/// - `has:persons`, `has:tags`, `has:hash`, `has:rating`, `has:flag`, `has:label`,
+///   `has:caption`, `has:exif` and `has:gps` match files for which the given data is set.
+/// - Words without a key match person names, tag keys, tag values and words in captions.
+///
+/// Terms can be combined with `and` (`&`), `or` (`|`), `not` (`!`, `-`) and parentheses.
+/// Terms next to each other without an operator are combined with `and`.
+/// Values containing spaces can be quoted: `person:"Anna Maria"`.
+/// All comparisons are case-insensitive. The empty query matches every file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Query {
+    All,
+    Person(PersonMatcher),
+    Tag {
+        key: String,
+        value: Option<String>,
+    },
+    Hash(String),
+    Has(Property),
+    Rating(Comparison, u8),
+    Flag(Flag),
+    Label(ColorLabel),
+    /// Lowercase words that all have to appear in the caption, see [text::contains_all].
+    Text(Vec<String>),
+    /// Compares the start of the date taken (`YYYY-MM-DDTHH:MM:SS`) with the given value.
+    Date(Comparison, String),
+    Camera(String),
+    Lens(String),
+    Any(String),
+    Not(Box<Query>),
+    And(Box<Query>, Box<Query>),
+    Or(Box<Query>, Box<Query>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PersonMatcher {
+    Id(PersonId),
+    Name(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Property {
+    Persons,
+    Tags,
+    Hash,
+    Rating,
+    Flag,
+    Label,
+    Caption,
+    Exif,
+    Gps,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Less,
+    LessOrEqual,
+    Equal,
+    GreaterOrEqual,
+    Greater,
+}
+
+impl Comparison {
+    pub fn compare<T: Ord>(&self, a: T, b: T) -> bool {
+        match self {
+            Comparison::Less => a < b,
+            Comparison::LessOrEqual => a <= b,
+            Comparison::Equal => a == b,
+            Comparison::GreaterOrEqual => a >= b,
+            Comparison::Greater => a > b,
+        }
+    }
+
+    /// Splits a leading comparison operator off the value. Values without operator compare for equality.
+    pub fn split(value: &str) -> (Self, &str) {
+        [
+            ("<=", Comparison::LessOrEqual),
+            (">=", Comparison::GreaterOrEqual),
+            ("<", Comparison::Less),
+            (">", Comparison::Greater),
+            ("=", Comparison::Equal),
+        ]
+        .into_iter()
+        .find_map(|(prefix, comparison)| {
+            value.strip_prefix(prefix).map(|value| (comparison, value))
+        })
+        .unwrap_or((Comparison::Equal, value))
+    }
+}
+
+impl Query {
+    pub fn parse(input: &str) -> Result<Self> {
+        let mut parser = Parser {
+            tokens: tokenize(input)?.into_iter().peekable(),
+        };
+        if parser.tokens.peek().is_none() {
+            return Ok(Query::All);
+        }
+
+        let query = parser.parse_or()?;
+        match parser.tokens.next() {
+            None => Ok(query),
+            Some(token) => Err(Error::InvalidQuery(format!("unexpected {token}"))),
+        }
+    }
+
+    pub fn matches(&self, file: &MetaFile, persons: &PersonCollection) -> bool {
+        match self {
+            Query::All => true,
+            Query::Person(PersonMatcher::Id(id)) => file.persons.contains(id),
+            Query::Person(PersonMatcher::Name(name)) => {
+                file.persons.iter().any(|id| has_name(persons, id, name))
+            }
+            Query::Tag { key, value } => file.tags.iter().any(|(tag_key, values)| {
+                text::equals_ignore_case(tag_key, key)
+                    && value.as_ref().is_none_or(|value| {
+                        values
+                            .iter()
+                            .any(|tag_value| text::equals_ignore_case(tag_value, value))
+                    })
+            }),
+            Query::Hash(hash) => file
+                .hash
+                .as_ref()
+                .is_some_and(|file_hash| text::equals_ignore_case(file_hash, hash)),
+            Query::Has(Property::Persons) => !file.persons.is_empty(),
+            Query::Has(Property::Tags) => !file.tags.is_empty(),
+            Query::Has(Property::Hash) => file.hash.is_some(),
+            Query::Has(Property::Rating) => file.rating.is_some(),
+            Query::Has(Property::Flag) => file.flag.is_some(),
+            Query::Has(Property::Label) => file.label.is_some(),
+            Query::Has(Property::Caption) => file.caption.is_some(),
+            Query::Has(Property::Exif) => file.exif.is_some(),
+            Query::Has(Property::Gps) => file
+                .exif
+                .as_ref()
+                .is_some_and(|exif| exif.coordinates().is_some()),
+            Query::Date(comparison, date) => file
+                .exif
+                .as_ref()
+                .and_then(|exif| exif.date_taken.as_ref())
+                .is_some_and(|date_taken| {
+                    let length = date.len().min(date_taken.len());
+                    date_taken
+                        .get(..length)
+                        .is_some_and(|prefix| comparison.compare(prefix, date.as_str()))
+                }),
+            Query::Camera(camera) => file
+                .exif
+                .as_ref()
+                .and_then(|exif| exif.camera.as_ref())
+                .is_some_and(|value| contains_ignore_case(value, camera)),
+            Query::Lens(lens) => file
+                .exif
+                .as_ref()
+                .and_then(|exif| exif.lens.as_ref())
+                .is_some_and(|value| contains_ignore_case(value, lens)),
+            Query::Rating(comparison, rating) => {
+                comparison.compare(file.rating.unwrap_or(0), *rating)
+            }
+            Query::Flag(flag) => file.flag == Some(*flag),
+            Query::Label(label) => file.label == Some(*label),
+            Query::Text(tokens) => file
+                .caption
+                .as_ref()
+                .is_some_and(|caption| text::contains_all(tokens, caption)),
+            Query::Any(word) => {
+                file.persons.iter().any(|id| has_name(persons, id, word))
+                    || file.tags.iter().any(|(key, values)| {
+                        text::equals_ignore_case(key, word)
+                            || values
+                                .iter()
+                                .any(|value| text::equals_ignore_case(value, word))
+                    })
+                    || file.caption.as_ref().is_some_and(|caption| {
+                        text::contains_all(&text::tokenize(word).collect::<Vec<_>>(), caption)
+                    })
+            }
+            Query::Not(query) => !query.matches(file, persons),
+            Query::And(a, b) => a.matches(file, persons) && b.matches(file, persons),
+            Query::Or(a, b) => a.matches(file, persons) || b.matches(file, persons),
+        }
+    }
+}
+
+impl Query {
+    /// Words of all terms that are matched against captions and not negated.
+    ///
+    /// Used to rank search results, see [text::score].
+    pub fn text_tokens(&self) -> Vec<String> {
+        let mut tokens = Vec::new();
+        self.collect_text_tokens(&mut tokens);
+        tokens
+    }
+
+    fn collect_text_tokens(&self, tokens: &mut Vec<String>) {
+        match self {
+            Query::Text(words) => tokens.extend(words.iter().cloned()),
+            Query::Any(word) => tokens.extend(text::tokenize(word)),
+            Query::And(a, b) | Query::Or(a, b) => {
+                a.collect_text_tokens(tokens);
+                b.collect_text_tokens(tokens);
+            }
+            _ => {}
+        }
+    }
+}
+
+impl FromStr for Query {
+    type Err = Error;
+
+    fn from_str(input: &str) -> Result<Self> {
+        Self::parse(input)
+    }
+}
+
+fn has_name(persons: &PersonCollection, id: &PersonId, name: &str) -> bool {
+    persons
+        .person(id)
+        .is_some_and(|person| text::equals_ignore_case(&person.name, name))
+}
+
+fn contains_ignore_case(text: &str, search: &str) -> bool {
+    text.to_lowercase().contains(&search.to_lowercase())
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Token {
+    Open,
+    Close,
+    And,
+    Or,
+    Not,
+    Term { key: Option<String>, value: String },
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Open => write!(f, "`(`"),
+            Token::Close => write!(f, "`)`"),
+            Token::And => write!(f, "`and`"),
+            Token::Or => write!(f, "`or`"),
+            Token::Not => write!(f, "`not`"),
+            Token::Term { key: None, value } => write!(f, "`{value}`"),
+            Token::Term {
+                key: Some(key),
+                value,
+            } => write!(f, "`{key}:{value}`"),
+        }
+    }
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = input.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        let token = match c {
+            c if c.is_whitespace() => {
+                chars.next();
+                continue;
+            }
+            '(' => Token::Open,
+            ')' => Token::Close,
+            '&' => Token::And,
+            '|' => Token::Or,
+            '!' | '-' => Token::Not,
+            _ => {
+                tokens.push(read_term(&mut chars)?);
+                continue;
+            }
+        };
+        chars.next();
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+fn read_term(chars: &mut Peekable<Chars<'_>>) -> Result<Token> {
+    let mut key = None;
+    let mut value = String::new();
+    let mut quoted = false;
+    while let Some(&c) = chars.peek() {
+        match c {
+            '"' => {
+                chars.next();
+                quoted = true;
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some(c) => value.push(c),
+                        None => return Err(Error::InvalidQuery("unterminated quote".into())),
+                    }
+                }
+            }
+            ':' if key.is_none() && !quoted => {
+                chars.next();
+                key = Some(std::mem::take(&mut value));
+            }
+            c if c.is_whitespace() || matches!(c, '(' | ')' | '&' | '|') => break,
+            c => {
+                chars.next();
+                value.push(c);
+            }
+        }
+    }
+
+    if key.is_none() && !quoted {
+        match value.to_lowercase().as_str() {
+            "and" => return Ok(Token::And),
+            "or" => return Ok(Token::Or),
+            "not" => return Ok(Token::Not),
+            _ => {}
+        }
+    }
+    Ok(Token::Term { key, value })
+}
+
+struct Parser {
+    tokens: Peekable<std::vec::IntoIter<Token>>,
+}
+
+impl Parser {
+    fn parse_or(&mut self) -> Result<Query> {
+        let mut query = self.parse_and()?;
+        while self.tokens.next_if_eq(&Token::Or).is_some() {
+            query = Query::Or(Box::new(query), Box::new(self.parse_and()?));
+        }
+        Ok(query)
+    }
+
+    fn parse_and(&mut self) -> Result<Query> {
+        let mut query = self.parse_unary()?;
+        loop {
+            match self.tokens.peek() {
+                Some(Token::And) => {
+                    self.tokens.next();
+                }
+                Some(Token::Open | Token::Not | Token::Term { .. }) => {}
+                _ => break,
+            }
+            query = Query::And(Box::new(query), Box::new(self.parse_unary()?));
+        }
+        Ok(query)
+    }
+
+    fn parse_unary(&mut self) -> Result<Query> {
+        if self.tokens.next_if_eq(&Token::Not).is_some() {
+            Ok(Query::Not(Box::new(self.parse_unary()?)))
+        } else {
+            self.parse_primary()
+        }
+    }
+
+    fn parse_primary(&mut self) -> Result<Query> {
+        match self.tokens.next() {
+            Some(Token::Open) => {
+                let query = self.parse_or()?;
+                match self.tokens.next() {
+                    Some(Token::Close) => Ok(query),
+                    Some(token) => Err(Error::InvalidQuery(format!(
+                        "expected `)` but found {token}"
+                    ))),
+                    None => Err(Error::InvalidQuery("missing `)`".into())),
+                }
+            }
+            Some(Token::Term { key, value }) => parse_term(key, value),
+            Some(token) => Err(Error::InvalidQuery(format!("unexpected {token}"))),
+            None => Err(Error::InvalidQuery("unexpected end of query".into())),
+        }
+    }
+}
+
+fn parse_term(key: Option<String>, value: String) -> Result<Query> {
+    let Some(key) = key else {
+        return Ok(Query::Any(value));
+    };
+    if value.is_empty() {
+        return Err(Error::InvalidQuery(format!("missing value for `{key}`")));
+    }
+
+    Ok(match key.to_lowercase().as_str() {
+        "person" | "p" => match value.strip_prefix('#') {
+            Some(id) => {
+                let id = id
+                    .parse()
+                    .map_err(|_| Error::InvalidQuery(format!("invalid person id `{id}`")))?;
+                Query::Person(PersonMatcher::Id(PersonId(id)))
+            }
+            None => Query::Person(PersonMatcher::Name(value)),
+        },
+        "tag" | "t" => match value.split_once('=') {
+            Some((key, tag_value)) => Query::Tag {
+                key: key.to_string(),
+                value: Some(tag_value.to_string()),
+            },
+            None => Query::Tag {
+                key: value,
+                value: None,
+            },
+        },
+        "event" | "e" => Query::Tag {
+            key: EVENT_TAG.to_string(),
+            value: Some(value),
+        },
+        "hash" => Query::Hash(value),
+        "rating" => {
+            let (comparison, rating) = Comparison::split(&value);
+            let rating = rating
+                .parse()
+                .ok()
+                .filter(|rating| *rating <= MetaFile::MAX_RATING)
+                .ok_or_else(|| Error::InvalidQuery(format!("invalid rating `{value}`")))?;
+            Query::Rating(comparison, rating)
+        }
+        "date" => {
+            let (comparison, date) = Comparison::split(&value);
+            if !date.is_ascii() {
+                return Err(Error::InvalidQuery(format!("invalid date `{value}`")));
+            }
+            Query::Date(comparison, date.to_string())
+        }
+        "camera" => Query::Camera(value),
+        "lens" => Query::Lens(value),
+        "caption" | "text" => Query::Text(text::tokenize(&value).collect()),
+        "flag" => Query::Flag(parse_named(&value, &Flag::ALL, Flag::name, "flag")?),
+        "label" => Query::Label(parse_named(
+            &value,
+            &ColorLabel::ALL,
+            ColorLabel::name,
+            "label",
+        )?),
+        "has" => Query::Has(match value.to_lowercase().as_str() {
+            "person" | "persons" => Property::Persons,
+            "tag" | "tags" => Property::Tags,
+            "hash" => Property::Hash,
+            "rating" => Property::Rating,
+            "flag" => Property::Flag,
+            "label" => Property::Label,
+            "caption" => Property::Caption,
+            "exif" => Property::Exif,
+            "gps" => Property::Gps,
+            _ => return Err(Error::InvalidQuery(format!("unknown property `{value}`"))),
+        }),
+        _ => return Err(Error::InvalidQuery(format!("unknown key `{key}`"))),
+    })
+}
+
+fn parse_named<T: Copy>(
+    value: &str,
+    options: &[T],
+    name: impl Fn(&T) -> &'static str,
+    kind: &str,
+) -> Result<T> {
+    let mut value = value.to_lowercase();
+    if value == "favorite" {
+        value = "favourite".to_string();
+    }
+    options
+        .iter()
+        .find(|option| name(option) == value)
+        .copied()
+        .ok_or_else(|| Error::InvalidQuery(format!("unknown {kind} `{value}`")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{Exif, Person};
+    use std::collections::HashSet;
+
+    fn person(name: &str) -> Query {
+        Query::Person(PersonMatcher::Name(name.into()))
+    }
+
+    fn and(a: Query, b: Query) -> Query {
+        Query::And(Box::new(a), Box::new(b))
+    }
+
+    fn or(a: Query, b: Query) -> Query {
+        Query::Or(Box::new(a), Box::new(b))
+    }
+
+    fn not(query: Query) -> Query {
+        Query::Not(Box::new(query))
+    }
+
+    #[test]
+    fn parse_empty() {
+        assert_eq!(Query::All, Query::parse("").unwrap());
+        assert_eq!(Query::All, Query::parse("   ").unwrap());
+    }
+
+    #[test]
+    fn parse_terms() {
+        assert_eq!(person("Anna"), Query::parse("person:Anna").unwrap());
+        assert_eq!(
+            Query::Person(PersonMatcher::Id(PersonId(3))),
+            Query::parse("p:#3").unwrap()
+        );
+        assert_eq!(
+            Query::Tag {
+                key: "location".into(),
+                value: Some("Rome".into())
+            },
+            Query::parse("tag:location=Rome").unwrap()
+        );
+        assert_eq!(
+            Query::Tag {
+                key: EVENT_TAG.into(),
+                value: Some("Wedding".into())
+            },
+            Query::parse("event:Wedding").unwrap()
+        );
+        assert_eq!(
+            Query::Has(Property::Persons),
+            Query::parse("has:persons").unwrap()
+        );
+        assert_eq!(Query::Any("beach".into()), Query::parse("beach").unwrap());
+    }
+
+    #[test]
+    fn parse_quoted() {
+        assert_eq!(
+            person("Anna Maria"),
+            Query::parse("person:\"Anna Maria\"").unwrap()
+        );
+        assert_eq!(Query::Any("and".into()), Query::parse("\"and\"").unwrap());
+    }
+
+    #[test]
+    fn parse_precedence() {
+        assert_eq!(
+            or(and(person("a"), not(person("b"))), person("c")),
+            Query::parse("person:a and not person:b or person:c").unwrap()
+        );
+        assert_eq!(
+            and(person("a"), or(person("b"), person("c"))),
+            Query::parse("person:a (person:b | person:c)").unwrap()
+        );
+        assert_eq!(
+            and(and(person("a"), person("b")), not(person("c"))),
+            Query::parse("person:a & person:b -person:c").unwrap()
+        );
+    }
+
+    #[test]
+    fn parse_ratings_flags_labels() {
+        assert_eq!(
+            Query::Rating(Comparison::GreaterOrEqual, 3),
+            Query::parse("rating:>=3").unwrap()
+        );
+        assert_eq!(
+            Query::Rating(Comparison::Equal, 0),
+            Query::parse("rating:0").unwrap()
+        );
+        assert_eq!(
+            Query::Flag(Flag::Favourite),
+            Query::parse("flag:Favorite").unwrap()
+        );
+        assert_eq!(
+            Query::Label(ColorLabel::Red),
+            Query::parse("label:red").unwrap()
+        );
+        assert!(Query::parse("rating:6").is_err());
+        assert!(Query::parse("rating:>x").is_err());
+        assert!(Query::parse("flag:maybe").is_err());
+    }
+
+    #[test]
+    fn text_tokens() {
+        let query = Query::parse("caption:\"Sunset Beach\" (rome or -text:paris)").unwrap();
+        assert_eq!(vec!["sunset", "beach", "rome"], query.text_tokens());
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(Query::parse("(person:a").is_err());
+        assert!(Query::parse("person:a)").is_err());
+        assert!(Query::parse("person:a and").is_err());
+        assert!(Query::parse("person:").is_err());
+        assert!(Query::parse("person:#x").is_err());
+        assert!(Query::parse("unknown:x").is_err());
+        assert!(Query::parse("person:\"a").is_err());
+    }
+
+    #[test]
+    fn matches_files() {
+        let mut persons = PersonCollection::default();
+        let anna = persons.add(Person::new("Anna".into(), None));
+        let ben = persons.add(Person::new("Ben".into(), None));
+        let carl = persons.add(Person::new("Carl".into(), None));
+
+        let file = |ids: &[PersonId], tags: &[(&str, &str)]| MetaFile {
+            persons: ids.iter().copied().collect::<HashSet<_>>(),
+            tags: tags
+                .iter()
+                .map(|(key, value)| (key.to_string(), vec![value.to_string()]))
+                .collect(),
+            ..Default::default()
+        };
+
+        let query =
+            Query::parse("person:anna and person:Ben and not person:Carl and tag:location=rome")
+                .unwrap();
+        assert!(query.matches(&file(&[anna, ben], &[("location", "Rome")]), &persons));
+        assert!(!query.matches(&file(&[anna, ben, carl], &[("location", "Rome")]), &persons));
+        assert!(!query.matches(&file(&[anna, ben], &[("location", "Paris")]), &persons));
+        assert!(!query.matches(&file(&[anna], &[("location", "Rome")]), &persons));
+
+        let query = Query::parse("rome | carl").unwrap();
+        assert!(query.matches(&file(&[], &[("location", "Rome")]), &persons));
+        assert!(query.matches(&file(&[carl], &[]), &persons));
+        assert!(!query.matches(&file(&[anna], &[]), &persons));
+
+        let rated = MetaFile {
+            rating: Some(4),
+            flag: Some(Flag::Pick),
+            ..Default::default()
+        };
+        assert!(Query::parse("rating:>3 flag:pick")
+            .unwrap()
+            .matches(&rated, &persons));
+        assert!(!Query::parse("rating:<4").unwrap().matches(&rated, &persons));
+        assert!(Query::parse("rating:<4")
+            .unwrap()
+            .matches(&file(&[], &[]), &persons));
+
+        let taken = MetaFile {
+            exif: Some(Exif {
+                date_taken: Some("2023-05-17T12:30:00".into()),
+                camera: Some("Canon EOS 5D".into()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        assert!(Query::parse("date:2023-05 camera:eos")
+            .unwrap()
+            .matches(&taken, &persons));
+        assert!(Query::parse("date:>=2023")
+            .unwrap()
+            .matches(&taken, &persons));
+        assert!(Query::parse("date:<2023-06")
+            .unwrap()
+            .matches(&taken, &persons));
+        assert!(!Query::parse("date:<2023")
+            .unwrap()
+            .matches(&taken, &persons));
+        assert!(!Query::parse("date:2023-05-18")
+            .unwrap()
+            .matches(&taken, &persons));
+        assert!(!Query::parse("has:gps").unwrap().matches(&taken, &persons));
+
+        let query = Query::parse("!has:persons").unwrap();
+        assert!(query.matches(&file(&[], &[("location", "Rome")]), &persons));
+        assert!(!query.matches(&file(&[anna], &[]), &persons));
+    }
+}
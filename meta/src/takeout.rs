@@ -0,0 +1,306 @@
+use crate::{
+    model::{Exif, Flag, MetaFile, PersonCollection, PersonId},
+    time, Result,
+};
+
+use std::{
+    fs::{self, File},
+    io::BufReader,
+    path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+
+/// Tag key under which the album of a Takeout folder is stored.
+pub const ALBUM_TAG: &str = "album";
+
+/// Suffix inserted by newer Takeout exports, e.g. `image.jpg.supplemental-metadata.json`.
+const SUPPLEMENTAL_SUFFIX: &str = "supplemental-metadata";
+
+/// Suffix of edited images that share the sidecar of the original image.
+const EDITED_SUFFIX: &str = "-edited";
+
+/// JSON sidecar of a photo in a Google Takeout export. Album folders contain
+/// a similar file with a title but without the time the photo was taken.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Sidecar {
+    title: Option<String>,
+    description: Option<String>,
+    photo_taken_time: Option<Timestamp>,
+    geo_data: Option<GeoData>,
+    geo_data_exif: Option<GeoData>,
+    #[serde(default)]
+    people: Vec<SidecarPerson>,
+    #[serde(default)]
+    favorited: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct Timestamp {
+    /// Seconds since the Unix epoch.
+    timestamp: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct GeoData {
+    latitude: f64,
+    longitude: f64,
+}
+
+#[derive(Debug, Deserialize)]
+struct SidecarPerson {
+    name: String,
+}
+
+/// Outcome of [crate::Repository::import_takeout].
+#[derive(Debug, Default)]
+pub struct TakeoutImport {
+    /// Number of image files whose meta data was updated.
+    pub imported: usize,
+
+    /// Sidecars for which no image file could be found.
+    pub unmatched: Vec<PathBuf>,
+}
+
+/// Sidecars of one folder with the file names of the images they belong to.
+pub(crate) struct TakeoutFolder {
+    pub(crate) path: PathBuf,
+    album: Option<String>,
+    sidecars: Vec<(Sidecar, Vec<String>)>,
+    pub(crate) unmatched: Vec<PathBuf>,
+}
+
+/// Reads the sidecars of all folders below the given folder and matches them to image files.
+pub(crate) fn read_folders(path: &Path, found: &mut Vec<TakeoutFolder>) -> Result<()> {
+    let mut file_names = Vec::new();
+    let mut json_paths = Vec::new();
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            read_folders(&entry.path(), found)?;
+        } else if let Some(file_name) = entry.file_name().to_str() {
+            if file_name.ends_with(".json") {
+                json_paths.push(entry.path());
+            } else {
+                file_names.push(file_name.to_string());
+            }
+        }
+    }
+
+    let mut folder = TakeoutFolder {
+        path: path.to_path_buf(),
+        album: None,
+        sidecars: Vec::new(),
+        unmatched: Vec::new(),
+    };
+    for json_path in json_paths {
+        // Other JSON files, e.g. of the repository itself, are no sidecars.
+        let Ok(sidecar) =
+            serde_json::from_reader::<_, Sidecar>(BufReader::new(File::open(&json_path)?))
+        else {
+            continue;
+        };
+        if sidecar.photo_taken_time.is_none() {
+            folder.album = folder.album.or(sidecar.title);
+            continue;
+        }
+
+        let images = find_images(&json_path, sidecar.title.as_deref(), &file_names);
+        if images.is_empty() {
+            folder.unmatched.push(json_path);
+        } else {
+            folder.sidecars.push((sidecar, images));
+        }
+    }
+    if !folder.sidecars.is_empty() || !folder.unmatched.is_empty() {
+        found.push(folder);
+    }
+    Ok(())
+}
+
+impl TakeoutFolder {
+    /// Paths of the images in this folder with the sidecar belonging to them.
+    pub(crate) fn images(&self) -> impl Iterator<Item = (PathBuf, usize)> + '_ {
+        self.sidecars
+            .iter()
+            .enumerate()
+            .flat_map(move |(index, (_, images))| {
+                images
+                    .iter()
+                    .map(move |image| (self.path.join(image), index))
+            })
+    }
+
+    /// Ids of the persons by name for each sidecar. Persons that do not exist yet are created.
+    pub(crate) fn person_ids(&self, persons: &mut PersonCollection) -> Vec<Vec<PersonId>> {
+        self.sidecars
+            .iter()
+            .map(|(sidecar, _)| {
+                sidecar
+                    .people
+                    .iter()
+                    .map(|person| persons.get_or_create(&person.name))
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Adds the data of the sidecar to the meta data of the file.
+    ///
+    /// Data that is already set, e.g. a caption, is kept.
+    pub(crate) fn apply(&self, sidecar: usize, file: &mut MetaFile, person_ids: &[PersonId]) {
+        let (sidecar, _) = &self.sidecars[sidecar];
+        file.persons.extend(person_ids);
+        if let Some(album) = &self.album {
+            let albums = file.tags.entry(ALBUM_TAG.to_string()).or_default();
+            if !albums.contains(album) {
+                albums.push(album.clone());
+            }
+        }
+        if file.caption.is_none() {
+            file.caption = sidecar
+                .description
+                .clone()
+                .filter(|description| !description.trim().is_empty());
+        }
+        if sidecar.favorited && file.flag.is_none() {
+            file.flag = Some(Flag::Favourite);
+        }
+
+        let date_taken = sidecar
+            .photo_taken_time
+            .as_ref()
+            .and_then(|time| time.timestamp.parse().ok())
+            .and_then(time::format_local_timestamp);
+        // Takeout uses zero coordinates for photos without location.
+        let coordinates = [&sidecar.geo_data, &sidecar.geo_data_exif]
+            .into_iter()
+            .flatten()
+            .find(|geo| geo.latitude != 0.0 || geo.longitude != 0.0);
+        if date_taken.is_none() && coordinates.is_none() {
+            return;
+        }
+        let exif = file.exif.get_or_insert_with(Exif::default);
+        if exif.date_taken.is_none() {
+            exif.date_taken = date_taken;
+        }
+        if let Some(geo) = coordinates.filter(|_| exif.coordinates().is_none()) {
+            exif.latitude = Some(geo.latitude);
+            exif.longitude = Some(geo.longitude);
+        }
+    }
+}
+
+/// Finds the images in the folder that the sidecar belongs to.
+///
+/// Handles the quirks of Takeout exports: sidecars of duplicates are named `image.jpg(1).json`
+/// for `image(1).jpg`, long file names are truncated differently for sidecars and images,
+/// and edited images like `image-edited.jpg` share the sidecar of the original.
+fn find_images(json_path: &Path, title: Option<&str>, file_names: &[String]) -> Vec<String> {
+    let exists = |name: &str| file_names.iter().any(|file_name| file_name == name);
+    let json_name = json_path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or_default();
+    let mut name = json_name.strip_suffix(".json").unwrap_or(json_name);
+
+    let mut counter = "";
+    if let Some(start) = name.rfind('(').filter(|_| name.ends_with(')')) {
+        if name[start + 1..name.len() - 1]
+            .chars()
+            .all(|c| c.is_ascii_digit())
+        {
+            counter = &name[start..];
+            name = &name[..start];
+        }
+    }
+    if let Some((image_name, suffix)) = name.rsplit_once('.') {
+        if SUPPLEMENTAL_SUFFIX.starts_with(suffix) {
+            name = image_name;
+        }
+    }
+
+    let (stem, extension) = split_extension(name);
+    let candidate = format!("{stem}{counter}{extension}");
+    let found = if exists(&candidate) {
+        Some(candidate)
+    } else if let Some(title) = title.filter(|title| counter.is_empty() && exists(title)) {
+        Some(title.to_string())
+    } else {
+        // The image name is a truncated version of the title.
+        let (title_stem, title_extension) = split_extension(title.unwrap_or(name));
+        file_names
+            .iter()
+            .filter(|file_name| {
+                let (file_stem, file_extension) = split_extension(file_name);
+                let Some(file_stem) = file_stem.strip_suffix(counter) else {
+                    return false;
+                };
+                !file_stem.is_empty()
+                    && file_extension.eq_ignore_ascii_case(title_extension)
+                    && title_stem.starts_with(file_stem)
+            })
+            .max_by_key(|file_name| file_name.len())
+            .cloned()
+    };
+
+    let Some(found) = found else {
+        return Vec::new();
+    };
+    let (found_stem, found_extension) = split_extension(&found);
+    let edited = format!("{found_stem}{EDITED_SUFFIX}{found_extension}");
+    let mut images = vec![found];
+    if exists(&edited) {
+        images.push(edited);
+    }
+    images
+}
+
+/// Splits a file name into stem and extension including the dot.
+fn split_extension(file_name: &str) -> (&str, &str) {
+    match file_name.rfind('.') {
+        Some(index) if index > 0 => file_name.split_at(index),
+        _ => (file_name, ""),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find(json_name: &str, title: &str, file_names: &[&str]) -> Vec<String> {
+        let file_names: Vec<_> = file_names.iter().map(|name| name.to_string()).collect();
+        find_images(Path::new(json_name), Some(title), &file_names)
+    }
+
+    #[test]
+    fn find_images_quirks() {
+        assert_eq!(
+            vec!["a.jpg"],
+            find("a.jpg.json", "a.jpg", &["a.jpg", "b.jpg"])
+        );
+        assert_eq!(
+            vec!["a.jpg"],
+            find("a.jpg.supplemental-metadata.json", "a.jpg", &["a.jpg"])
+        );
+        assert_eq!(vec!["a.jpg"], find("a.jpg.suppl.json", "a.jpg", &["a.jpg"]));
+        assert_eq!(
+            vec!["a(1).jpg"],
+            find("a.jpg(1).json", "a.jpg", &["a.jpg", "a(1).jpg"])
+        );
+        assert_eq!(
+            vec!["a.jpg", "a-edited.jpg"],
+            find("a.jpg.json", "a.jpg", &["a.jpg", "a-edited.jpg"])
+        );
+        assert_eq!(
+            vec!["a_very_long_nam.jpg"],
+            find(
+                "a_very_long_name.json",
+                "a_very_long_name_indeed.jpg",
+                &["a_very_long_nam.jpg", "a_very.jpg"]
+            )
+        );
+        assert!(find("b.jpg.json", "b.jpg", &["a.jpg"]).is_empty());
+    }
+}
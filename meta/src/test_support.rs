@@ -0,0 +1,48 @@
+//! Helpers for tests of this crate and of crates using it, see the `test-support` feature.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use tempfile::TempDir;
+
+/// Empty folder in the temporary directory that is removed again when dropped,
+/// also when a test fails.
+pub struct TempFolder {
+    path: PathBuf,
+    _folder: TempDir,
+}
+
+impl TempFolder {
+    /// Creates the folder with `name` as prefix of its file name.
+    pub fn new(name: &str) -> Self {
+        let folder = tempfile::Builder::new()
+            .prefix(&format!("{name}-"))
+            .tempdir()
+            .unwrap();
+        Self {
+            path: folder.path().canonicalize().unwrap(),
+            _folder: folder,
+        }
+    }
+
+    /// Creates files at the paths relative to the folder, containing their file name.
+    pub fn with_files(self, paths: &[impl AsRef<Path>]) -> Self {
+        for path in paths {
+            let file = self.path.join(path);
+            fs::create_dir_all(file.parent().unwrap()).unwrap();
+            fs::write(&file, file.file_name().unwrap().as_encoded_bytes()).unwrap();
+        }
+        self
+    }
+
+    /// Canonical path of the folder.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn join(&self, path: impl AsRef<Path>) -> PathBuf {
+        self.path.join(path)
+    }
+}
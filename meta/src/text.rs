@@ -0,0 +1,90 @@
+/// Splits the text into lowercase words.
+pub fn tokenize(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+}
+
+/// Compares the texts ignoring case.
+pub fn equals_ignore_case(a: &str, b: &str) -> bool {
+    a.chars()
+        .flat_map(char::to_lowercase)
+        .eq(b.chars().flat_map(char::to_lowercase))
+}
+
+/// Checks that every search token is the start of a word in the text.
+pub fn contains_all(search_tokens: &[String], text: &str) -> bool {
+    let words: Vec<_> = tokenize(text).collect();
+    search_tokens
+        .iter()
+        .all(|token| words.iter().any(|word| word.starts_with(token.as_str())))
+}
+
+/// Computes how well the text matches the search tokens.
+///
+/// Every word of the text that equals a search token scores higher than a
+/// word that only starts with a search token. The score is normalised by the
+/// number of words, so short texts that match score higher than long ones.
+/// Returns 0 if no word matches.
+pub fn score(search_tokens: &[String], text: &str) -> f32 {
+    const EXACT_MATCH: f32 = 2.0;
+    const PREFIX_MATCH: f32 = 1.0;
+
+    let mut word_count = 0;
+    let mut score = 0.0;
+    for word in tokenize(text) {
+        word_count += 1;
+        for token in search_tokens {
+            if word == *token {
+                score += EXACT_MATCH;
+            } else if word.starts_with(token.as_str()) {
+                score += PREFIX_MATCH;
+            }
+        }
+    }
+
+    if word_count == 0 {
+        0.0
+    } else {
+        score / (word_count as f32).sqrt()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(text: &str) -> Vec<String> {
+        tokenize(text).collect()
+    }
+
+    #[test]
+    fn tokenize_words() {
+        assert_eq!(
+            vec!["sunset", "at", "the", "beach", "2023"],
+            tokens("Sunset at the BEACH, 2023!")
+        );
+        assert!(tokens(" -- ").is_empty());
+    }
+
+    #[test]
+    fn contains_all_prefixes() {
+        let text = "Sunset at the beach";
+        assert!(contains_all(&tokens("sun beach"), text));
+        assert!(contains_all(&[], text));
+        assert!(!contains_all(&tokens("sun mountain"), text));
+        assert!(!contains_all(&tokens("set"), text));
+    }
+
+    #[test]
+    fn score_ranking() {
+        let search = tokens("beach");
+        let exact = score(&search, "At the beach");
+        let prefix = score(&search, "At the beaches");
+        let long = score(&search, "At the beach with everyone from the family");
+        assert!(exact > prefix);
+        assert!(exact > long);
+        assert_eq!(0.0, score(&search, "mountains"));
+        assert_eq!(0.0, score(&search, ""));
+    }
+}
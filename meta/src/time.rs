@@ -0,0 +1,51 @@
+use std::time::SystemTime;
+
+use chrono::{DateTime, Local, TimeZone};
+
+/// Format of times in reports, like dates taken from EXIF data.
+const FORMAT: &str = "%Y-%m-%dT%H:%M:%S";
+
+/// Formats seconds since the Unix epoch as `YYYY-MM-DDTHH:MM:SS` in the local time zone.
+///
+/// Takeout stores times in UTC, while dates taken from EXIF data are in local time
+/// without time zone, so they are converted to match.
+pub(crate) fn format_local_timestamp(timestamp: i64) -> Option<String> {
+    let time = Local.timestamp_opt(timestamp, 0).single()?;
+    Some(time.format(FORMAT).to_string())
+}
+
+/// Formats the time as `YYYY-MM-DDTHH:MM:SS` in the local time zone, e.g. modification times
+/// of files, so they can be compared with dates taken.
+pub(crate) fn format_local_time(time: SystemTime) -> String {
+    DateTime::<Local>::from(time).format(FORMAT).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use chrono::NaiveDateTime;
+
+    #[test]
+    fn format_local_timestamps() {
+        let timestamp = 1_500_000_000;
+        let local = format_local_timestamp(timestamp).unwrap();
+        let parsed = NaiveDateTime::parse_from_str(&local, "%Y-%m-%dT%H:%M:%S").unwrap();
+        let offset = Local.offset_from_local_datetime(&parsed).single().unwrap();
+        assert_eq!(
+            timestamp,
+            parsed.and_local_timezone(offset).unwrap().timestamp()
+        );
+    }
+
+    #[test]
+    fn format_local_times() {
+        let time = UNIX_EPOCH + Duration::from_secs(1_500_000_000);
+        assert_eq!(
+            format_local_timestamp(1_500_000_000).unwrap(),
+            format_local_time(time)
+        );
+    }
+}
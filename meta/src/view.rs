@@ -0,0 +1,312 @@
+use crate::{Error, Result};
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashSet},
+    fs::{self, File},
+    io::{self, BufReader, Read},
+    path::{Path, PathBuf},
+};
+
+pub const PERSON_FOLDER: &str = "by-person";
+pub const TAG_FOLDER: &str = "by-tag";
+
+/// File in the view folder listing the links created for the view, relative to the view folder.
+const MANIFEST: &str = ".file-manager-view.json";
+
+/// Outcome of [crate::Repository::update_view].
+#[derive(Debug, Default)]
+pub struct ViewUpdate {
+    pub created: usize,
+    pub removed: usize,
+    pub unchanged: usize,
+
+    /// Links that had to be created as hard links or copies because symbolic links failed.
+    pub fallbacks: usize,
+}
+
+/// Chooses the paths of the links in the view for the given files by person and tag.
+///
+/// Files with the same name in one folder of the view get numbered, e.g. `image (2).jpg`,
+/// in the order of their source paths, so the names stay the same between updates.
+pub(crate) fn plan(
+    files: impl IntoIterator<Item = (PathBuf, Vec<String>, Vec<(String, Option<String>)>)>,
+) -> BTreeMap<PathBuf, PathBuf> {
+    let mut folders: BTreeMap<PathBuf, Vec<PathBuf>> = BTreeMap::new();
+    for (source, persons, tags) in files {
+        for person in persons {
+            let folder = Path::new(PERSON_FOLDER).join(sanitize(&person));
+            folders.entry(folder).or_default().push(source.clone());
+        }
+        for (key, value) in tags {
+            let mut folder = Path::new(TAG_FOLDER).join(sanitize(&key));
+            if let Some(value) = value {
+                folder.push(sanitize(&value));
+            }
+            folders.entry(folder).or_default().push(source.clone());
+        }
+    }
+
+    let mut links = BTreeMap::new();
+    for (folder, mut sources) in folders {
+        sources.sort();
+        sources.dedup();
+        let mut used = HashSet::new();
+        for source in sources {
+            let Some(file_name) = source.file_name().and_then(|name| name.to_str()) else {
+                continue;
+            };
+            let mut name = file_name.to_string();
+            let mut number = 1;
+            while !used.insert(name.to_lowercase()) {
+                number += 1;
+                name = numbered(file_name, number);
+            }
+            links.insert(folder.join(name), source);
+        }
+    }
+    links
+}
+
+/// Makes a person name or tag usable as folder name.
+fn sanitize(name: &str) -> String {
+    let name: String = name
+        .trim()
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    let name = name.trim_start_matches('.');
+    if name.is_empty() {
+        "_".into()
+    } else {
+        name.into()
+    }
+}
+
+fn numbered(file_name: &str, number: usize) -> String {
+    match file_name.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() => format!("{stem} ({number}).{extension}"),
+        _ => format!("{file_name} ({number})"),
+    }
+}
+
+/// Creates the links below the view folder and removes links that are not planned anymore.
+///
+/// Without `incremental`, all existing links are replaced. Otherwise existing links are kept
+/// if they point to the right file, hard links and copies if their content is the same.
+/// Symbolic links and the files listed in the [MANIFEST] may be removed, anything else in the
+/// person and tag folders is refused with [Error::ForeignFileInView] before changing anything.
+pub(crate) fn update(
+    view: &Path,
+    links: &BTreeMap<PathBuf, PathBuf>,
+    incremental: bool,
+) -> Result<ViewUpdate> {
+    let mut update = ViewUpdate::default();
+    let roots = [view.join(PERSON_FOLDER), view.join(TAG_FOLDER)];
+    let manifest = view.join(MANIFEST);
+    let created: HashSet<PathBuf> = match fs::read(&manifest) {
+        Ok(content) => serde_json::from_slice(&content)?,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => HashSet::new(),
+        Err(error) => return Err(error.into()),
+    };
+
+    let mut existing = Vec::new();
+    for root in &roots {
+        find_entries(root, &mut existing)?;
+    }
+    for path in &existing {
+        let relative = path.strip_prefix(view)?;
+        if !fs::symlink_metadata(path)?.is_symlink() && !created.contains(relative) {
+            return Err(Error::ForeignFileInView(path.clone()));
+        }
+    }
+
+    // Lists the planned links before creating them, so they are known as own files
+    // even if the update fails halfway.
+    write_manifest(&manifest, created.iter().chain(links.keys()))?;
+
+    let mut kept = HashSet::new();
+    for path in existing {
+        let up_to_date = incremental
+            && path
+                .strip_prefix(view)
+                .ok()
+                .and_then(|relative| links.get(relative))
+                .is_some_and(|source| points_to(&path, source));
+        if up_to_date {
+            kept.insert(path);
+            update.unchanged += 1;
+        } else {
+            fs::remove_file(&path)?;
+            update.removed += 1;
+        }
+    }
+
+    for (relative, source) in links {
+        let path = view.join(relative);
+        if kept.contains(&path) {
+            continue;
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        if !link(source, &path)? {
+            update.fallbacks += 1;
+        }
+        update.created += 1;
+    }
+
+    for root in &roots {
+        remove_empty_folders(root)?;
+    }
+    write_manifest(&manifest, links.keys())?;
+    Ok(update)
+}
+
+fn write_manifest<'a>(manifest: &Path, links: impl Iterator<Item = &'a PathBuf>) -> Result<()> {
+    let links: BTreeSet<_> = links.collect();
+    fs::write(manifest, serde_json::to_vec_pretty(&links)?)?;
+    Ok(())
+}
+
+/// Collects all files and links below the folder.
+fn find_entries(folder: &Path, found: &mut Vec<PathBuf>) -> Result<()> {
+    if !folder.try_exists()? {
+        return Ok(());
+    }
+    for entry in fs::read_dir(folder)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            find_entries(&entry.path(), found)?;
+        } else {
+            found.push(entry.path());
+        }
+    }
+    Ok(())
+}
+
+/// Checks whether the file in the view is a symbolic link to the source
+/// or a hard link or copy with the same content.
+fn points_to(path: &Path, source: &Path) -> bool {
+    match fs::read_link(path) {
+        Ok(target) => target == source,
+        Err(_) => same_content(path, source).unwrap_or(false),
+    }
+}
+
+fn same_content(a: &Path, b: &Path) -> io::Result<bool> {
+    if fs::metadata(a)?.len() != fs::metadata(b)?.len() {
+        return Ok(false);
+    }
+    let mut a = BufReader::new(File::open(a)?);
+    let mut b = BufReader::new(File::open(b)?);
+    let mut buffer_a = [0; 8192];
+    let mut buffer_b = [0; 8192];
+    loop {
+        let read = a.read(&mut buffer_a)?;
+        if read == 0 {
+            return Ok(b.read(&mut buffer_b)? == 0);
+        }
+        b.read_exact(&mut buffer_b[..read])?;
+        if buffer_a[..read] != buffer_b[..read] {
+            return Ok(false);
+        }
+    }
+}
+
+/// Creates a symbolic link, falling back to a hard link and then to a copy.
+///
+/// Returns `false` if a fallback was used.
+fn link(source: &Path, path: &Path) -> io::Result<bool> {
+    if symlink(source, path).is_ok() {
+        return Ok(true);
+    }
+    if fs::hard_link(source, path).is_err() {
+        fs::copy(source, path)?;
+    }
+    Ok(false)
+}
+
+#[cfg(unix)]
+fn symlink(source: &Path, path: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(source, path)
+}
+
+#[cfg(windows)]
+fn symlink(source: &Path, path: &Path) -> io::Result<()> {
+    std::os::windows::fs::symlink_file(source, path)
+}
+
+#[cfg(not(any(unix, windows)))]
+fn symlink(_source: &Path, _path: &Path) -> io::Result<()> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+/// Removes the folders below the given folder that became empty, including the folder itself.
+fn remove_empty_folders(folder: &Path) -> Result<bool> {
+    if !folder.try_exists()? {
+        return Ok(true);
+    }
+    let mut empty = true;
+    for entry in fs::read_dir(folder)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_dir() || !remove_empty_folders(&entry.path())? {
+            empty = false;
+        }
+    }
+    if empty {
+        fs::remove_dir(folder)?;
+    }
+    Ok(empty)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_support::TempFolder;
+
+    #[test]
+    fn plan_numbers_duplicates() {
+        let links = plan([
+            (
+                PathBuf::from("/b/image.jpg"),
+                vec!["Anna".into()],
+                vec![("location".into(), Some("Rome/Italy".into()))],
+            ),
+            (
+                PathBuf::from("/a/image.jpg"),
+                vec!["Anna".into()],
+                vec![("holiday".into(), None)],
+            ),
+        ]);
+        let expected = BTreeMap::from([
+            ("by-person/Anna/image.jpg".into(), "/a/image.jpg".into()),
+            ("by-person/Anna/image (2).jpg".into(), "/b/image.jpg".into()),
+            ("by-tag/holiday/image.jpg".into(), "/a/image.jpg".into()),
+            (
+                "by-tag/location/Rome_Italy/image.jpg".into(),
+                "/b/image.jpg".into(),
+            ),
+        ]);
+        assert_eq!(expected, links);
+    }
+
+    #[test]
+    fn copies_are_compared_by_content() {
+        let folder = TempFolder::new("view");
+        let source = folder.join("source.jpg");
+        let copy = folder.join("copy.jpg");
+        fs::write(&source, "abc").unwrap();
+        fs::write(&copy, "abc").unwrap();
+        assert!(points_to(&copy, &source));
+
+        fs::write(&copy, "abd").unwrap();
+        assert!(!points_to(&copy, &source));
+        fs::write(&copy, "abcd").unwrap();
+        assert!(!points_to(&copy, &source));
+    }
+}
@@ -0,0 +1,711 @@
+use crate::{
+    model::{ColorLabel, Flag, MetaFile, PersonCollection, PersonId, Region},
+    Error, Result,
+};
+
+use std::{
+    collections::HashMap,
+    fmt::Write,
+    path::{Path, PathBuf},
+};
+
+use roxmltree::{Document, Node};
+
+const RDF: &str = "http://www.w3.org/1999/02/22-rdf-syntax-ns#";
+const DC: &str = "http://purl.org/dc/elements/1.1/";
+const XMP: &str = "http://ns.adobe.com/xap/1.0/";
+const LR: &str = "http://ns.adobe.com/lightroom/1.0/";
+const IPTC_EXT: &str = "http://iptc.org/std/Iptc4xmpExt/2008-02-29/";
+const MWG_RS: &str = "http://www.metadataworkinggroup.com/schemas/regions/";
+const ST_AREA: &str = "http://ns.adobe.com/xmp/sType/Area#";
+
+const X: &str = "adobe:ns:meta/";
+
+/// Value of the `x:xmptk` attribute that marks sidecars written by this application.
+const TOOLKIT: &str = "file-manager";
+
+/// Root keywords under which digiKam and Lightroom file persons in hierarchical keywords.
+const PERSON_KEYWORDS: [&str; 2] = ["People", "Persons"];
+
+/// Meta data in the form of an XMP sidecar file, referencing persons by name.
+///
+/// Tags are stored as hierarchical keywords `key|value` (`lr:hierarchicalSubject`)
+/// and their values as flat keywords (`dc:subject`). Persons are stored in
+/// `Iptc4xmpExt:PersonInImage` and their regions as `mwg-rs` face regions.
+#[derive(Debug, Default, PartialEq)]
+pub struct Xmp {
+    pub persons: Vec<String>,
+    pub regions: Vec<(String, Region)>,
+    pub tags: HashMap<String, Vec<String>>,
+    pub rating: Option<u8>,
+    pub rejected: bool,
+    pub label: Option<ColorLabel>,
+    pub description: Option<String>,
+}
+
+/// Path of the sidecar written for the given file, e.g. `image.jpg.xmp`.
+pub fn sidecar_path(path: impl AsRef<Path>) -> PathBuf {
+    let mut sidecar = path.as_ref().as_os_str().to_os_string();
+    sidecar.push(".xmp");
+    sidecar.into()
+}
+
+/// Finds an existing sidecar of the given file.
+///
+/// Checks for `image.jpg.xmp` as written by digiKam and darktable, then `image.xmp` as written by Lightroom.
+pub fn find_sidecar(path: impl AsRef<Path>) -> Result<Option<PathBuf>> {
+    let path = path.as_ref();
+    for sidecar in [sidecar_path(path), path.with_extension("xmp")] {
+        if sidecar.try_exists()? {
+            return Ok(Some(sidecar));
+        }
+    }
+    Ok(None)
+}
+
+/// Whether the XMP packet was written by this application (see [Xmp::to_xml]), so it can be
+/// overwritten without losing data of other applications, e.g. the edit history of darktable.
+pub fn is_own_sidecar(xml: &str) -> bool {
+    Document::parse(xml)
+        .is_ok_and(|document| document.root_element().attribute((X, "xmptk")) == Some(TOOLKIT))
+}
+
+impl Xmp {
+    /// Collects the meta data of the file. Persons that do not exist anymore are skipped.
+    pub fn from_meta_file(file: &MetaFile, persons: &PersonCollection) -> Self {
+        let name = |id: &PersonId| persons.person(id).map(|person| person.name.clone());
+        let mut xmp = Self {
+            persons: file.persons.iter().filter_map(name).collect(),
+            regions: file
+                .regions
+                .iter()
+                .filter_map(|(id, regions)| Some((name(id)?, regions)))
+                .flat_map(|(name, regions)| {
+                    regions.iter().map(move |region| (name.clone(), *region))
+                })
+                .collect(),
+            tags: file.tags.clone(),
+            rating: file.rating,
+            rejected: file.flag == Some(Flag::Reject),
+            label: file.label,
+            description: file.caption.clone(),
+        };
+        xmp.persons.sort();
+        xmp.regions.sort_by(|(a, _), (b, _)| a.cmp(b));
+        xmp
+    }
+
+    /// Ids of the persons in this sidecar by name. Persons that do not exist yet are created.
+    pub fn person_ids(&self, persons: &mut PersonCollection) -> HashMap<String, PersonId> {
+        self.persons
+            .iter()
+            .chain(self.regions.iter().map(|(name, _)| name))
+            .map(|name| (name.clone(), persons.get_or_create(name)))
+            .collect()
+    }
+
+    /// Merges the sidecar into the meta data of the file.
+    ///
+    /// Persons, tags and regions are added to the existing ones, while rating,
+    /// label and description of the sidecar replace the existing ones if they are set.
+    pub fn apply(&self, file: &mut MetaFile, person_ids: &HashMap<String, PersonId>) {
+        file.persons.extend(
+            self.persons
+                .iter()
+                .filter_map(|name| person_ids.get(name).copied()),
+        );
+        for (name, region) in &self.regions {
+            let Some(id) = person_ids.get(name) else {
+                continue;
+            };
+            if !file
+                .regions
+                .get(id)
+                .is_some_and(|regions| regions.contains(region))
+            {
+                file.add_person_region(*id, *region);
+            }
+        }
+
+        for (key, values) in &self.tags {
+            let existing = file.tags.entry(key.clone()).or_default();
+            for value in values {
+                if !existing.contains(value) {
+                    existing.push(value.clone());
+                }
+            }
+        }
+
+        if self.rating.is_some() {
+            file.rating = self.rating;
+        }
+        if self.rejected {
+            file.flag = Some(Flag::Reject);
+        }
+        if self.label.is_some() {
+            file.label = self.label;
+        }
+        if self.description.is_some() {
+            file.caption = self.description.clone();
+        }
+    }
+
+    /// Reads an XMP packet as written by this application, digiKam, darktable or Lightroom.
+    pub fn parse(xml: &str) -> Result<Self> {
+        let document = Document::parse(xml)?;
+        let root = document.root();
+        let mut xmp = Self::default();
+
+        let mut persons = values(root, IPTC_EXT, "PersonInImage");
+        let hierarchical = values(root, LR, "hierarchicalSubject");
+        if hierarchical.is_empty() {
+            for keyword in values(root, DC, "subject") {
+                xmp.tags.entry(keyword).or_default();
+            }
+        }
+        for keyword in hierarchical {
+            let (key, value) = match keyword.split_once('|') {
+                Some((key, value)) => (key, Some(value)),
+                None => (keyword.as_str(), None),
+            };
+            match value {
+                Some(name) if PERSON_KEYWORDS.contains(&key) => persons.push(name.into()),
+                Some(value) => xmp.tags.entry(key.into()).or_default().push(value.into()),
+                None => {
+                    xmp.tags.entry(key.into()).or_default();
+                }
+            }
+        }
+
+        xmp.regions = regions(root);
+        persons.extend(xmp.regions.iter().map(|(name, _)| name.clone()));
+        persons.sort();
+        persons.dedup();
+        // Flat keywords often repeat the person names.
+        xmp.tags
+            .retain(|key, values| !(values.is_empty() && persons.contains(key)));
+        xmp.persons = persons;
+
+        if let Some(rating) = values(root, XMP, "Rating").first() {
+            let rating: f32 = rating.parse().unwrap_or(0.0);
+            xmp.rejected = rating < 0.0;
+            xmp.rating = (rating >= 0.0).then(|| (rating.round() as u8).min(MetaFile::MAX_RATING));
+        }
+        xmp.label = values(root, XMP, "Label").first().and_then(|label| {
+            ColorLabel::ALL
+                .into_iter()
+                .find(|option| option.name().eq_ignore_ascii_case(label))
+        });
+        xmp.description = values(root, DC, "description")
+            .into_iter()
+            .find(|description| !description.is_empty());
+        Ok(xmp)
+    }
+
+    /// Writes the sidecar as XMP packet.
+    pub fn to_xml(&self) -> String {
+        let mut attributes = String::new();
+        let mut properties = String::new();
+
+        if let Some(rating) = self.rating {
+            write!(attributes, "\n   xmp:Rating=\"{rating}\"").unwrap();
+        } else if self.rejected {
+            attributes.push_str("\n   xmp:Rating=\"-1\"");
+        }
+        if let Some(label) = self.label {
+            let name = label.name();
+            let name = name[..1].to_uppercase() + &name[1..];
+            write!(attributes, "\n   xmp:Label=\"{name}\"").unwrap();
+        }
+
+        let mut keys: Vec<_> = self.tags.keys().collect();
+        keys.sort();
+        let mut flat = Vec::new();
+        let mut hierarchical = Vec::new();
+        for key in keys {
+            let values = &self.tags[key];
+            if values.is_empty() {
+                flat.push(key.clone());
+                hierarchical.push(key.clone());
+            }
+            for value in values {
+                flat.push(value.clone());
+                hierarchical.push(format!("{key}|{value}"));
+            }
+        }
+        write_list(&mut properties, "dc:subject", "rdf:Bag", &flat);
+        write_list(
+            &mut properties,
+            "lr:hierarchicalSubject",
+            "rdf:Bag",
+            &hierarchical,
+        );
+        if let Some(description) = &self.description {
+            writeln!(
+                properties,
+                "   <dc:description>\n    <rdf:Alt>\n     \
+                 <rdf:li xml:lang=\"x-default\">{}</rdf:li>\n    </rdf:Alt>\n   </dc:description>",
+                escape(description)
+            )
+            .unwrap();
+        }
+        write_list(
+            &mut properties,
+            "Iptc4xmpExt:PersonInImage",
+            "rdf:Bag",
+            &self.persons,
+        );
+
+        if !self.regions.is_empty() {
+            properties.push_str(
+                "   <mwg-rs:Regions rdf:parseType=\"Resource\">\n    \
+                 <mwg-rs:RegionList>\n     <rdf:Bag>\n",
+            );
+            for (name, region) in &self.regions {
+                // MWG regions are positioned by their center.
+                writeln!(
+                    properties,
+                    "      <rdf:li rdf:parseType=\"Resource\">\n       \
+                     <mwg-rs:Name>{}</mwg-rs:Name>\n       \
+                     <mwg-rs:Type>Face</mwg-rs:Type>\n       \
+                     <mwg-rs:Area stArea:x=\"{}\" stArea:y=\"{}\" stArea:w=\"{}\" stArea:h=\"{}\" \
+                     stArea:unit=\"normalized\"/>\n      </rdf:li>",
+                    escape(name),
+                    region.x + region.width / 2.0,
+                    region.y + region.height / 2.0,
+                    region.width,
+                    region.height,
+                )
+                .unwrap();
+            }
+            properties
+                .push_str("     </rdf:Bag>\n    </mwg-rs:RegionList>\n   </mwg-rs:Regions>\n");
+        }
+
+        format!(
+            "<?xpacket begin=\"\u{feff}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>\n\
+             <x:xmpmeta xmlns:x=\"{X}\" x:xmptk=\"{TOOLKIT}\">\n \
+             <rdf:RDF xmlns:rdf=\"{RDF}\">\n  \
+             <rdf:Description rdf:about=\"\"\n   \
+             xmlns:dc=\"{DC}\"\n   \
+             xmlns:xmp=\"{XMP}\"\n   \
+             xmlns:lr=\"{LR}\"\n   \
+             xmlns:Iptc4xmpExt=\"{IPTC_EXT}\"\n   \
+             xmlns:mwg-rs=\"{MWG_RS}\"\n   \
+             xmlns:stArea=\"{ST_AREA}\"{attributes}>\n\
+             {properties}  \
+             </rdf:Description>\n \
+             </rdf:RDF>\n\
+             </x:xmpmeta>\n\
+             <?xpacket end=\"w\"?>\n"
+        )
+    }
+
+    /// Merges the sidecar into an existing XMP packet, e.g. the one embedded in an image.
+    ///
+    /// Properties written by [Self::to_xml] replace the existing ones, while all other
+    /// properties, like the edit history of darktable or the reference to an extended XMP
+    /// packet, are kept unchanged. Properties without value are not written, so the existing
+    /// ones are kept as well.
+    pub fn merge_into(&self, xml: &str) -> Result<String> {
+        let own_xml = self.to_xml();
+        let own_document = Document::parse(&own_xml)?;
+        let Some(own) = own_document
+            .descendants()
+            .find(|node| node.has_tag_name((RDF, "Description")))
+        else {
+            return Ok(xml.to_string());
+        };
+        let document = Document::parse(xml)?;
+        let descriptions: Vec<_> = document
+            .descendants()
+            .filter(|node| node.has_tag_name((RDF, "Description")))
+            .collect();
+        let Some(first) = descriptions.first() else {
+            return Ok(own_xml);
+        };
+
+        let own_attributes: Vec<_> = own
+            .attributes()
+            .filter(|attribute| attribute.namespace() != Some(RDF))
+            .collect();
+        let own_properties: Vec<_> = own.children().filter(Node::is_element).collect();
+        if own_attributes.is_empty() && own_properties.is_empty() {
+            return Ok(xml.to_string());
+        }
+
+        // Existing properties are removed from all descriptions, the own ones are added
+        // to the first description, where their namespaces are declared.
+        let mut edits = Vec::new();
+        let replaced = |namespace: Option<&str>, name: &str| {
+            own_attributes
+                .iter()
+                .any(|own| own.namespace() == namespace && own.name() == name)
+                || own_properties.iter().any(|own| {
+                    own.tag_name().namespace() == namespace && own.tag_name().name() == name
+                })
+        };
+        for description in &descriptions {
+            for attribute in description.attributes() {
+                if replaced(attribute.namespace(), attribute.name()) {
+                    let start = attribute.position();
+                    let end = start + attribute_source(xml, start).len();
+                    edits.push((start..end, String::new()));
+                }
+            }
+            for property in description.children().filter(Node::is_element) {
+                if replaced(property.tag_name().namespace(), property.tag_name().name()) {
+                    edits.push((property.range(), String::new()));
+                }
+            }
+        }
+
+        let mut start_tag = String::new();
+        for namespace in own.namespaces() {
+            let Some(prefix) = namespace.name() else {
+                continue;
+            };
+            match first.lookup_namespace_uri(Some(prefix)) {
+                None => write!(start_tag, " xmlns:{prefix}=\"{}\"", namespace.uri()).unwrap(),
+                Some(uri) if uri == namespace.uri() => {}
+                Some(_) => return Err(Error::XmpPrefixConflict(prefix.to_string())),
+            }
+        }
+        for attribute in &own_attributes {
+            write!(
+                start_tag,
+                " {}",
+                attribute_source(&own_xml, attribute.position())
+            )
+            .unwrap();
+        }
+        let mut properties = String::new();
+        for property in &own_properties {
+            writeln!(properties, "   {}", &own_xml[property.range()]).unwrap();
+        }
+
+        let range = first.range();
+        let name_length = xml[range.start + 1..]
+            .find(|c: char| c.is_whitespace() || c == '/' || c == '>')
+            .unwrap_or_default();
+        let name = &xml[range.start + 1..range.start + 1 + name_length];
+        edits.push((
+            range.start + 1 + name_length..range.start + 1 + name_length,
+            start_tag,
+        ));
+        if xml[..range.end].ends_with("/>") {
+            edits.push((
+                range.end - 2..range.end,
+                format!(">\n{properties}  </{name}>"),
+            ));
+        } else {
+            let end_tag = xml[..range.end].rfind("</").unwrap_or(range.end);
+            edits.push((end_tag..end_tag, properties));
+        }
+
+        let mut merged = xml.to_string();
+        edits.sort_by_key(|(range, _)| std::cmp::Reverse(range.start));
+        for (range, replacement) in edits {
+            merged.replace_range(range, &replacement);
+        }
+        Ok(merged)
+    }
+}
+
+/// Source of the attribute starting at the given position, e.g. `xmp:Rating="4"`.
+fn attribute_source(xml: &str, start: usize) -> &str {
+    let rest = &xml[start..];
+    let end = rest
+        .find(['"', '\''])
+        .and_then(|open| {
+            let quote = &rest[open..open + 1];
+            rest[open + 1..]
+                .find(quote)
+                .map(|close| open + 1 + close + 1)
+        })
+        .unwrap_or(rest.len());
+    &rest[..end]
+}
+
+/// Values of all occurrences of the property, given either as attribute,
+/// as element text or as `rdf:li` items of an element.
+fn values(root: Node, namespace: &str, name: &str) -> Vec<String> {
+    let mut values = Vec::new();
+    for node in root.descendants().filter(Node::is_element) {
+        if let Some(value) = node.attribute((namespace, name)) {
+            values.push(value.trim().to_string());
+        }
+        if node.has_tag_name((namespace, name)) {
+            let items: Vec<_> = node
+                .descendants()
+                .filter(|item| item.has_tag_name((RDF, "li")))
+                .map(text)
+                .collect();
+            if items.is_empty() {
+                values.push(text(node));
+            } else {
+                values.extend(items);
+            }
+        }
+    }
+    values
+}
+
+/// Named face regions in the `mwg-rs` schema.
+fn regions(root: Node) -> Vec<(String, Region)> {
+    let mut regions = Vec::new();
+    let lists = root
+        .descendants()
+        .filter(|node| node.has_tag_name((MWG_RS, "RegionList")));
+    for item in lists.flat_map(|list| {
+        list.descendants()
+            .filter(|node| node.has_tag_name((RDF, "li")))
+    }) {
+        // digiKam nests the properties in a description.
+        let item = item
+            .children()
+            .find(|node| node.has_tag_name((RDF, "Description")))
+            .unwrap_or(item);
+        let Some(name) = property(item, MWG_RS, "Name") else {
+            continue;
+        };
+        if property(item, MWG_RS, "Type").is_some_and(|kind| kind != "Face") {
+            continue;
+        }
+        let Some(area) = item
+            .children()
+            .find(|node| node.has_tag_name((MWG_RS, "Area")))
+        else {
+            continue;
+        };
+        let number = |name| property(area, ST_AREA, name)?.parse::<f32>().ok();
+        let (Some(x), Some(y), Some(width), Some(height)) =
+            (number("x"), number("y"), number("w"), number("h"))
+        else {
+            continue;
+        };
+        if property(area, ST_AREA, "unit").is_some_and(|unit| unit != "normalized") {
+            continue;
+        }
+        let region = Region {
+            x: x - width / 2.0,
+            y: y - height / 2.0,
+            width,
+            height,
+        };
+        regions.push((name, region));
+    }
+    regions
+}
+
+/// Value of a property of the node, given either as attribute or as child element.
+fn property(node: Node, namespace: &str, name: &str) -> Option<String> {
+    node.attribute((namespace, name))
+        .map(|value| value.trim().to_string())
+        .or_else(|| {
+            node.children()
+                .find(|child| child.has_tag_name((namespace, name)))
+                .map(text)
+        })
+}
+
+fn text(node: Node) -> String {
+    node.text().unwrap_or_default().trim().to_string()
+}
+
+fn write_list(xml: &mut String, property: &str, kind: &str, values: &[String]) {
+    if values.is_empty() {
+        return;
+    }
+    writeln!(xml, "   <{property}>\n    <{kind}>").unwrap();
+    for value in values {
+        writeln!(xml, "     <rdf:li>{}</rdf:li>", escape(value)).unwrap();
+    }
+    writeln!(xml, "    </{kind}>\n   </{property}>").unwrap();
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let xmp = Xmp {
+            persons: vec!["Anna".into(), "Ben & Co".into()],
+            regions: vec![(
+                "Anna".into(),
+                Region {
+                    x: 0.25,
+                    y: 0.5,
+                    width: 0.25,
+                    height: 0.125,
+                },
+            )],
+            tags: HashMap::from([
+                ("event".into(), vec!["Birthday".into()]),
+                ("holiday".into(), vec![]),
+            ]),
+            rating: Some(4),
+            rejected: false,
+            label: Some(ColorLabel::Green),
+            description: Some("Cake <3".into()),
+        };
+        assert_eq!(xmp, Xmp::parse(&xmp.to_xml()).unwrap());
+    }
+
+    const DARKTABLE: &str = r#"<?xpacket begin="" id="W5M0MpCehiHzreSzNTczkc9d"?>
+<x:xmpmeta xmlns:x="adobe:ns:meta/" x:xmptk="XMP Core 4.4.0-Exiv2">
+ <rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+  <rdf:Description rdf:about=""
+    xmlns:dc="http://purl.org/dc/elements/1.1/"
+    xmlns:xmp="http://ns.adobe.com/xap/1.0/"
+    xmlns:xmpNote="http://ns.adobe.com/xmp/note/"
+    xmlns:darktable="http://darktable.sf.net/"
+   xmp:Rating="1"
+   xmpNote:HasExtendedXMP="0123456789ABCDEF0123456789ABCDEF"
+   darktable:history_end="1">
+   <dc:subject>
+    <rdf:Bag>
+     <rdf:li>old</rdf:li>
+    </rdf:Bag>
+   </dc:subject>
+   <dc:description>
+    <rdf:Alt>
+     <rdf:li xml:lang="x-default">Kept</rdf:li>
+    </rdf:Alt>
+   </dc:description>
+   <darktable:history>
+    <rdf:Seq>
+     <rdf:li darktable:operation="exposure" darktable:enabled="1"/>
+    </rdf:Seq>
+   </darktable:history>
+  </rdf:Description>
+ </rdf:RDF>
+</x:xmpmeta>
+<?xpacket end="w"?>"#;
+
+    #[test]
+    fn merge_replaces_only_own_properties() {
+        let xmp = Xmp {
+            persons: vec!["Anna".into()],
+            tags: HashMap::from([("trip".into(), vec!["Rome".into()])]),
+            rating: Some(4),
+            ..Default::default()
+        };
+        let merged = xmp.merge_into(DARKTABLE).unwrap();
+        let parsed = Xmp::parse(&merged).unwrap();
+        assert_eq!(xmp.persons, parsed.persons);
+        assert_eq!(xmp.tags, parsed.tags);
+        assert_eq!(Some(4), parsed.rating);
+        assert_eq!(Some("Kept".into()), parsed.description);
+        assert!(!merged.contains("<rdf:li>old</rdf:li>"));
+        assert!(!merged.contains("xmp:Rating=\"1\""));
+
+        // Unknown properties stay byte for byte.
+        for kept in [
+            "xmpNote:HasExtendedXMP=\"0123456789ABCDEF0123456789ABCDEF\"",
+            "darktable:history_end=\"1\"",
+            "<rdf:li darktable:operation=\"exposure\" darktable:enabled=\"1\"/>",
+            "x:xmptk=\"XMP Core 4.4.0-Exiv2\"",
+        ] {
+            assert!(merged.contains(kept), "{kept} missing in {merged}");
+        }
+        assert!(merged.ends_with("<?xpacket end=\"w\"?>"));
+        assert!(!is_own_sidecar(&merged));
+    }
+
+    #[test]
+    fn merge_into_empty_description() {
+        let xml = r#"<x:xmpmeta xmlns:x="adobe:ns:meta/"><rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#"><rdf:Description rdf:about="" xmlns:tiff="http://ns.adobe.com/tiff/1.0/" tiff:Make="Canon"/></rdf:RDF></x:xmpmeta>"#;
+        let xmp = Xmp {
+            description: Some("Lake".into()),
+            ..Default::default()
+        };
+        let merged = xmp.merge_into(xml).unwrap();
+        assert_eq!(
+            Some("Lake".into()),
+            Xmp::parse(&merged).unwrap().description
+        );
+        assert!(merged.contains("tiff:Make=\"Canon\""));
+        assert_eq!(xml, Xmp::default().merge_into(xml).unwrap());
+    }
+
+    #[test]
+    fn merge_rejects_conflicting_prefixes() {
+        let xml = r#"<x:xmpmeta xmlns:x="adobe:ns:meta/"><rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#"><rdf:Description xmlns:dc="urn:other"/></rdf:RDF></x:xmpmeta>"#;
+        let xmp = Xmp {
+            description: Some("Lake".into()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            xmp.merge_into(xml),
+            Err(Error::XmpPrefixConflict(prefix)) if prefix == "dc"
+        ));
+    }
+
+    #[test]
+    fn own_sidecars() {
+        assert!(is_own_sidecar(&Xmp::default().to_xml()));
+        assert!(!is_own_sidecar(
+            r#"<x:xmpmeta xmlns:x="adobe:ns:meta/" x:xmptk="XMP Core 4.4.0-Exiv2"/>"#
+        ));
+        assert!(!is_own_sidecar(r#"<x:xmpmeta xmlns:x="adobe:ns:meta/"/>"#));
+        assert!(!is_own_sidecar("<broken"));
+    }
+
+    #[test]
+    fn parse_digikam() {
+        let xml = r#"<x:xmpmeta xmlns:x="adobe:ns:meta/">
+ <rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+  <rdf:Description rdf:about=""
+    xmlns:xmp="http://ns.adobe.com/xap/1.0/"
+    xmlns:dc="http://purl.org/dc/elements/1.1/"
+    xmlns:lr="http://ns.adobe.com/lightroom/1.0/"
+    xmlns:mwg-rs="http://www.metadataworkinggroup.com/schemas/regions/"
+    xmlns:stArea="http://ns.adobe.com/xmp/sType/Area#"
+   xmp:Rating="-1">
+   <dc:subject>
+    <rdf:Bag>
+     <rdf:li>Anna</rdf:li>
+     <rdf:li>Rome</rdf:li>
+    </rdf:Bag>
+   </dc:subject>
+   <lr:hierarchicalSubject>
+    <rdf:Bag>
+     <rdf:li>People|Anna</rdf:li>
+     <rdf:li>location|Rome</rdf:li>
+    </rdf:Bag>
+   </lr:hierarchicalSubject>
+   <mwg-rs:Regions rdf:parseType="Resource">
+    <mwg-rs:RegionList>
+     <rdf:Bag>
+      <rdf:li>
+       <rdf:Description mwg-rs:Name="Ben" mwg-rs:Type="Face">
+        <mwg-rs:Area stArea:x="0.5" stArea:y="0.5" stArea:w="0.5" stArea:h="0.5" stArea:unit="normalized"/>
+       </rdf:Description>
+      </rdf:li>
+     </rdf:Bag>
+    </mwg-rs:RegionList>
+   </mwg-rs:Regions>
+  </rdf:Description>
+ </rdf:RDF>
+</x:xmpmeta>"#;
+        let xmp = Xmp::parse(xml).unwrap();
+        assert_eq!(vec!["Anna".to_string(), "Ben".to_string()], xmp.persons);
+        assert_eq!(
+            HashMap::from([("location".into(), vec!["Rome".into()])]),
+            xmp.tags
+        );
+        assert!(xmp.rejected);
+        assert_eq!(None, xmp.rating);
+        assert_eq!(1, xmp.regions.len());
+        assert_eq!(0.25, xmp.regions[0].1.x);
+    }
+}